@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Backend without a GPU, for running renderer code headless. Resources are IDs handed out by a
+/// counter and commands are dropped, only buffer contents are kept so maps and writes work
+pub struct NullRenderBackend {
+  next_id: u32,
+  swapchain_res: rhi::Resolution2D,
+  swapchain_images: Vec<rhi::ImageID>,
+  buffers: HashMap<u32, Vec<u8>>,
+}
+
+impl NullRenderBackend {
+  pub fn new(swapchain_res: rhi::Resolution2D, swapchain_image_count: u32) -> Self {
+    let mut backend = Self {
+      next_id: 0,
+      swapchain_res,
+      swapchain_images: vec![],
+      buffers: HashMap::new(),
+    };
+    backend.swapchain_images =
+      (0..swapchain_image_count).map(|_| rhi::ImageID(backend.next_id())).collect();
+    backend
+  }
+
+  fn next_id(&mut self) -> u32 {
+    let id = self.next_id;
+    self.next_id += 1;
+    id
+  }
+
+  fn get_buffer_mut(&mut self, buffer_id: rhi::BufferID) -> Result<&mut Vec<u8>, rhi::RhiError> {
+    self
+      .buffers
+      .get_mut(&buffer_id.0)
+      .ok_or_else(|| rhi::RhiError::InvalidHandle(format!("buffer {}", buffer_id.0)))
+  }
+
+  fn copy_to_buffer(&mut self, buffer_id: rhi::BufferID, data: &[u8]) -> Result<(), rhi::RhiError> {
+    let buffer = self.get_buffer_mut(buffer_id)?;
+    if data.len() > buffer.len() {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at write buffer: {} bytes don't fit in buffer of size {}",
+        data.len(),
+        buffer.len()
+      )));
+    }
+    buffer[..data.len()].copy_from_slice(data);
+    Ok(())
+  }
+}
+
+impl rhi::RenderBackend for NullRenderBackend {
+  fn get_swapchain_info(&self) -> rhi::SwapchainInfo {
+    rhi::SwapchainInfo {
+      res: self.swapchain_res,
+      image_count: self.swapchain_images.len() as _,
+      present_mode: rhi::PresentMode::Fifo,
+      is_preferred_present_mode: true,
+    }
+  }
+
+  fn timestamp_period(&self) -> f32 {
+    1.0
+  }
+
+  fn get_device_info(&self) -> (rhi::GPUInfo, rhi::DeviceLimits, rhi::DeviceFeatures) {
+    (
+      rhi::GPUInfo { id: 0, name: "Null Device".to_string(), integrated: false },
+      rhi::DeviceLimits {
+        max_texture_size: 16384,
+        max_push_constant_size: 128,
+        max_compute_group_size: [1024, 1024, 64],
+        min_uniform_buffer_alignment: 256,
+        timestamp_valid_bits: 0,
+      },
+      // Nothing is ever drawn, so every feature can be claimed
+      rhi::DeviceFeatures {
+        anisotropy: true,
+        fill_mode_non_solid: true,
+        multi_draw_indirect: true,
+        tessellation: true,
+        geometry_shader: true,
+        buffer_device_address: true,
+      },
+    )
+  }
+
+  fn get_memory_budget(&self) -> Vec<rhi::MemoryHeapBudget> {
+    vec![]
+  }
+
+  fn get_memory_info(&self) -> rhi::MemoryInfo {
+    rhi::MemoryInfo { heaps: vec![], types: vec![] }
+  }
+
+  fn create_buffer(
+    &mut self,
+    size: u64,
+    _usage: rhi::BufferUsage,
+    _memory_location: rhi::MemoryLocation
+  ) -> Result<rhi::BufferID, rhi::RhiError> {
+    let id = self.next_id();
+    self.buffers.insert(id, vec![0; size as usize]);
+    Ok(rhi::BufferID(id))
+  }
+
+  fn map_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<*mut u8, rhi::RhiError> {
+    Ok(self.get_buffer_mut(buffer_id)?.as_mut_ptr())
+  }
+
+  fn unmap_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<(), rhi::RhiError> {
+    self.get_buffer_mut(buffer_id).map(|_| ())
+  }
+
+  fn write_buffer<T: bytemuck::Pod>(
+    &mut self,
+    buffer_id: rhi::BufferID,
+    data: &[T]
+  ) -> Result<(), rhi::RhiError> {
+    self.copy_to_buffer(buffer_id, bytemuck::cast_slice(data))
+  }
+
+  fn upload_buffer_sync(&mut self, dst: rhi::BufferID, data: &[u8]) -> Result<(), rhi::RhiError> {
+    self.copy_to_buffer(dst, data)
+  }
+
+  fn destroy_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<(), rhi::RhiError> {
+    self
+      .buffers
+      .remove(&buffer_id.0)
+      .map(|_| ())
+      .ok_or_else(|| rhi::RhiError::InvalidHandle(format!("buffer {}", buffer_id.0)))
+  }
+
+  /// The address of the buffer's CPU side storage
+  fn get_buffer_device_address(&self, buffer_id: rhi::BufferID) -> Result<u64, rhi::RhiError> {
+    self
+      .buffers
+      .get(&buffer_id.0)
+      .map(|x| x.as_ptr() as u64)
+      .ok_or_else(|| rhi::RhiError::InvalidHandle(format!("buffer {}", buffer_id.0)))
+  }
+
+  fn query_image_format_support(
+    &self,
+    _format: rhi::ImageFormat,
+    _usage: rhi::ImageUsage,
+    _tiling: rhi::ImageTiling,
+  ) -> rhi::FormatSupport {
+    rhi::FormatSupport {
+      supported: true,
+      max_sample_count: rhi::ImageSampleCount::E16,
+      // Full mip chain of the max texture size
+      max_mip_levels: 15,
+    }
+  }
+
+  fn query_buffer_format_support(&self, _format: rhi::ImageFormat) -> bool {
+    true
+  }
+
+  fn create_texture(&mut self, _desc: rhi::TextureDesc) -> Result<rhi::ImageID, rhi::RhiError> {
+    Ok(rhi::ImageID(self.next_id()))
+  }
+
+  fn create_cube_face_view(
+    &mut self,
+    _image: rhi::ImageID,
+    _face: u32
+  ) -> Result<rhi::ImageViewID, rhi::RhiError> {
+    Ok(rhi::ImageViewID(self.next_id()))
+  }
+
+  fn create_image_view(
+    &mut self,
+    _image: rhi::ImageID,
+    _base_mip: u32,
+    _mip_count: u32,
+    _base_layer: u32,
+    _layer_count: u32,
+  ) -> Result<rhi::ImageViewID, rhi::RhiError> {
+    Ok(rhi::ImageViewID(self.next_id()))
+  }
+
+  fn destroy_image_view(&mut self, _image_view_id: rhi::ImageViewID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn upload_texture(
+    &mut self,
+    _data: &[u8],
+    _res: rhi::Resolution2D,
+    _format: rhi::ImageFormat,
+  ) -> Result<rhi::ImageID, rhi::RhiError> {
+    Ok(rhi::ImageID(self.next_id()))
+  }
+
+  fn destroy_image(&mut self, image_id: rhi::ImageID) -> Result<(), rhi::RhiError> {
+    if self.swapchain_images.contains(&image_id) {
+      return Err(rhi::RhiError::BackendError(
+        "at destroy image: swapchain images can't be destroyed".to_string()
+      ));
+    }
+    Ok(())
+  }
+
+  fn create_shader(&mut self, _path: PathBuf) -> Result<rhi::ShaderID, rhi::RhiError> {
+    Ok(rhi::ShaderID(self.next_id()))
+  }
+
+  async fn load_shader(&mut self, path: PathBuf) -> Result<rhi::ShaderID, rhi::RhiError> {
+    self.create_shader(path)
+  }
+
+  fn destroy_shader(&mut self, _shader_id: rhi::ShaderID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  async fn create_graphics_pipeline(
+    &mut self,
+    _desc: rhi::GraphicsPipelineDesc
+  ) -> Result<rhi::PipelineID, rhi::RhiError> {
+    Ok(rhi::PipelineID(self.next_id()))
+  }
+
+  fn destroy_pipeline(&mut self, _pipeline_id: rhi::PipelineID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  async fn create_compute_pipeline(
+    &mut self,
+    _shader: PathBuf,
+    _max_buffer_count: u32,
+    _max_texture_count: u32,
+    _storage_image_count: u32,
+  ) -> Result<rhi::ComputePipelineID, rhi::RhiError> {
+    Ok(rhi::ComputePipelineID(self.next_id()))
+  }
+
+  fn create_ray_tracing_pipeline(
+    &mut self,
+    _desc: rhi::RayTracingPipelineDesc
+  ) -> Result<rhi::RayTracingPipelineID, rhi::RhiError> {
+    Ok(rhi::RayTracingPipelineID(self.next_id()))
+  }
+
+  fn destroy_ray_tracing_pipeline(
+    &mut self,
+    _pipeline_id: rhi::RayTracingPipelineID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_blas(&mut self, _desc: rhi::BLASDesc) -> Result<rhi::AccelStructID, rhi::RhiError> {
+    Ok(rhi::AccelStructID(self.next_id()))
+  }
+
+  fn create_tlas(
+    &mut self,
+    _instances: Vec<rhi::TLASInstance>
+  ) -> Result<rhi::AccelStructID, rhi::RhiError> {
+    Ok(rhi::AccelStructID(self.next_id()))
+  }
+
+  fn destroy_accel_struct(
+    &mut self,
+    _accel_struct: rhi::AccelStructID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_frame_buffer(
+    &mut self,
+    _pipeline_id: rhi::PipelineID,
+    _color_attachments: Vec<rhi::ImageID>,
+    _depth_attachment: Option<rhi::ImageID>,
+    _resolve_attachments: Vec<rhi::ImageID>,
+  ) -> Result<rhi::FramebufferID, rhi::RhiError> {
+    Ok(rhi::FramebufferID(self.next_id()))
+  }
+
+  fn destroy_framebuffer(
+    &mut self,
+    _framebuffer_id: rhi::FramebufferID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_input_set(
+    &mut self,
+    _pipeline_id: rhi::PipelineID
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    Ok(rhi::InputSetID(self.next_id()))
+  }
+
+  fn create_compute_input_set(
+    &mut self,
+    _pipeline_id: rhi::ComputePipelineID
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    Ok(rhi::InputSetID(self.next_id()))
+  }
+
+  fn create_ray_tracing_input_set(
+    &mut self,
+    _pipeline_id: rhi::RayTracingPipelineID
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    Ok(rhi::InputSetID(self.next_id()))
+  }
+
+  fn update_input_set(
+    &mut self,
+    _input_set: rhi::InputSetID,
+    _buffers: Vec<rhi::BufferID>,
+    _uniform_buffers: Vec<rhi::BufferID>,
+    _textures: Vec<(rhi::ImageViewID, rhi::SamplerID)>,
+    _storage_images: Vec<rhi::ImageID>,
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn update_input_set_buffer(
+    &mut self,
+    _set: rhi::InputSetID,
+    _binding: u32,
+    _buffer: rhi::BufferID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn update_input_set_texture(
+    &mut self,
+    _set: rhi::InputSetID,
+    _binding: u32,
+    _image_view: rhi::ImageViewID,
+    _sampler: rhi::SamplerID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn register_texture_bindless(
+    &mut self,
+    _set: rhi::InputSetID,
+    _slot: u32,
+    _image_view: rhi::ImageViewID,
+    _sampler: rhi::SamplerID
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn destroy_input_set(&mut self, _input_set: rhi::InputSetID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_sampler(&mut self, _desc: rhi::SamplerDesc) -> Result<rhi::SamplerID, rhi::RhiError> {
+    Ok(rhi::SamplerID(self.next_id()))
+  }
+
+  fn destroy_sampler(&mut self, _sampler_id: rhi::SamplerID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_fence(&mut self, _signaled: bool) -> Result<rhi::FenceID, rhi::RhiError> {
+    Ok(rhi::FenceID(self.next_id()))
+  }
+
+  async fn wait_for_fence(&self, _fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn reset_fence(&self, _fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn destroy_fence(&mut self, _fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_semaphore(&mut self) -> Result<rhi::SemaphoreID, rhi::RhiError> {
+    Ok(rhi::SemaphoreID(self.next_id()))
+  }
+
+  fn destroy_semaphore(&mut self, _semaphore_id: rhi::SemaphoreID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn create_timestamp_query_pool(
+    &mut self,
+    _count: u32
+  ) -> Result<rhi::QueryPoolID, rhi::RhiError> {
+    Ok(rhi::QueryPoolID(self.next_id()))
+  }
+
+  fn destroy_query_pool(&mut self, _pool: rhi::QueryPoolID) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn read_timestamps(
+    &self,
+    _pool: rhi::QueryPoolID,
+    _first: u32,
+    count: u32
+  ) -> Result<Vec<u64>, rhi::RhiError> {
+    Ok(vec![0; count as usize])
+  }
+
+  fn create_pipeline_stats_query_pool(
+    &mut self,
+    _count: u32,
+    _stats: rhi::PipelineStatFlags
+  ) -> Result<rhi::QueryPoolID, rhi::RhiError> {
+    Ok(rhi::QueryPoolID(self.next_id()))
+  }
+
+  fn read_pipeline_stats(
+    &self,
+    _pool: rhi::QueryPoolID,
+    _index: u32
+  ) -> Result<rhi::PipelineStatsResult, rhi::RhiError> {
+    Ok(rhi::PipelineStatsResult::default())
+  }
+
+  fn create_command_buffer(&mut self) -> Result<rhi::CommandBufferID, rhi::RhiError> {
+    Ok(rhi::CommandBufferID(self.next_id()))
+  }
+
+  fn create_secondary_command_buffer(
+    &mut self,
+    _inheritance: rhi::CommandBufferInheritance
+  ) -> Result<rhi::CommandBufferID, rhi::RhiError> {
+    Ok(rhi::CommandBufferID(self.next_id()))
+  }
+
+  fn reset_command_buffer(
+    &mut self,
+    _command_buffer: rhi::CommandBufferID,
+    _wait_before_reset: bool
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn compile_commands(
+    &self,
+    _command_buffer: rhi::CommandBufferID,
+    _commands: Vec<rhi::GPUCommands>
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn run_commands(
+    &mut self,
+    _command_buffer: rhi::CommandBufferID,
+    _fence_id: rhi::FenceID,
+    _wait_semaphores: Vec<rhi::SemaphoreID>,
+    _signal_semaphores: Vec<rhi::SemaphoreID>,
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn device_wait_idle(&self) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn queue_wait_idle(&self) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+
+  fn get_swapchain_images(&self) -> Vec<rhi::ImageID> {
+    self.swapchain_images.clone()
+  }
+
+  fn rebuild_swapchain(&mut self, width: u32, height: u32) -> Result<(), rhi::RhiError> {
+    self.swapchain_res = rhi::Resolution2D { width, height };
+    Ok(())
+  }
+
+  fn present_swapchain_image(
+    &self,
+    _image_index: u32,
+    _wait_semaphores: &[rhi::SemaphoreID]
+  ) -> Result<bool, rhi::RhiError> {
+    Ok(false)
+  }
+
+  fn acquire_present_image(
+    &self,
+    _fence_id: rhi::FenceID,
+    _signal_semaphore: Option<rhi::SemaphoreID>
+  ) -> Result<u32, rhi::RhiError> {
+    Ok(0)
+  }
+
+  fn set_debug_name(
+    &self,
+    _resource: impl Into<rhi::DebugResource>,
+    _name: &str
+  ) -> Result<(), rhi::RhiError> {
+    Ok(())
+  }
+}
@@ -1,46 +1,942 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use rhi::{
+  AttachmentDesc,
+  AttachmentOps,
+  BlendFactor,
+  BlendOp,
+  BlendState,
+  BufferID,
+  BufferUsage,
+  ColorComponentFlags,
   CommandBufferID,
+  CompareOp,
+  DepthState,
+  DrawInfo,
+  DrawMode,
   FenceID,
   FramebufferID,
+  FrontFace,
+  GPUCommands,
+  GraphicsPipelineDesc,
+  ImageBarrier,
+  ImageFormat,
+  ImageID,
+  ImageLayoutType,
+  ImageSampleCount,
+  ImageUsage,
+  IndexType,
   InputSetID,
+  LoadOp,
+  MemAccessType,
+  MemoryLocation,
+  MultisampleState,
   PipelineID,
+  PipelineType,
+  PipelineStage,
+  RasterConfig,
+  RenderPassDesc,
+  RasterStyle,
   RenderBackend,
+  Resolution2D,
+  RhiError,
+  SemaphoreID,
+  ShaderStageFlags,
+  StencilOp,
+  StencilOpState,
+  StoreOp,
+  TextureDesc,
+  TextureType,
 };
+use seq_id_store::SequentialIDStore;
+use tokio::sync::Mutex;
 
+#[cfg(feature = "null-backend")]
+pub use null_rhi_backend::NullRenderBackend;
+
+static PBR_VERTEX_SHADER: &str = "assets/shaders/pbr.vert.spv";
+static PBR_FRAGMENT_SHADER: &str = "assets/shaders/pbr.frag.spv";
+/// The camera buffer and the vertex buffers of all the meshes drawn in a frame share the
+/// buffer array, the camera always comes first
+const PBR_MAX_BUFFER_COUNT: u32 = 256;
+const PBR_MAX_TEXTURE_COUNT: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct MeshVertex{
-  position: glam::Vec4,
-  normal: glam::Vec4,
-  tangent: glam::Vec4,
-  bi_tangent: glam::Vec4,
-  tex_coord: glam::Vec4,
+  pub position: glam::Vec4,
+  pub normal: glam::Vec4,
+  pub tangent: glam::Vec4,
+  pub bi_tangent: glam::Vec4,
+  pub tex_coord: glam::Vec4,
 }
 
+// Only `Vec4` fields, so there is no padding and any bit pattern is valid
+unsafe impl bytemuck::Zeroable for MeshVertex {}
+unsafe impl bytemuck::Pod for MeshVertex {}
+
 pub struct MeshCPU{
-  verts: Vec<MeshVertex>,
-  indices: Vec<u32>,
+  pub verts: Vec<MeshVertex>,
+  pub indices: Vec<u32>,
+}
+
+/// One `MeshCPU` per primitive of every mesh in the file. Missing attributes are zeroed,
+/// primitives without indices get one index per vertex
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<Vec<MeshCPU>, String> {
+  let path = path.as_ref();
+  let file_data = std::fs::read(path)
+    .map_err(|e| format!("at gltf file read {}: {e}", path.display()))?;
+  load_gltf_from_slice(path, &file_data)
+}
+
+/// `load_gltf` on file contents that were already read. External buffers are still read
+/// relative to `path`
+pub fn load_gltf_from_slice(path: &Path, file_data: &[u8]) -> Result<Vec<MeshCPU>, String> {
+  let gltf = gltf::Gltf::from_slice(file_data)
+    .map_err(|e| format!("at gltf parse {}: {e}", path.display()))?;
+  let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob)
+    .map_err(|e| format!("at gltf buffers import {}: {e}", path.display()))?;
+  let document = gltf.document;
+  let mut meshes = vec![];
+  for mesh in document.meshes() {
+    for primitive in mesh.primitives() {
+      let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|x| &x.0[..]));
+      let positions = reader
+        .read_positions()
+        .ok_or(format!("at gltf {}: primitive without positions", path.display()))?
+        .collect::<Vec<_>>();
+      let mut normals = reader.read_normals().map(|x| x.collect::<Vec<_>>()).unwrap_or_default();
+      let mut tangents = reader.read_tangents().map(|x| x.collect::<Vec<_>>()).unwrap_or_default();
+      let mut tex_coords = reader
+        .read_tex_coords(0)
+        .map(|x| x.into_f32().collect::<Vec<_>>())
+        .unwrap_or_default();
+      normals.resize(positions.len(), [0.0; 3]);
+      tangents.resize(positions.len(), [0.0; 4]);
+      tex_coords.resize(positions.len(), [0.0; 2]);
+      let verts = (0..positions.len())
+        .map(|i| {
+          let normal = glam::Vec3::from(normals[i]);
+          let tangent = glam::Vec4::from(tangents[i]);
+          // GLTF tangents carry the bitangent handedness in w
+          let bi_tangent = normal.cross(tangent.truncate()) * tangent.w;
+          MeshVertex {
+            position: glam::Vec3::from(positions[i]).extend(1.0),
+            normal: normal.extend(0.0),
+            tangent,
+            bi_tangent: bi_tangent.extend(0.0),
+            tex_coord: glam::Vec4::new(tex_coords[i][0], tex_coords[i][1], 0.0, 0.0),
+          }
+        })
+        .collect::<Vec<_>>();
+      let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..verts.len() as u32).collect(),
+      };
+      meshes.push(MeshCPU { verts, indices });
+    }
+  }
+  Ok(meshes)
+}
+
+fn translate_ktx2_format(format: ktx2::Format) -> Option<ImageFormat> {
+  match format {
+    ktx2::Format::R8_UNORM => Some(ImageFormat::R8Unorm),
+    ktx2::Format::R8G8_UNORM => Some(ImageFormat::Rg8Unorm),
+    ktx2::Format::R8G8B8A8_UNORM => Some(ImageFormat::Rgba8Unorm),
+    ktx2::Format::R8G8B8A8_SRGB => Some(ImageFormat::Rgba8Srgb),
+    ktx2::Format::R16_SFLOAT => Some(ImageFormat::R16Sfloat),
+    ktx2::Format::R16G16_SFLOAT => Some(ImageFormat::Rg16Sfloat),
+    ktx2::Format::R16G16B16A16_SFLOAT => Some(ImageFormat::Rgba16Sfloat),
+    ktx2::Format::R32_SFLOAT => Some(ImageFormat::R32Sfloat),
+    ktx2::Format::R32G32_SFLOAT => Some(ImageFormat::Rg32Sfloat),
+    ktx2::Format::R32G32B32A32_SFLOAT => Some(ImageFormat::Rgba32Sfloat),
+    _ => None,
+  }
+}
+
+/// PNG and JPEG files are decoded to 8 bit sRGB RGBA. KTX2 files keep their format and only
+/// the first mip level is read, supercompressed KTX2 files are not supported
+pub fn load_texture_from_file(
+  path: impl AsRef<Path>
+) -> Result<(Vec<u8>, Resolution2D, ImageFormat), String> {
+  let path = path.as_ref();
+  let file_data = std::fs::read(path)
+    .map_err(|e| format!("at texture file read {}: {e}", path.display()))?;
+  load_texture_from_slice(path, &file_data)
+}
+
+/// `load_texture_from_file` on file contents that were already read, `path` picks the decoder
+pub fn load_texture_from_slice(
+  path: &Path,
+  file_data: &[u8],
+) -> Result<(Vec<u8>, Resolution2D, ImageFormat), String> {
+  let is_ktx2 = path.extension().is_some_and(|x| x.eq_ignore_ascii_case("ktx2"));
+  if !is_ktx2 {
+    let image = image::ImageFormat::from_path(path)
+      .and_then(|format| image::load_from_memory_with_format(file_data, format))
+      .map_err(|e| format!("at image decode {}: {e}", path.display()))?
+      .into_rgba8();
+    let res = Resolution2D { width: image.width(), height: image.height() };
+    return Ok((image.into_raw(), res, ImageFormat::Rgba8Srgb));
+  }
+  let reader = ktx2::Reader::new(file_data)
+    .map_err(|e| format!("at ktx2 parse {}: {e}", path.display()))?;
+  let header = reader.header();
+  if header.supercompression_scheme.is_some() {
+    return Err(format!("at ktx2 {}: supercompressed files are not supported", path.display()));
+  }
+  let format = header
+    .format
+    .and_then(translate_ktx2_format)
+    .ok_or(format!("at ktx2 {}: unsupported format {:?}", path.display(), header.format))?;
+  let level = reader
+    .levels()
+    .next()
+    .ok_or(format!("at ktx2 {}: file has no mip levels", path.display()))?;
+  let res = Resolution2D { width: header.pixel_width, height: header.pixel_height.max(1) };
+  Ok((level.data.to_vec(), res, format))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshID(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera{
+  pub position: glam::Vec3,
+  pub target: glam::Vec3,
+  pub up: glam::Vec3,
+  /// Radians
+  pub fov_y: f32,
+  pub near: f32,
+  pub far: f32,
+}
+
+impl Default for Camera{
+  fn default() -> Self {
+    Self {
+      position: glam::Vec3::new(0.0, 0.0, 3.0),
+      target: glam::Vec3::ZERO,
+      up: glam::Vec3::Y,
+      fov_y: 60f32.to_radians(),
+      near: 0.1,
+      far: 1000.0,
+    }
+  }
+}
+
+impl Camera{
+  pub fn view_matrix(&self) -> glam::Mat4 {
+    glam::Mat4::look_at_rh(self.position, self.target, self.up)
+  }
+
+  /// 0 to 1 depth range, with Y flipped since Vulkan's clip space Y points down
+  pub fn proj_matrix(&self, aspect: f32) -> glam::Mat4 {
+    let mut proj = glam::Mat4::perspective_rh(self.fov_y, aspect, self.near, self.far);
+    proj.y_axis.y *= -1.0;
+    proj
+  }
+}
+
+/// Matrices are stored as column arrays
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CameraUniforms{
+  view: [glam::Vec4; 4],
+  proj: [glam::Vec4; 4],
+  view_proj: [glam::Vec4; 4],
+  camera_pos: glam::Vec4,
 }
 
+// Only `Vec4` fields, so there is no padding and any bit pattern is valid
+unsafe impl bytemuck::Zeroable for CameraUniforms {}
+unsafe impl bytemuck::Pod for CameraUniforms {}
+
+impl CameraUniforms{
+  fn new(camera: &Camera, aspect: f32) -> Self {
+    let to_cols = |m: glam::Mat4| [m.x_axis, m.y_axis, m.z_axis, m.w_axis];
+    let view = camera.view_matrix();
+    let proj = camera.proj_matrix(aspect);
+    Self {
+      view: to_cols(view),
+      proj: to_cols(proj),
+      view_proj: to_cols(proj * view),
+      camera_pos: camera.position.extend(1.0),
+    }
+  }
+}
+
+/// Index of a pass in the `FrameGraph` it was added to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassID(pub u32);
+
+/// Collects the commands recorded by a frame graph pass
+#[derive(Default)]
+pub struct CommandEncoder {
+  commands: Vec<GPUCommands>,
+}
+
+impl CommandEncoder {
+  pub fn push(&mut self, command: GPUCommands) {
+    self.commands.push(command);
+  }
+}
+
+struct RenderPass<'a> {
+  name: String,
+  color_out: Vec<ImageID>,
+  depth_out: Option<ImageID>,
+  /// Images sampled by the pass' shaders
+  reads: Vec<ImageID>,
+  exec: Box<dyn Fn(&mut CommandEncoder) + 'a>,
+}
+
+impl RenderPass<'_> {
+  fn writes(&self) -> impl Iterator<Item = &ImageID> {
+    self.color_out.iter().chain(self.depth_out.iter())
+  }
+}
+
+/// How a pass uses an image. `src` is what later uses of the image wait on
+#[derive(Clone, Copy)]
+struct ImageUse {
+  layout: ImageLayoutType,
+  stage: PipelineStage,
+  access: MemAccessType,
+  src: (PipelineStage, MemAccessType),
+}
+
+/// Orders passes by the images they write and read, and inserts the barriers between them.
+/// A pass reading an image runs after every pass writing it, passes writing the same image run
+/// in the order they were added
+#[derive(Default)]
+pub struct FrameGraph<'a> {
+  passes: Vec<RenderPass<'a>>,
+}
+
+impl<'a> FrameGraph<'a> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add_pass(
+    &mut self,
+    name: &str,
+    color_out: &[ImageID],
+    depth_out: Option<ImageID>,
+    reads: &[ImageID],
+    exec: impl Fn(&mut CommandEncoder) + 'a,
+  ) -> PassID {
+    self.passes.push(RenderPass {
+      name: name.to_string(),
+      color_out: color_out.to_vec(),
+      depth_out,
+      reads: reads.to_vec(),
+      exec: Box::new(exec),
+    });
+    PassID(self.passes.len() as u32 - 1)
+  }
+
+  /// Pass indices in execution order, ties are broken by the order passes were added in
+  fn sorted_passes(&self) -> Result<Vec<usize>, RhiError> {
+    let dependencies = self
+      .passes
+      .iter()
+      .enumerate()
+      .map(|(i, pass)| {
+        self
+          .passes
+          .iter()
+          .enumerate()
+          .filter(|(j, other)| {
+            *j != i &&
+              other.writes().any(|x| {
+                pass.reads.contains(x) || (*j < i && pass.writes().any(|y| y == x))
+              })
+          })
+          .map(|(j, _)| j)
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    let mut scheduled = vec![false; self.passes.len()];
+    let mut order = Vec::with_capacity(self.passes.len());
+    while order.len() < self.passes.len() {
+      let next = (0..self.passes.len())
+        .find(|i| !scheduled[*i] && dependencies[*i].iter().all(|d| scheduled[*d]))
+        .ok_or_else(|| {
+          let cycle = (0..self.passes.len())
+            .filter(|i| !scheduled[*i])
+            .map(|i| self.passes[i].name.as_str())
+            .collect::<Vec<_>>();
+          RhiError::BackendError(format!(
+            "at frame graph compile: passes {cycle:?} depend on each other"
+          ))
+        })?;
+      scheduled[next] = true;
+      order.push(next);
+    }
+    Ok(order)
+  }
+
+  /// Flattens the passes into commands for `compile_commands`. Images are treated as undefined
+  /// before their first use, so their previous contents are discarded. The first barrier of an
+  /// attachment still waits on the same writes of frames before, which may share it, other
+  /// images wait on all earlier commands
+  pub fn compile(&self) -> Result<Vec<GPUCommands>, RhiError> {
+    let mut image_states = HashMap::new();
+    let mut commands = vec![];
+    for pass in self.sorted_passes()?.into_iter().map(|i| &self.passes[i]) {
+      let write_use =
+        |layout, stage, access| ImageUse { layout, stage, access, src: (stage, access) };
+      let color_use = write_use(
+        ImageLayoutType::ColorAttachment,
+        PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+        MemAccessType::ColorAttachmentWrite,
+      );
+      let depth_use = write_use(
+        ImageLayoutType::DepthAttachment,
+        PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS,
+        MemAccessType::DepthAttachmentWrite,
+      );
+      let read_use = ImageUse {
+        layout: ImageLayoutType::ShaderRO,
+        stage: PipelineStage::FRAGMENT_SHADER,
+        access: MemAccessType::ShaderRead,
+        src: (PipelineStage::FRAGMENT_SHADER, MemAccessType::None),
+      };
+      let mut usages = pass
+        .color_out
+        .iter()
+        .map(|x| (*x, color_use))
+        .chain(pass.depth_out.map(|x| (x, depth_use)))
+        .collect::<Vec<_>>();
+      // An image the pass also writes gets a single barrier into the general layout
+      for image in &pass.reads {
+        match usages.iter_mut().find(|(x, _)| x == image) {
+          Some((_, usage)) => {
+            usage.layout = ImageLayoutType::General;
+            usage.stage |= read_use.stage;
+            usage.access = MemAccessType::MemoryReadWrite;
+          }
+          None => usages.push((*image, read_use)),
+        }
+      }
+      let mut src_stage = PipelineStage::empty();
+      let mut dst_stage = PipelineStage::empty();
+      let mut image_barriers = vec![];
+      for (image, usage) in usages {
+        let first_src = match usage.src.1 {
+          MemAccessType::None => (PipelineStage::ALL_COMMANDS, MemAccessType::None),
+          _ => usage.src,
+        };
+        let (old_layout, (old_stage, src_access)) = image_states
+          .insert(image, (usage.layout, usage.src))
+          .unwrap_or((ImageLayoutType::Undefined, first_src));
+        src_stage |= old_stage;
+        dst_stage |= usage.stage;
+        image_barriers.push(ImageBarrier {
+          image,
+          src_access,
+          dst_access: usage.access,
+          old_layout,
+          new_layout: usage.layout,
+        });
+      }
+      if !image_barriers.is_empty() {
+        commands.push(GPUCommands::PipelineBarrier {
+          src_stage,
+          dst_stage,
+          buffer_barriers: vec![],
+          image_barriers,
+        });
+      }
+      let mut encoder = CommandEncoder::default();
+      (pass.exec)(&mut encoder);
+      commands.extend(encoder.commands);
+    }
+    Ok(commands)
+  }
+}
+
+pub struct GpuMesh{
+  vertex_buffer: BufferID,
+  index_buffer: BufferID,
+  index_count: u32,
+}
+
+/// Methods that aren't async block on the backend lock, so they can't be called from async code
 pub struct PbrRenderer<B: RenderBackend>{
   pipeline: PipelineID,
+  depth_image: ImageID,
+  /// One per swapchain image, the per-frame resources below are indexed by `current_frame`
+  frame_count: usize,
+  current_frame: usize,
   input_sets: Vec<InputSetID>,
+  /// Indexed by the acquired swapchain image instead
   framebuffers: Vec<FramebufferID>,
   command_buffers: Vec<CommandBufferID>,
+  /// Signaled when the frame's commands finish, starts signaled so the first wait passes
   fences: Vec<FenceID>,
+  acquire_fences: Vec<FenceID>,
+  render_semaphores: Vec<SemaphoreID>,
+  camera: Camera,
+  camera_buffers: Vec<BufferID>,
+  meshes: SequentialIDStore<GpuMesh>,
+  /// Meshes drawn by the next `render_frame`
+  queued_meshes: Vec<MeshID>,
   backend: Arc<Mutex<B>>
 }
 
 impl<B: RenderBackend> PbrRenderer<B>{
-  pub fn new(backend: Arc<Mutex<B>>) -> Result<Self, String>{
-    let mut backend_lock = backend
-      .lock()
-      .map_err(|e| format!("at backend lock: {e}"))?;
-    let frame_count = backend_lock.get_swapchain_images().len();
+  pub async fn new(backend: Arc<Mutex<B>>) -> Result<Self, RhiError>{
+    let mut backend_lock = backend.lock().await;
+    let swapchain_images = backend_lock.get_swapchain_images();
+    let frame_count = swapchain_images.len();
     let fences = (0..frame_count)
+      .map(|_| backend_lock.create_fence(true))
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let acquire_fences = (0..frame_count)
       .map(|_| backend_lock.create_fence(false))
-      .collect::<Result<Vec<_>, String>>()?;
-    todo!();
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let render_semaphores = (0..frame_count)
+      .map(|_| backend_lock.create_semaphore())
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let opaque_blend = BlendState {
+      blend_enable: false,
+      src_color: BlendFactor::One,
+      dst_color: BlendFactor::Zero,
+      color_op: BlendOp::Add,
+      src_alpha: BlendFactor::One,
+      dst_alpha: BlendFactor::Zero,
+      alpha_op: BlendOp::Add,
+      write_mask: ColorComponentFlags::all(),
+    };
+    let no_stencil = StencilOpState {
+      fail_op: StencilOp::Keep,
+      pass_op: StencilOp::Keep,
+      depth_fail_op: StencilOp::Keep,
+      compare_op: CompareOp::Always,
+      compare_mask: 0,
+      write_mask: 0,
+      reference: 0,
+    };
+    let depth_state = DepthState {
+      depth_test_enable: true,
+      depth_write_enable: true,
+      compare_op: CompareOp::Less,
+      stencil_enable: false,
+      front: no_stencil,
+      back: no_stencil,
+    };
+    let vertex_shader = backend_lock.create_shader(PathBuf::from(PBR_VERTEX_SHADER))?;
+    let fragment_shader = backend_lock.create_shader(PathBuf::from(PBR_FRAGMENT_SHADER))?;
+    let pipeline = backend_lock
+      .create_graphics_pipeline(GraphicsPipelineDesc {
+        raster_config: RasterConfig {
+          style: RasterStyle::Fill,
+          cull_mode: None,
+          front_face: FrontFace::CounterClockwise,
+          depth_clamp: false,
+          depth_bias: None,
+        },
+        pass: RenderPassDesc {
+          // Both are cleared, the present barrier follows the pass
+          color_attachments: vec![AttachmentDesc {
+            format: ImageFormat::Presentation,
+            initial_layout: ImageLayoutType::Undefined,
+            final_layout: ImageLayoutType::ColorAttachment,
+          }],
+          depth_attachment: Some(AttachmentDesc {
+            format: ImageFormat::Depth32Sfloat,
+            initial_layout: ImageLayoutType::Undefined,
+            final_layout: ImageLayoutType::DepthAttachment,
+          }),
+          color_ops: vec![AttachmentOps {
+            load: LoadOp::Clear([0.0, 0.0, 0.0, 0.0]),
+            store: StoreOp::Store,
+          }],
+          // Depth is only needed while drawing
+          depth_ops: Some(AttachmentOps {
+            load: LoadOp::Clear([1.0, 0.0, 0.0, 0.0]),
+            store: StoreOp::DontCare,
+          }),
+          dependencies: vec![],
+        },
+        max_buffer_count: PBR_MAX_BUFFER_COUNT,
+        uniform_buffer_count: 0,
+        max_texture_count: PBR_MAX_TEXTURE_COUNT,
+        storage_image_count: 0,
+        pipeline_type: PipelineType::VertexPipeline { vertex_shader },
+        fragment_shader,
+        geometry_shader: None,
+        tessellation: None,
+        vertex_spec_constants: vec![],
+        fragment_spec_constants: vec![],
+        // Index of the draw's vertex buffer in the buffer array
+        push_constant_size: 4,
+        push_constant_stages: ShaderStageFlags::VERTEX,
+        multisample: MultisampleState::default(),
+        blend_states: vec![opaque_blend],
+        depth_state: Some(depth_state),
+        multiview_mask: None,
+        instance_input: None,
+      })
+      .await?;
+    backend_lock.destroy_shader(vertex_shader)?;
+    backend_lock.destroy_shader(fragment_shader)?;
+    let camera_buffers = (0..frame_count)
+      .map(|_| {
+        backend_lock.create_buffer(
+          size_of::<CameraUniforms>() as u64,
+          BufferUsage::STORAGE,
+          MemoryLocation::CPUToGPU
+        )
+      })
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let input_sets = (0..frame_count)
+      .map(|_| backend_lock.create_input_set(pipeline))
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let command_buffers = (0..frame_count)
+      .map(|_| backend_lock.create_command_buffer())
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    let (depth_image, framebuffers) = Self::create_swapchain_targets(&mut *backend_lock, pipeline)?;
+    drop(backend_lock);
+    Ok(Self {
+      pipeline,
+      depth_image,
+      frame_count,
+      current_frame: 0,
+      input_sets,
+      framebuffers,
+      command_buffers,
+      fences,
+      acquire_fences,
+      render_semaphores,
+      camera: Camera::default(),
+      camera_buffers,
+      meshes: SequentialIDStore::new(1024),
+      queued_meshes: vec![],
+      backend,
+    })
+  }
+
+  /// Depth image sized to the swapchain and one framebuffer per swapchain image
+  fn create_swapchain_targets(
+    backend: &mut B,
+    pipeline: PipelineID,
+  ) -> Result<(ImageID, Vec<FramebufferID>), RhiError> {
+    let depth_image = backend.create_texture(TextureDesc {
+      ty: TextureType::Texture2D,
+      res: backend.get_swapchain_info().res,
+      format: ImageFormat::Depth32Sfloat,
+      usage: ImageUsage::RENDER_TARGET,
+      memory_location: MemoryLocation::GPU,
+      sample_count: ImageSampleCount::E1,
+      mip_levels: 1,
+      is_cube: false,
+    })?;
+    let framebuffers = backend
+      .get_swapchain_images()
+      .iter()
+      .map(|image| backend.create_frame_buffer(pipeline, vec![*image], Some(depth_image), vec![]))
+      .collect::<Result<Vec<_>, RhiError>>()?;
+    Ok((depth_image, framebuffers))
+  }
+
+  /// Replaces the depth image and framebuffers after the backend's swapchain was rebuilt
+  pub fn rebuild_swapchain_targets(&mut self) -> Result<(), RhiError> {
+    let backend = self.backend.clone();
+    let mut backend_lock = backend.blocking_lock();
+    self.replace_swapchain_targets(&mut backend_lock)
+  }
+
+  fn replace_swapchain_targets(&mut self, backend: &mut B) -> Result<(), RhiError> {
+    for framebuffer in self.framebuffers.drain(..) {
+      backend.destroy_framebuffer(framebuffer)?;
+    }
+    backend.destroy_image(self.depth_image)?;
+    (self.depth_image, self.framebuffers) = Self::create_swapchain_targets(backend, self.pipeline)?;
+    Ok(())
+  }
+
+  pub fn upload_mesh(&mut self, mesh: &MeshCPU) -> Result<MeshID, RhiError> {
+    let mut backend_lock = self.backend.blocking_lock();
+    let (vertex_buffer, index_buffer) = upload_mesh_buffers(&mut *backend_lock, mesh)?;
+    let mesh_id = self.meshes.add_obj(GpuMesh {
+      vertex_buffer,
+      index_buffer,
+      index_count: mesh.indices.len() as u32,
+    })?;
+    Ok(MeshID(mesh_id))
+  }
+
+  pub fn load_and_upload_gltf(&mut self, path: impl AsRef<Path>) -> Result<Vec<MeshID>, String> {
+    load_gltf(path)?
+      .iter()
+      .map(|mesh| self.upload_mesh(mesh).map_err(|e| e.to_string()))
+      .collect()
+  }
+
+  pub fn load_and_upload_texture(&mut self, path: impl AsRef<Path>) -> Result<ImageID, String> {
+    let (data, res, format) = load_texture_from_file(path)?;
+    self
+      .backend
+      .blocking_lock()
+      .upload_texture(&data, res, format)
+      .map_err(|e| e.to_string())
+  }
+
+  pub fn draw_mesh(&mut self, mesh_id: MeshID) {
+    self.queued_meshes.push(mesh_id);
+  }
+
+  pub fn set_camera(&mut self, camera: Camera) {
+    self.camera = camera;
+  }
+
+  /// Moves on to the next frame's resources once the GPU is done with their last submit
+  pub async fn advance_frame(&mut self) -> Result<(), RhiError> {
+    self.current_frame = (self.current_frame + 1) % self.frame_count;
+    self.backend.lock().await.wait_for_fence(self.fences[self.current_frame]).await
+  }
+
+  /// Records, submits and presents the current frame, then advances to the next one.
+  /// Returns true when the swapchain is suboptimal and should be rebuilt
+  pub async fn render_frame(&mut self) -> Result<bool, RhiError> {
+    let frame = self.current_frame;
+    // Checked before an image is acquired, which would then never be presented. The queue is
+    // only cleared once the frame is submitted
+    let draw_meshes = self
+      .queued_meshes
+      .iter()
+      .map(|id| self.meshes.get_obj(id.0))
+      .collect::<Result<Vec<_>, _>>()?;
+    if draw_meshes.len() >= PBR_MAX_BUFFER_COUNT as usize {
+      return Err(RhiError::BackendError(format!(
+        "at render frame: {} meshes queued, at most {} can be drawn",
+        draw_meshes.len(),
+        PBR_MAX_BUFFER_COUNT - 1
+      )));
+    }
+    let mut backend_lock = self.backend.lock().await;
+    // Cheap when `advance_frame` already waited, guards a frame that failed before advancing
+    backend_lock.wait_for_fence(self.fences[frame]).await?;
+    // Acquired before the frame fence is reset, so an out of date swapchain leaves it signaled
+    let image_index = backend_lock.acquire_present_image(self.acquire_fences[frame], None)?;
+    backend_lock.wait_for_fence(self.acquire_fences[frame]).await?;
+    backend_lock.reset_fence(self.acquire_fences[frame])?;
+    backend_lock.reset_command_buffer(self.command_buffers[frame], false)?;
+    let swapchain_image = backend_lock.get_swapchain_images()[image_index as usize];
+    let swapchain_res = backend_lock.get_swapchain_info().res;
+    let camera_uniforms = CameraUniforms::new(
+      &self.camera,
+      swapchain_res.width as f32 / swapchain_res.height as f32
+    );
+    backend_lock.write_buffer(self.camera_buffers[frame], &[camera_uniforms])?;
+    let frame_buffers = std::iter::once(self.camera_buffers[frame])
+      .chain(draw_meshes.iter().map(|x| x.vertex_buffer))
+      .collect();
+    backend_lock.update_input_set(self.input_sets[frame], frame_buffers, vec![], vec![], vec![])?;
+    let draw_infos = draw_meshes
+      .iter()
+      .enumerate()
+      .map(|(i, mesh)| {
+        let mut push_const_data = [0u8; 128];
+        push_const_data[..4].copy_from_slice(&(i as u32 + 1).to_ne_bytes());
+        DrawInfo {
+          mode: DrawMode::Indexed { index_type: IndexType::Uint32 },
+          index_buffer: Some(mesh.index_buffer),
+          instance_buffer: None,
+          offset: 0,
+          count: 0,
+          index_count: mesh.index_count,
+          index_offset: 0,
+          vertex_offset: 0,
+          instance_count: 1,
+          first_instance: 0,
+          push_const_data: Some(push_const_data),
+        }
+      })
+      .collect::<Vec<_>>();
+    let pipeline = self.pipeline;
+    let framebuffer = self.framebuffers[image_index as usize];
+    let input_set = self.input_sets[frame];
+    let mut frame_graph = FrameGraph::new();
+    frame_graph.add_pass(
+      "pbr",
+      &[swapchain_image],
+      Some(self.depth_image),
+      &[],
+      move |encoder| encoder.push(GPUCommands::RunGraphicsPipeline {
+        pipeline,
+        framebuffer,
+        input_set,
+        index_buffer: None,
+        draw_infos: draw_infos.clone(),
+      }),
+    );
+    let mut commands = frame_graph.compile()?;
+    let present_barrier = GPUCommands::PipelineBarrier {
+      src_stage: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+      dst_stage: PipelineStage::BOTTOM_OF_PIPE,
+      buffer_barriers: vec![],
+      image_barriers: vec![ImageBarrier {
+        image: swapchain_image,
+        src_access: MemAccessType::ColorAttachmentWrite,
+        dst_access: MemAccessType::MemoryRead,
+        old_layout: ImageLayoutType::ColorAttachment,
+        new_layout: ImageLayoutType::Present,
+      }],
+    };
+    commands.push(present_barrier);
+    backend_lock.compile_commands(self.command_buffers[frame], commands)?;
+    // Reset last, an error before the submit would leave the next wait on it blocked forever
+    backend_lock.reset_fence(self.fences[frame])?;
+    backend_lock.run_commands(
+      self.command_buffers[frame],
+      self.fences[frame],
+      vec![],
+      vec![self.render_semaphores[frame]]
+    )?;
+    self.queued_meshes.clear();
+    let suboptimal =
+      backend_lock.present_swapchain_image(image_index, &[self.render_semaphores[frame]])?;
+    drop(backend_lock);
+    self.advance_frame().await?;
+    Ok(suboptimal)
+  }
+}
+
+/// Vertex buffer usable as the PBR pipeline's storage buffer and index buffer, both filled
+fn upload_mesh_buffers<B: RenderBackend>(
+  backend: &mut B,
+  mesh: &MeshCPU,
+) -> Result<(BufferID, BufferID), RhiError> {
+  let vertex_data: &[u8] = bytemuck::cast_slice(&mesh.verts);
+  let index_data: &[u8] = bytemuck::cast_slice(&mesh.indices);
+  let vertex_buffer = backend.create_buffer(
+    vertex_data.len() as u64,
+    BufferUsage::STORAGE | BufferUsage::COPY_DST,
+    MemoryLocation::GPU
+  )?;
+  let index_buffer = backend.create_buffer(
+    index_data.len() as u64,
+    BufferUsage::INDEX | BufferUsage::COPY_DST,
+    MemoryLocation::GPU
+  )?;
+  backend.upload_buffer_sync(vertex_buffer, vertex_data)?;
+  backend.upload_buffer_sync(index_buffer, index_data)?;
+  Ok((vertex_buffer, index_buffer))
+}
+
+/// Result of a load queued on an `AssetLoader`
+pub struct LoadHandle<T>(tokio::sync::oneshot::Receiver<Result<T, RhiError>>);
+
+impl<T> LoadHandle<T> {
+  pub async fn wait(self) -> Result<T, RhiError> {
+    self
+      .0
+      .await
+      .map_err(|_| RhiError::BackendError("at asset load: loader stopped".to_string()))?
+  }
+
+  /// `None` while the load is still running
+  pub fn try_take(&mut self) -> Option<Result<T, RhiError>> {
+    match self.0.try_recv() {
+      Ok(result) => Some(result),
+      Err(tokio::sync::oneshot::error::TryRecvError::Empty) => None,
+      Err(tokio::sync::oneshot::error::TryRecvError::Closed) => {
+        Some(Err(RhiError::BackendError("at asset load: loader stopped".to_string())))
+      }
+    }
+  }
+}
+
+enum LoadRequest {
+  Texture(PathBuf, tokio::sync::oneshot::Sender<Result<ImageID, RhiError>>),
+  Mesh(PathBuf, tokio::sync::oneshot::Sender<Result<(BufferID, BufferID), RhiError>>),
+}
+
+/// Reads and decodes files on a background task. The backend is only locked while the decoded
+/// data is uploaded, so frames keep rendering while files load
+pub struct AssetLoader {
+  requests: tokio::sync::mpsc::UnboundedSender<LoadRequest>,
+}
+
+impl AssetLoader {
+  /// Spawns the background task, has to be called from inside a tokio runtime. The task stops
+  /// once the loader is dropped and the queued loads are done
+  pub fn new<B: RenderBackend + 'static>(backend: Arc<Mutex<B>>) -> Self {
+    let (requests, mut request_receiver) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+      while let Some(request) = request_receiver.recv().await {
+        // Every load gets its own task so a big file doesn't hold up the ones queued after it
+        tokio::spawn(Self::load(backend.clone(), request));
+      }
+    });
+    Self { requests }
+  }
+
+  pub fn queue_texture(&self, path: PathBuf) -> LoadHandle<ImageID> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    // A failed send drops `sender`, which the handle reports as a stopped loader
+    let _ = self.requests.send(LoadRequest::Texture(path, sender));
+    LoadHandle(receiver)
+  }
+
+  /// Vertex and index buffer like `PbrRenderer::upload_mesh` makes. All the primitives in the
+  /// GLTF file are merged into one mesh
+  pub fn queue_mesh(&self, path: PathBuf) -> LoadHandle<(BufferID, BufferID)> {
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let _ = self.requests.send(LoadRequest::Mesh(path, sender));
+    LoadHandle(receiver)
+  }
+
+  async fn load<B: RenderBackend>(backend: Arc<Mutex<B>>, request: LoadRequest) {
+    // A failed send means the handle was dropped and nobody wants the result
+    match request {
+      LoadRequest::Texture(path, sender) => {
+        let _ = sender.send(Self::load_texture(&backend, &path).await);
+      }
+      LoadRequest::Mesh(path, sender) => {
+        let _ = sender.send(Self::load_mesh(&backend, &path).await);
+      }
+    }
+  }
+
+  async fn read_file(path: &Path) -> Result<Vec<u8>, RhiError> {
+    tokio::fs::read(path)
+      .await
+      .map_err(|e| RhiError::BackendError(format!("at asset file read {}: {e}", path.display())))
+  }
+
+  async fn load_texture<B: RenderBackend>(
+    backend: &Mutex<B>,
+    path: &Path,
+  ) -> Result<ImageID, RhiError> {
+    let file_data = Self::read_file(path).await?;
+    let (data, res, format) =
+      load_texture_from_slice(path, &file_data).map_err(RhiError::BackendError)?;
+    backend.lock().await.upload_texture(&data, res, format)
+  }
+
+  async fn load_mesh<B: RenderBackend>(
+    backend: &Mutex<B>,
+    path: &Path,
+  ) -> Result<(BufferID, BufferID), RhiError> {
+    let file_data = Self::read_file(path).await?;
+    let mut merged = MeshCPU { verts: vec![], indices: vec![] };
+    for mesh in load_gltf_from_slice(path, &file_data).map_err(RhiError::BackendError)? {
+      let index_offset = merged.verts.len() as u32;
+      merged.indices.extend(mesh.indices.iter().map(|x| x + index_offset));
+      merged.verts.extend(mesh.verts);
+    }
+    if merged.indices.is_empty() {
+      return Err(RhiError::BackendError(format!("at gltf {}: file has no meshes", path.display())));
+    }
+    upload_mesh_buffers(&mut *backend.lock().await, &merged)
   }
 }
 
@@ -50,8 +946,205 @@ pub struct Renderer<B: RenderBackend>{
 }
 
 impl<B: RenderBackend> Renderer<B>{
-  pub fn new(backend: Arc<Mutex<B>>) -> Result<Renderer<B>, String>{
-    let pbr_renderer = PbrRenderer::new(backend.clone())?;
+  pub async fn new(backend: Arc<Mutex<B>>) -> Result<Renderer<B>, RhiError>{
+    let pbr_renderer = PbrRenderer::new(backend.clone()).await?;
     Ok(Self{ backend, pbr_renderer })
   }
+
+  pub fn upload_mesh(&mut self, mesh: &MeshCPU) -> Result<MeshID, RhiError> {
+    self.pbr_renderer.upload_mesh(mesh)
+  }
+
+  pub fn draw_mesh(&mut self, mesh_id: MeshID) {
+    self.pbr_renderer.draw_mesh(mesh_id)
+  }
+
+  pub fn set_camera(&mut self, camera: Camera) {
+    self.pbr_renderer.set_camera(camera)
+  }
+
+  /// Rebuilds the backend's swapchain and everything sized to it, see
+  /// `RenderBackend::rebuild_swapchain` for `width` and `height`
+  pub fn rebuild_swapchain(&mut self, width: u32, height: u32) -> Result<(), RhiError> {
+    self.backend.blocking_lock().rebuild_swapchain(width, height)?;
+    self.pbr_renderer.rebuild_swapchain_targets()
+  }
+
+  /// Acquires, draws and presents one frame. Out of date and suboptimal swapchains are rebuilt
+  /// at their current size, the frame is dropped when it was out of date
+  pub async fn render_frame(&mut self) -> Result<(), RhiError> {
+    match self.pbr_renderer.render_frame().await {
+      Ok(false) => Ok(()),
+      Ok(true) | Err(RhiError::SwapchainOutOfDate) => {
+        let mut backend_lock = self.backend.lock().await;
+        let swapchain_res = backend_lock.get_swapchain_info().res;
+        backend_lock.rebuild_swapchain(swapchain_res.width, swapchain_res.height)?;
+        self.pbr_renderer.replace_swapchain_targets(&mut backend_lock)
+      }
+      Err(e) => Err(e),
+    }
+  }
+}
+
+#[cfg(test)]
+mod frame_graph_tests {
+  use super::*;
+
+  /// Stands in for a pass' commands so the pass order shows in the output
+  fn marker(pass: u64) -> GPUCommands {
+    GPUCommands::CopyBufferRegion {
+      src: BufferID(0),
+      src_offset: 0,
+      dst: BufferID(0),
+      dst_offset: 0,
+      size: pass,
+    }
+  }
+
+  fn pass_order(commands: &[GPUCommands]) -> Vec<u64> {
+    commands
+      .iter()
+      .filter_map(|command| match command {
+        GPUCommands::CopyBufferRegion { size, .. } => Some(*size),
+        _ => None,
+      })
+      .collect()
+  }
+
+  fn barriers(commands: &[GPUCommands]) -> Vec<(PipelineStage, PipelineStage, &[ImageBarrier])> {
+    commands
+      .iter()
+      .filter_map(|command| match command {
+        GPUCommands::PipelineBarrier { src_stage, dst_stage, image_barriers, .. } => {
+          Some((*src_stage, *dst_stage, image_barriers.as_slice()))
+        }
+        _ => None,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn readers_run_after_writers() {
+    let (gbuffer, depth, lit) = (ImageID(0), ImageID(1), ImageID(2));
+    let mut graph = FrameGraph::new();
+    graph.add_pass("lighting", &[lit], None, &[gbuffer], |e| e.push(marker(1)));
+    graph.add_pass("gbuffer", &[gbuffer], Some(depth), &[], |e| e.push(marker(0)));
+    let commands = graph.compile().unwrap();
+    assert_eq!(pass_order(&commands), vec![0, 1]);
+
+    let barriers = barriers(&commands);
+    assert_eq!(barriers.len(), 2);
+    // First use of the attachments waits on the same writes of frames before
+    let (src_stage, dst_stage, gbuffer_barriers) = barriers[0];
+    let depth_stages = PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS;
+    assert_eq!(src_stage.bits(), (PipelineStage::COLOR_ATTACHMENT_OUTPUT | depth_stages).bits());
+    assert_eq!(dst_stage.bits(), src_stage.bits());
+    assert_eq!(gbuffer_barriers.len(), 2);
+    assert!(matches!(gbuffer_barriers[0], ImageBarrier {
+      image: ImageID(0),
+      src_access: MemAccessType::ColorAttachmentWrite,
+      dst_access: MemAccessType::ColorAttachmentWrite,
+      old_layout: ImageLayoutType::Undefined,
+      new_layout: ImageLayoutType::ColorAttachment,
+    }));
+    assert!(matches!(gbuffer_barriers[1], ImageBarrier {
+      image: ImageID(1),
+      src_access: MemAccessType::DepthAttachmentWrite,
+      old_layout: ImageLayoutType::Undefined,
+      new_layout: ImageLayoutType::DepthAttachment,
+      ..
+    }));
+
+    let (src_stage, dst_stage, lighting_barriers) = barriers[1];
+    assert_eq!(src_stage.bits(), PipelineStage::COLOR_ATTACHMENT_OUTPUT.bits());
+    let lighting_stages = PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::FRAGMENT_SHADER;
+    assert_eq!(dst_stage.bits(), lighting_stages.bits());
+    assert_eq!(lighting_barriers.len(), 2);
+    assert!(matches!(lighting_barriers[0], ImageBarrier {
+      image: ImageID(2),
+      old_layout: ImageLayoutType::Undefined,
+      new_layout: ImageLayoutType::ColorAttachment,
+      ..
+    }));
+    assert!(matches!(lighting_barriers[1], ImageBarrier {
+      image: ImageID(0),
+      src_access: MemAccessType::ColorAttachmentWrite,
+      dst_access: MemAccessType::ShaderRead,
+      old_layout: ImageLayoutType::ColorAttachment,
+      new_layout: ImageLayoutType::ShaderRO,
+    }));
+  }
+
+  #[test]
+  fn first_read_waits_on_all_commands() {
+    let mut graph = FrameGraph::new();
+    graph.add_pass("sample", &[], None, &[ImageID(0)], |_| {});
+    let commands = graph.compile().unwrap();
+    let barriers = barriers(&commands);
+    let (src_stage, _, image_barriers) = barriers[0];
+    assert_eq!(src_stage.bits(), PipelineStage::ALL_COMMANDS.bits());
+    assert!(matches!(image_barriers, [ImageBarrier {
+      src_access: MemAccessType::None,
+      old_layout: ImageLayoutType::Undefined,
+      new_layout: ImageLayoutType::ShaderRO,
+      ..
+    }]));
+  }
+
+  #[test]
+  fn read_write_image_gets_one_general_barrier() {
+    let image = ImageID(0);
+    let mut graph = FrameGraph::new();
+    graph.add_pass("feedback", &[image], None, &[image], |_| {});
+    let commands = graph.compile().unwrap();
+    let barriers = barriers(&commands);
+    let (_, dst_stage, image_barriers) = barriers[0];
+    assert!(dst_stage.contains(PipelineStage::FRAGMENT_SHADER));
+    assert!(matches!(image_barriers, [ImageBarrier {
+      dst_access: MemAccessType::MemoryReadWrite,
+      new_layout: ImageLayoutType::General,
+      ..
+    }]));
+  }
+
+  #[test]
+  fn cycles_are_rejected() {
+    let (a, b) = (ImageID(0), ImageID(1));
+    let mut graph = FrameGraph::new();
+    graph.add_pass("first", &[a], None, &[b], |_| {});
+    graph.add_pass("second", &[b], None, &[a], |_| {});
+    assert!(graph.compile().is_err());
+  }
+}
+
+#[cfg(all(test, feature = "null-backend"))]
+mod tests {
+  use super::*;
+
+  fn triangle() -> MeshCPU {
+    let vertex = |x: f32, y: f32| MeshVertex {
+      position: glam::Vec4::new(x, y, 0.0, 1.0),
+      normal: glam::Vec4::Z,
+      tangent: glam::Vec4::X,
+      bi_tangent: glam::Vec4::Y,
+      tex_coord: glam::Vec4::ZERO,
+    };
+    MeshCPU {
+      verts: vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)],
+      indices: vec![0, 1, 2],
+    }
+  }
+
+  #[test]
+  fn renders_mesh_on_null_backend() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let backend = NullRenderBackend::new(Resolution2D { width: 640, height: 480 }, 3);
+    let mut renderer = runtime.block_on(Renderer::new(Arc::new(Mutex::new(backend)))).unwrap();
+    let mesh_id = renderer.upload_mesh(&triangle()).unwrap();
+    // More frames than swapchain images, so every frame's fence gets waited on again
+    for _ in 0..4 {
+      renderer.draw_mesh(mesh_id);
+      runtime.block_on(renderer.render_frame()).unwrap();
+    }
+  }
 }
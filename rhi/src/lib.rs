@@ -9,22 +9,158 @@ pub struct GPUInfo{
   pub integrated: bool,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceLimits{
+  /// Largest width or height of a 2D texture
+  pub max_texture_size: u32,
+  pub max_push_constant_size: u32,
+  pub max_compute_group_size: [u32; 3],
+  pub min_uniform_buffer_alignment: u64,
+  /// Meaningful bits in timestamps written on the graphics queue, 0 if they aren't supported
+  pub timestamp_valid_bits: u32,
+}
+
+/// Optional features the GPU supports
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceFeatures{
+  pub anisotropy: bool,
+  pub fill_mode_non_solid: bool,
+  pub multi_draw_indirect: bool,
+  pub tessellation: bool,
+  pub geometry_shader: bool,
+  /// `get_buffer_device_address` and `BufferUsage::DEVICE_ADDRESS` work
+  pub buffer_device_address: bool,
+}
+
+/// Sizes are in bytes. `heap_usage` and `budget` include other processes' use of the heap
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryHeapBudget{
+  pub heap_size: u64,
+  pub heap_usage: u64,
+  pub budget: u64,
+}
+
+bitflags! {
+  #[derive(Debug, Clone, Copy)]
+  pub struct MemoryHeapFlags: u32 {
+    const DEVICE_LOCAL = 0b01;
+    const MULTI_INSTANCE = 0b10;
+  }
+}
+
+bitflags! {
+  #[derive(Debug, Clone, Copy)]
+  pub struct MemoryPropertyFlags: u32 {
+    const DEVICE_LOCAL = 0b00001;
+    const HOST_VISIBLE = 0b00010;
+    const HOST_COHERENT = 0b00100;
+    const HOST_CACHED = 0b01000;
+    const LAZILY_ALLOCATED = 0b10000;
+  }
+}
+
+/// `size` is in bytes
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryHeap {
+  pub size: u64,
+  pub flags: MemoryHeapFlags,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryType {
+  pub property_flags: MemoryPropertyFlags,
+  pub heap_index: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryInfo {
+  pub heaps: Vec<MemoryHeap>,
+  pub types: Vec<MemoryType>,
+}
+
+#[derive(Debug, Clone)]
+pub enum RhiError {
+  OutOfMemory,
+  DeviceLost,
+  /// The swapchain no longer matches the surface and has to be rebuilt
+  SwapchainOutOfDate,
+  InvalidHandle(String),
+  BackendError(String),
+  AllocationFailed(String),
+}
+
+impl std::fmt::Display for RhiError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RhiError::OutOfMemory => write!(f, "out of memory"),
+      RhiError::DeviceLost => write!(f, "device lost"),
+      RhiError::SwapchainOutOfDate => write!(f, "swapchain out of date"),
+      RhiError::InvalidHandle(e) => write!(f, "invalid handle: {e}"),
+      RhiError::BackendError(e) => write!(f, "{e}"),
+      RhiError::AllocationFailed(e) => write!(f, "allocation failed: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for RhiError {}
+
+impl From<&str> for RhiError {
+  fn from(e: &str) -> Self {
+    RhiError::InvalidHandle(e.to_string())
+  }
+}
+
+impl From<seq_id_store::StoreError> for RhiError {
+  fn from(e: seq_id_store::StoreError) -> Self {
+    match e {
+      seq_id_store::StoreError::CapacityExceeded => RhiError::AllocationFailed(e.to_string()),
+      seq_id_store::StoreError::StaleId | seq_id_store::StoreError::NotFound => {
+        RhiError::InvalidHandle(e.to_string())
+      }
+    }
+  }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Resolution2D {
   pub width: u32,
   pub height: u32,
 }
 
+impl Resolution2D {
+  /// Length of a full mip chain, `floor(log2(max(width, height))) + 1`
+  pub fn mip_count(&self) -> u32 {
+    self.width.max(self.height).max(1).ilog2() + 1
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresentMode {
+  /// Vsync, always supported
+  #[default]
+  Fifo,
+  Mailbox,
+  Immediate,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct SwapchainInfo{
   pub res: Resolution2D,
   pub image_count: u32,
+  pub present_mode: PresentMode,
+  /// False when the preferred present mode wasn't supported and FIFO was used instead
+  pub is_preferred_present_mode: bool,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum MemoryLocation{
   Any,
   GPU,
-  Shared,
+  /// CPU written, GPU read. For uniforms updated every frame and `COPY_SRC` upload staging
+  CPUToGPU,
+  /// GPU written, CPU read. For `COPY_DST` buffers that `CopyImageToBuffer` or
+  /// `CopyBufferRegion` read results back into
+  CPUReadback,
 }
 
 bitflags! {
@@ -36,6 +172,8 @@ bitflags! {
     const BLIT_DST = 0b00001000;
     const SHADER_SAMPLED = 0b00010000;
     const SHADER_STORAGE = 0b00100000;
+    /// Color or depth attachment. Multiview pipelines render into `Texture2DArray` targets
+    const RENDER_TARGET = 0b01000000;
   }
 }
 
@@ -43,9 +181,24 @@ bitflags! {
 pub enum ImageFormat {
   Texture,
   Float,
-  Depth,
   RenderIntermediate,
   Presentation,
+  R8Unorm,
+  Rg8Unorm,
+  Rgba8Unorm,
+  Rgba8Srgb,
+  R16Sfloat,
+  Rg16Sfloat,
+  Rgba16Sfloat,
+  R32Sfloat,
+  Rg32Sfloat,
+  Rgba32Sfloat,
+  R32Uint,
+  R32Sint,
+  /// HDR environment maps, 16 bit float RGBA
+  Hdr,
+  Depth32Sfloat,
+  Depth24Stencil8,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -62,6 +215,10 @@ pub enum MemAccessType {
   DepthAttachmentWrite,
   MemoryRead,
   MemoryWrite,
+  /// `MemoryRead` and `MemoryWrite` together, e.g. for an image a pass both reads and writes
+  MemoryReadWrite,
+  /// Only orders execution, e.g. for a barrier waiting on reads
+  None,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -74,6 +231,7 @@ pub enum ImageLayoutType {
   ShaderRO,
   TransferSrc,
   TransferDst,
+  Present,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -85,6 +243,46 @@ pub enum ImageSampleCount {
   E16,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum ImageTiling {
+  Optimal,
+  Linear,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TextureType {
+  Texture2D,
+  Texture3D { depth: u32 },
+  Texture2DArray { layers: u32 },
+}
+
+/// `is_cube` needs `Texture2DArray { layers: 6 }`, the layers are the +X, -X, +Y, -Y, +Z, -Z faces
+#[derive(Debug, Clone, Copy)]
+pub struct TextureDesc {
+  pub ty: TextureType,
+  pub res: Resolution2D,
+  pub format: ImageFormat,
+  pub usage: ImageUsage,
+  pub memory_location: MemoryLocation,
+  pub sample_count: ImageSampleCount,
+  pub mip_levels: u32,
+  pub is_cube: bool,
+}
+
+/// What a 2D texture of a format, usage and tiling can be created with
+#[derive(Debug, Clone, Copy)]
+pub struct FormatSupport {
+  pub supported: bool,
+  pub max_sample_count: ImageSampleCount,
+  pub max_mip_levels: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BlitFilter {
+  Linear,
+  Nearest,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ImageID(pub u32);
 
@@ -94,6 +292,41 @@ pub struct ImageViewID(pub u32);
 #[derive(Debug, Clone, Copy)]
 pub struct SamplerID(pub u32);
 
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerFilter {
+  Linear,
+  Nearest,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerMipMode {
+  Linear,
+  Nearest,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerAddressMode {
+  Repeat,
+  MirroredRepeat,
+  ClampToEdge,
+  ClampToBorder,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerDesc {
+  pub mag_filter: SamplerFilter,
+  pub min_filter: SamplerFilter,
+  pub mip_mode: SamplerMipMode,
+  pub address_u: SamplerAddressMode,
+  pub address_v: SamplerAddressMode,
+  pub address_w: SamplerAddressMode,
+  /// Values above 1.0 enable anisotropic filtering if the device supports it
+  pub anisotropy_max: f32,
+  pub lod_bias: f32,
+  pub min_lod: f32,
+  pub max_lod: f32,
+}
+
 bitflags! {
   #[derive(Debug, Clone, Copy)]
   pub struct BufferUsage: u32 {
@@ -101,17 +334,62 @@ bitflags! {
     const COPY_DST = 0b00000010;
     const UNIFORM = 0b00000100;
     const STORAGE = 0b00001000;
+    const VERTEX = 0b00010000;
+    const INDEX = 0b00100000;
+    const INDIRECT = 0b01000000;
+    /// Vertex, index or instance data read by acceleration structure builds
+    const ACCEL_STRUCT_INPUT = 0b10000000;
+    /// Lets `get_buffer_device_address` hand out the buffer's GPU pointer
+    const DEVICE_ADDRESS = 0b100000000;
   }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct BufferID(pub u32);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexType {
+  Uint16,
+  Uint32,
+}
+
 bitflags! {
   #[derive(Debug, Clone, Copy)]
   pub struct ShaderStageFlags: u32 {
     const VERTEX = 0b00000001;
     const FRAGMENT = 0b00000010;
+    const COMPUTE = 0b00000100;
+    const TESSELLATION_CONTROL = 0b00001000;
+    const TESSELLATION_EVALUATION = 0b00010000;
+    const GEOMETRY = 0b00100000;
+    const TASK = 0b01000000;
+    const MESH = 0b10000000;
+    const ALL_GRAPHICS = Self::VERTEX.bits()
+      | Self::FRAGMENT.bits()
+      | Self::TESSELLATION_CONTROL.bits()
+      | Self::TESSELLATION_EVALUATION.bits()
+      | Self::GEOMETRY.bits();
+    const ALL = 0b11111111;
+  }
+}
+
+bitflags! {
+  #[derive(Debug, Clone, Copy)]
+  pub struct PipelineStage: u32 {
+    const TOP_OF_PIPE = 0b00000000000001;
+    const DRAW_INDIRECT = 0b00000000000010;
+    const VERTEX_INPUT = 0b00000000000100;
+    const VERTEX_SHADER = 0b00000000001000;
+    const FRAGMENT_SHADER = 0b00000000010000;
+    const EARLY_FRAGMENT_TESTS = 0b00000000100000;
+    const LATE_FRAGMENT_TESTS = 0b00000001000000;
+    const COLOR_ATTACHMENT_OUTPUT = 0b00000010000000;
+    const COMPUTE_SHADER = 0b00000100000000;
+    const TRANSFER = 0b00001000000000;
+    const BOTTOM_OF_PIPE = 0b00010000000000;
+    const HOST = 0b00100000000000;
+    const ALL_GRAPHICS = 0b01000000000000;
+    const ALL_COMMANDS = 0b10000000000000;
   }
 }
 
@@ -119,22 +397,400 @@ bitflags! {
 pub enum DescriptorType {
   Uniform,
   Storage,
-  Sampler2D,
+  Sampler,
+  CombinedImageSampler,
+  StorageImage,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct DescriptorSetID(pub u32);
 
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderID(pub u32);
+
 #[derive(Debug, Clone, Copy)]
 pub struct PipelineID(pub u32);
 
+#[derive(Debug, Clone, Copy)]
+pub struct ComputePipelineID(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayTracingPipelineID(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct AccelStructID(pub u32);
+
+/// Triangle geometry of a bottom level acceleration structure. Each vertex starts with its
+/// position as 3 `f32`s, `stride` bytes apart. Indices are `u32`, without an index buffer every 3
+/// vertices make a triangle. The buffers need `BufferUsage::ACCEL_STRUCT_INPUT`
+#[derive(Debug, Clone, Copy)]
+pub struct BLASDesc {
+  pub vertex_buffer: BufferID,
+  pub vertex_count: u32,
+  pub stride: u32,
+  pub index_buffer: Option<BufferID>,
+  pub index_count: u32,
+}
+
+/// Placement of a bottom level acceleration structure in a top level one. `transform` is a row
+/// major 3x4 matrix, `instance_id` is the shaders' `gl_InstanceCustomIndexEXT` and only its low
+/// 24 bits are kept, as with `hit_group_offset`
+#[derive(Debug, Clone, Copy)]
+pub struct TLASInstance {
+  pub blas: AccelStructID,
+  pub transform: [f32; 12],
+  pub instance_id: u32,
+  /// Rays only hit instances whose mask shares a bit with the ray's cull mask
+  pub mask: u8,
+  /// Index of the instance's first hit group in the shader binding table
+  pub hit_group_offset: u32,
+}
+
+/// Shaders run for the rays hitting the geometry of one shader binding table entry. Triangle
+/// geometry has no `intersection` shader, procedural geometry needs one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HitGroup {
+  pub closest_hit: Option<ShaderID>,
+  pub any_hit: Option<ShaderID>,
+  pub intersection: Option<ShaderID>,
+}
+
+/// Shader binding table entries follow the order of `miss` and `hit_groups`. Rays can be
+/// traced from hit and miss shaders up to `max_recursion` levels deep. All stages share the
+/// input set layout of compute pipelines. Needs `VK_KHR_ray_tracing_pipeline`
+#[derive(Debug, Clone)]
+pub struct RayTracingPipelineDesc {
+  pub raygen: ShaderID,
+  pub miss: Vec<ShaderID>,
+  pub hit_groups: Vec<HitGroup>,
+  pub max_recursion: u32,
+  pub max_buffer_count: u32,
+  pub max_texture_count: u32,
+  pub storage_image_count: u32,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct FramebufferID(pub u32);
 
 #[derive(Debug, Clone, Copy)]
 pub enum RasterStyle {
   Fill,
-  WireFrame{thickness: u32}
+  /// Needs the `fillModeNonSolid` device feature, and `wideLines` for a thickness other than 1
+  WireFrame{thickness: f32},
+  /// Needs the `fillModeNonSolid` device feature, and `largePoints` for a size above 1.
+  /// Vulkan has no dynamic state for point size, it comes from `gl_PointSize` in the vertex
+  /// shader, so `size` is the largest size the shader writes and is fixed per pipeline
+  Point{size: f32},
+  /// Filled, rasterizing every pixel a triangle touches at all. Needs
+  /// `VK_EXT_conservative_rasterization`
+  FillConservative,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CullMode {
+  None,
+  Back,
+  Front,
+  FrontAndBack,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FrontFace {
+  Clockwise,
+  CounterClockwise,
+}
+
+/// Offsets fragment depths, keeps shadow maps from shadowing themselves
+#[derive(Debug, Clone, Copy)]
+pub struct DepthBias {
+  pub constant: f32,
+  /// Largest bias applied, 0 for no limit. Other values need the `depthBiasClamp` device feature
+  pub clamp: f32,
+  /// Scaled by the depth slope of the polygon
+  pub slope: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RasterConfig {
+  pub style: RasterStyle,
+  /// Defaults to `Back` for `Fill`, `None` otherwise so rear edges and points stay visible
+  pub cull_mode: Option<CullMode>,
+  pub front_face: FrontFace,
+  /// Clamps depths to the viewport depth range instead of clipping the geometry. Needs the
+  /// `depthClamp` device feature
+  pub depth_clamp: bool,
+  /// `None` disables depth bias, `GPUCommands::SetDepthBias` changes the values between runs
+  pub depth_bias: Option<DepthBias>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BlendFactor {
+  Zero,
+  One,
+  SrcColor,
+  OneMinusSrcColor,
+  DstColor,
+  OneMinusDstColor,
+  SrcAlpha,
+  OneMinusSrcAlpha,
+  DstAlpha,
+  OneMinusDstAlpha,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BlendOp {
+  Add,
+  Subtract,
+  ReverseSubtract,
+  Min,
+  Max,
+}
+
+bitflags! {
+  #[derive(Debug, Clone, Copy)]
+  pub struct ColorComponentFlags: u32 {
+    const R = 0b0001;
+    const G = 0b0010;
+    const B = 0b0100;
+    const A = 0b1000;
+  }
+}
+
+/// Blending for a single color attachment
+#[derive(Debug, Clone, Copy)]
+pub struct BlendState {
+  pub blend_enable: bool,
+  pub src_color: BlendFactor,
+  pub dst_color: BlendFactor,
+  pub color_op: BlendOp,
+  pub src_alpha: BlendFactor,
+  pub dst_alpha: BlendFactor,
+  pub alpha_op: BlendOp,
+  /// Channels the attachment keeps, e.g. only `A` for a mask pass or `R | G | B` to leave alpha
+  /// untouched. `ColorComponentFlags::all()` writes every channel
+  pub write_mask: ColorComponentFlags,
+}
+
+/// Opaque, writing every channel
+impl Default for BlendState {
+  fn default() -> Self {
+    Self {
+      blend_enable: false,
+      src_color: BlendFactor::One,
+      dst_color: BlendFactor::Zero,
+      color_op: BlendOp::Add,
+      src_alpha: BlendFactor::One,
+      dst_alpha: BlendFactor::Zero,
+      alpha_op: BlendOp::Add,
+      write_mask: ColorComponentFlags::all(),
+    }
+  }
+}
+
+/// Geometry stages of a graphics pipeline. Mesh shader pipelines replace vertex input with
+/// workgroups that output meshlets, are drawn with `GPUCommands::DrawMeshTasks` and need
+/// `VK_EXT_mesh_shader`. The optional task shader picks how many mesh workgroups run
+#[derive(Debug, Clone, Copy)]
+pub enum PipelineType {
+  VertexPipeline{vertex_shader: ShaderID},
+  MeshShaderPipeline{task_shader: Option<ShaderID>, mesh_shader: ShaderID},
+}
+
+/// Draws become patches of `patch_control_points` vertices, which the control shader subdivides
+/// and the evaluation shader places
+#[derive(Debug, Clone, Copy)]
+pub struct TessellationConfig {
+  pub control_shader: ShaderID,
+  pub evaluation_shader: ShaderID,
+  pub patch_control_points: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MultisampleState {
+  pub sample_count: ImageSampleCount,
+  /// Turns the fragment alpha into a coverage mask, so MSAA smooths the edges of cutouts like
+  /// foliage without sorting
+  pub alpha_to_coverage: bool,
+  /// Replaces the fragment alpha with 1 after coverage, needs the `alphaToOne` device feature
+  pub alpha_to_one: bool,
+}
+
+impl Default for MultisampleState {
+  fn default() -> Self {
+    Self {
+      sample_count: ImageSampleCount::E1,
+      alpha_to_coverage: false,
+      alpha_to_one: false,
+    }
+  }
+}
+
+/// Color or depth attachment of a graphics pipeline's pass. `initial_layout` is `Undefined` to
+/// discard the old contents, or the attachment layout to keep them. The pass leaves the
+/// attachment in `final_layout`
+#[derive(Debug, Clone, Copy)]
+pub struct AttachmentDesc {
+  pub format: ImageFormat,
+  pub initial_layout: ImageLayoutType,
+  pub final_layout: ImageLayoutType,
+}
+
+/// Execution and memory dependency of a render pass's subpass, `None` stands for the commands
+/// outside the pass
+#[derive(Debug, Clone, Copy)]
+pub struct SubpassDependency {
+  pub src_subpass: Option<u32>,
+  pub dst_subpass: Option<u32>,
+  pub src_stage: PipelineStage,
+  pub dst_stage: PipelineStage,
+  pub src_access: MemAccessType,
+  pub dst_access: MemAccessType,
+}
+
+/// Attachments of a graphics pipeline's pass. `color_ops` holds one entry per color attachment
+/// and `depth_ops` is needed exactly when there is a depth attachment. Unless `dependencies`
+/// already has one, the pass gets an external dependency ordering earlier attachment writes
+/// before its own. Dependencies only apply without dynamic rendering, which has no render pass
+#[derive(Debug, Clone)]
+pub struct RenderPassDesc {
+  pub color_attachments: Vec<AttachmentDesc>,
+  pub depth_attachment: Option<AttachmentDesc>,
+  pub color_ops: Vec<AttachmentOps>,
+  pub depth_ops: Option<AttachmentOps>,
+  pub dependencies: Vec<SubpassDependency>,
+}
+
+/// `blend_states` holds one entry per color attachment of `pass`, attachments past its end get
+/// `BlendState::default()`, which writes every channel. `depth_state: None` disables depth tests.
+/// Set 0 holds `max_buffer_count` storage buffers at binding 0 and `uniform_buffer_count`
+/// uniform buffers at binding 1.
+/// With a `multiview_mask` every draw renders once per set bit into that layer of all
+/// attachments, which need more layers than the highest set bit, e.g. two layers with mask
+/// `0b11` for stereo. Shaders read the layer from `gl_ViewIndex`. Needs `VK_KHR_multiview`.
+/// A `geometry_shader` runs between the vertex and fragment shaders, needs the `geometryShader`
+/// device feature, as does `tessellation` the `tessellationShader` one. Neither works with mesh
+/// shader pipelines, whose mesh shader gets `vertex_spec_constants`, and neither does an
+/// `instance_input`
+#[derive(Debug, Clone)]
+pub struct GraphicsPipelineDesc {
+  pub raster_config: RasterConfig,
+  pub pass: RenderPassDesc,
+  pub max_buffer_count: u32,
+  pub uniform_buffer_count: u32,
+  pub max_texture_count: u32,
+  pub storage_image_count: u32,
+  pub pipeline_type: PipelineType,
+  pub fragment_shader: ShaderID,
+  pub geometry_shader: Option<ShaderID>,
+  pub tessellation: Option<TessellationConfig>,
+  pub vertex_spec_constants: Vec<SpecializationConstant>,
+  pub fragment_spec_constants: Vec<SpecializationConstant>,
+  pub push_constant_size: u32,
+  pub push_constant_stages: ShaderStageFlags,
+  pub multisample: MultisampleState,
+  pub blend_states: Vec<BlendState>,
+  pub depth_state: Option<DepthState>,
+  pub multiview_mask: Option<u32>,
+  pub instance_input: Option<InstanceInput>,
+}
+
+/// A vertex shader input at `location`, read `offset` bytes into each instance's entry. Color
+/// formats like `Rgba32Sfloat` give the attribute's component layout
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceAttribute {
+  pub location: u32,
+  pub format: ImageFormat,
+  pub offset: u32,
+}
+
+/// Per instance vertex input, read from `DrawInfo::instance_buffer` in entries `stride` bytes
+/// apart
+#[derive(Debug, Clone)]
+pub struct InstanceInput {
+  pub stride: u32,
+  pub attributes: Vec<InstanceAttribute>,
+}
+
+/// What happens to an attachment's contents when a pass begins. For depth attachments
+/// `Clear` uses the first value as the depth and the second as the stencil
+#[derive(Debug, Clone, Copy)]
+pub enum LoadOp {
+  Clear([f32; 4]),
+  Load,
+  DontCare,
+}
+
+/// What happens to an attachment's contents when a pass ends
+#[derive(Debug, Clone, Copy)]
+pub enum StoreOp {
+  Store,
+  DontCare,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AttachmentOps {
+  pub load: LoadOp,
+  pub store: StoreOp,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CompareOp {
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+  Equal,
+  Always,
+  Never,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StencilOp {
+  Keep,
+  Zero,
+  Replace,
+  IncrementClamp,
+  DecrementClamp,
+  Invert,
+  IncrementWrap,
+  DecrementWrap,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StencilOpState {
+  pub fail_op: StencilOp,
+  pub pass_op: StencilOp,
+  pub depth_fail_op: StencilOp,
+  pub compare_op: CompareOp,
+  pub compare_mask: u32,
+  pub write_mask: u32,
+  pub reference: u32,
+}
+
+/// `front` and `back` are ignored unless `stencil_enable` is set
+#[derive(Debug, Clone, Copy)]
+pub struct DepthState {
+  pub depth_test_enable: bool,
+  pub depth_write_enable: bool,
+  pub compare_op: CompareOp,
+  pub stencil_enable: bool,
+  pub front: StencilOpState,
+  pub back: StencilOpState,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SpecConstValue {
+  Bool(bool),
+  Int(i32),
+  Uint(u32),
+  Float(f32),
+}
+
+/// Value for the shader's `layout(constant_id = id)` constant, fixed when the pipeline is made
+#[derive(Debug, Clone, Copy)]
+pub struct SpecializationConstant {
+  pub id: u32,
+  pub value: SpecConstValue,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -143,92 +799,608 @@ pub struct InputSetID(pub u32);
 #[derive(Debug, Clone, Copy)]
 pub struct FenceID(pub u32);
 
+#[derive(Debug, Clone, Copy)]
+pub struct SemaphoreID(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueryPoolID(pub u32);
+
+bitflags! {
+  #[derive(Debug, Clone, Copy)]
+  pub struct PipelineStatFlags: u32 {
+    const INPUT_ASSEMBLY_VERTICES = 0b0000001;
+    const INPUT_ASSEMBLY_PRIMITIVES = 0b0000010;
+    const VERTEX_SHADER_INVOCATIONS = 0b0000100;
+    const CLIPPING_INVOCATIONS = 0b0001000;
+    const CLIPPING_PRIMITIVES = 0b0010000;
+    const FRAGMENT_SHADER_INVOCATIONS = 0b0100000;
+    const COMPUTE_SHADER_INVOCATIONS = 0b1000000;
+  }
+}
+
+/// Counters of one pipeline statistics query, `None` for the ones its pool doesn't count
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipelineStatsResult {
+  pub input_assembly_vertices: Option<u64>,
+  pub input_assembly_primitives: Option<u64>,
+  pub vertex_shader_invocations: Option<u64>,
+  pub clipping_invocations: Option<u64>,
+  pub clipping_primitives: Option<u64>,
+  pub fragment_shader_invocations: Option<u64>,
+  pub compute_shader_invocations: Option<u64>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct CommandBufferID(pub u32);
 
+/// Pipeline and framebuffer a secondary command buffer draws with
+#[derive(Debug, Clone, Copy)]
+pub struct CommandBufferInheritance {
+  pub pipeline: PipelineID,
+  pub framebuffer: FramebufferID,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DebugResource {
+  Buffer(BufferID),
+  Image(ImageID),
+  Pipeline(PipelineID),
+  Fence(FenceID),
+  CommandBuffer(CommandBufferID),
+}
+
+impl From<BufferID> for DebugResource {
+  fn from(id: BufferID) -> Self {
+    DebugResource::Buffer(id)
+  }
+}
+
+impl From<ImageID> for DebugResource {
+  fn from(id: ImageID) -> Self {
+    DebugResource::Image(id)
+  }
+}
+
+impl From<PipelineID> for DebugResource {
+  fn from(id: PipelineID) -> Self {
+    DebugResource::Pipeline(id)
+  }
+}
+
+impl From<FenceID> for DebugResource {
+  fn from(id: FenceID) -> Self {
+    DebugResource::Fence(id)
+  }
+}
+
+impl From<CommandBufferID> for DebugResource {
+  fn from(id: CommandBufferID) -> Self {
+    DebugResource::CommandBuffer(id)
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DrawMode {
+  Vertices,
+  Indexed { index_type: IndexType },
+}
+
+/// `offset` and `count` are the first vertex and vertex count of `DrawMode::Vertices` draws,
+/// `index_offset`, `index_count` and `vertex_offset` only apply to `DrawMode::Indexed` draws.
+/// `index_buffer` replaces the pipeline run's index buffer, bound at offset 0, from this draw on.
+/// `instance_buffer`, made with `BufferUsage::VERTEX`, is bound at vertex input binding 1, offset
+/// 0, for pipelines made with an `instance_input`, which read one entry of it per instance
 #[derive(Debug, Clone, Copy)]
 pub struct DrawInfo{
+  pub mode: DrawMode,
+  pub index_buffer: Option<BufferID>,
+  pub instance_buffer: Option<BufferID>,
   pub offset: u32,
   pub count: u32,
-  pub push_const_data: [u8; 128],
+  pub index_count: u32,
+  pub index_offset: u32,
+  pub vertex_offset: i32,
+  pub instance_count: u32,
+  pub first_instance: u32,
+  pub push_const_data: Option<[u8; 128]>,
+}
+
+/// An empty non indexed draw of a single instance
+impl Default for DrawInfo {
+  fn default() -> Self {
+    Self {
+      mode: DrawMode::Vertices,
+      index_buffer: None,
+      instance_buffer: None,
+      offset: 0,
+      count: 0,
+      index_count: 0,
+      index_offset: 0,
+      vertex_offset: 0,
+      instance_count: 1,
+      first_instance: 0,
+      push_const_data: None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferBarrier {
+  pub buffer: BufferID,
+  pub src_access: MemAccessType,
+  pub dst_access: MemAccessType,
+  pub offset: u64,
+  /// `u64::MAX` covers the rest of the buffer from `offset`
+  pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageBarrier {
+  pub image: ImageID,
+  pub src_access: MemAccessType,
+  pub dst_access: MemAccessType,
+  pub old_layout: ImageLayoutType,
+  pub new_layout: ImageLayoutType,
 }
 
 #[derive(Debug, Clone)]
 pub enum GPUCommands{
-  CopyBufferToBuffer{src: BufferID, dst: BufferID},
-  CopyBufferToImage{src: BufferID, dst: ImageID},
-  BlitImage{src: ImageID, dst: ImageID},
+  /// Offsets and `size` are in bytes
+  CopyBufferRegion{src: BufferID, src_offset: u64, dst: BufferID, dst_offset: u64, size: u64},
+  /// Writes `value` to every 4 bytes of the range, a `size` of `None` fills to the end of the
+  /// buffer. `offset` and `size` must be multiples of 4 and the buffer needs
+  /// `BufferUsage::COPY_DST`. Later commands in the buffer see the written data
+  FillBuffer{buffer: BufferID, offset: u64, size: Option<u64>, value: u32},
+  /// Writes `data` inline from the command buffer, at most 65536 bytes. `offset` and the length
+  /// of `data` must be multiples of 4 and the buffer needs `BufferUsage::COPY_DST`
+  UpdateBuffer{buffer: BufferID, offset: u64, data: Vec<u8>},
+  /// Fills one mip level of one array layer, cube face or 3D depth slice of `dst`
+  CopyBufferToImage{src: BufferID, dst: ImageID, layer: u32, depth_slice: u32, mip: u32},
+  CopyImageToBuffer{src: ImageID, dst: BufferID},
+  /// Copies `extent` texels between one mip level and array layer of two different images
+  CopyImageRegion{
+    src: ImageID,
+    src_offset: [i32; 3],
+    dst: ImageID,
+    dst_offset: [i32; 3],
+    extent: [u32; 3],
+    src_mip: u32,
+    dst_mip: u32,
+    src_layer: u32,
+    dst_layer: u32,
+  },
+  BlitImage{src: ImageID, dst: ImageID, filter: BlitFilter},
+  /// Clears every mip level and layer of a color image, which needs `ImageUsage::COPY_DST`
+  ClearColorImage{image: ImageID, color: [f32; 4]},
+  /// Like `ClearColorImage` for depth images, `stencil` only applies to formats with stencil
+  ClearDepthStencilImage{image: ImageID, depth: f32, stencil: u32},
+  PushConstants{pipeline: PipelineID, data: Vec<u8>, offset: u32},
+  BindVertexBuffer{buffer: BufferID, binding: u32, offset: u64},
+  BindIndexBuffer{buffer: BufferID, offset: u64, index_type: IndexType},
   RunGraphicsPipeline{
     pipeline: PipelineID,
     framebuffer: FramebufferID,
     input_set: InputSetID,
+    /// Index buffer, its index type and byte offset, bound before the draws
+    index_buffer: Option<(BufferID, IndexType, u64)>,
     draw_infos: Vec<DrawInfo>
   },
+  /// Reads `draw_count` draws from `args_buffer`, `stride` bytes apart, each laid out like
+  /// `{ vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32 }`.
+  /// More than one draw needs the `multiDrawIndirect` device feature
+  DrawIndirect{
+    pipeline: PipelineID,
+    framebuffer: FramebufferID,
+    input_set: InputSetID,
+    args_buffer: BufferID,
+    args_offset: u64,
+    draw_count: u32,
+    stride: u32,
+  },
+  /// Like `DrawIndirect`, with each draw laid out like
+  /// `{ index_count: u32, instance_count: u32, first_index: u32, vertex_offset: i32,
+  /// first_instance: u32 }`
+  DrawIndexedIndirect{
+    pipeline: PipelineID,
+    framebuffer: FramebufferID,
+    input_set: InputSetID,
+    args_buffer: BufferID,
+    args_offset: u64,
+    draw_count: u32,
+    stride: u32,
+    index_buffer: BufferID,
+    index_type: IndexType,
+  },
+  /// Runs a mesh shader pipeline with `groups_x * groups_y * groups_z` task workgroups, or mesh
+  /// workgroups when it has no task shader
+  DrawMeshTasks{
+    pipeline: PipelineID,
+    framebuffer: FramebufferID,
+    input_set: InputSetID,
+    groups_x: u32,
+    groups_y: u32,
+    groups_z: u32,
+  },
+  DispatchCompute{
+    pipeline: ComputePipelineID,
+    input_set: InputSetID,
+    groups_x: u32,
+    groups_y: u32,
+    groups_z: u32,
+  },
+  /// Reads the group counts from `args_buffer`, laid out like `{ x: u32, y: u32, z: u32 }`.
+  /// `args_offset` must be a multiple of 4 and the buffer needs `BufferUsage::INDIRECT`
+  DispatchComputeIndirect{
+    pipeline: ComputePipelineID,
+    input_set: InputSetID,
+    args_buffer: BufferID,
+    args_offset: u64,
+  },
+  /// Runs the ray generation shader `width * height * depth` times
+  TraceRays{
+    pipeline: RayTracingPipelineID,
+    input_set: InputSetID,
+    width: u32,
+    height: u32,
+    depth: u32,
+  },
+  WriteTimestamp{
+    pool: QueryPoolID,
+    index: u32,
+  },
+  ResetQueryPool{
+    pool: QueryPoolID,
+    first: u32,
+    count: u32,
+  },
+  /// Counts the pool's statistics for the commands up to the matching `EndPipelineStatsQuery`
+  BeginPipelineStatsQuery{
+    pool: QueryPoolID,
+    index: u32,
+  },
+  EndPipelineStatsQuery{
+    pool: QueryPoolID,
+    index: u32,
+  },
+  /// Overrides the framebuffer sized viewport for the graphics pipelines run after it
+  SetViewport{
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    min_depth: f32,
+    max_depth: f32,
+  },
+  /// Overrides the framebuffer sized scissor for the graphics pipelines run after it
+  SetScissor{
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+  },
+  /// Overrides the pipelines' stencil reference values for the graphics pipelines run after it
+  SetStencilReference{
+    front: u32,
+    back: u32,
+  },
+  /// Starts a debugger frame capture right before the command buffer is submitted, ignored by
+  /// backends without capture support and in secondary command buffers
+  BeginDebugCapture,
+  /// Ends the capture right after the command buffer is submitted
+  EndDebugCapture,
+  /// Opens a named, colored region shown by GPU profilers and debuggers until the matching
+  /// `EndDebugLabel`. Debug labels are ignored by backends without marker support
+  BeginDebugLabel{label: String, color: [f32; 4]},
+  EndDebugLabel,
+  /// Single marker at this point of the command buffer
+  InsertDebugLabel{label: String, color: [f32; 4]},
+  /// Overrides the pipelines' depth bias values for the graphics pipelines run after it.
+  /// Pipelines created without a depth bias ignore it
+  SetDepthBias{
+    constant: f32,
+    clamp: f32,
+    slope: f32,
+  },
+  /// Runs secondaries inside the render pass of their shared framebuffer
+  ExecuteSecondary{
+    secondaries: Vec<CommandBufferID>,
+  },
+  /// Fills every mip level from level 0 and leaves the image in the shader read only layout.
+  /// Levels past the image's own mip count are skipped
+  GenerateMipmaps{
+    image: ImageID,
+  },
+  /// Explicit barrier, images are left in `new_layout` for the commands that follow
+  PipelineBarrier{
+    src_stage: PipelineStage,
+    dst_stage: PipelineStage,
+    buffer_barriers: Vec<BufferBarrier>,
+    image_barriers: Vec<ImageBarrier>,
+  },
 }
 
 #[trait_variant::make(RenderBackend: Send)]
 pub trait LocalRenderBackend {
   fn get_swapchain_info(&self) -> SwapchainInfo;
 
+  /// Nanoseconds per timestamp tick
+  fn timestamp_period(&self) -> f32;
+
+  /// The GPU the backend runs on, with its limits and optional features
+  fn get_device_info(&self) -> (GPUInfo, DeviceLimits, DeviceFeatures);
+
+  /// One entry per memory heap, empty if the backend can't query budgets
+  fn get_memory_budget(&self) -> Vec<MemoryHeapBudget>;
+
+  /// Memory heaps of the GPU and the memory types allocated from them
+  fn get_memory_info(&self) -> MemoryInfo;
+
   fn create_buffer(
     &mut self,
     size: u64,
     usage: BufferUsage,
     memory_location: MemoryLocation
-  ) -> Result<BufferID, String>;
+  ) -> Result<BufferID, RhiError>;
+
+  /// Only buffers created with `MemoryLocation::CPUToGPU` or `CPUReadback` can be mapped, others
+  /// return an error
+  fn map_buffer(&mut self, buffer_id: BufferID) -> Result<*mut u8, RhiError>;
 
-  fn create_texture_2d(
+  fn unmap_buffer(&mut self, buffer_id: BufferID) -> Result<(), RhiError>;
+
+  fn write_buffer<T: bytemuck::Pod>(
     &mut self,
-    res: Resolution2D,
+    buffer_id: BufferID,
+    data: &[T]
+  ) -> Result<(), RhiError>;
+
+  /// Copies `data` into a GPU buffer through a staging buffer and waits for the copy to finish.
+  /// `dst` needs `BufferUsage::COPY_DST`
+  fn upload_buffer_sync(&mut self, dst: BufferID, data: &[u8]) -> Result<(), RhiError>;
+
+  fn destroy_buffer(&mut self, buffer_id: BufferID) -> Result<(), RhiError>;
+
+  /// The 64 bit GPU pointer shaders can read the buffer through, e.g. from a push constant.
+  /// `buffer_id` needs `BufferUsage::DEVICE_ADDRESS`
+  fn get_buffer_device_address(&self, buffer_id: BufferID) -> Result<u64, RhiError>;
+
+  /// Lets callers fall back to another format before `create_texture` fails
+  fn query_image_format_support(
+    &self,
     format: ImageFormat,
     usage: ImageUsage,
-    memory_location: MemoryLocation
-  ) -> Result<ImageID, String>;
+    tiling: ImageTiling,
+  ) -> FormatSupport;
+
+  /// Whether shaders can read texel buffers of `format`
+  fn query_buffer_format_support(&self, format: ImageFormat) -> bool;
+
+  fn create_texture(&mut self, desc: TextureDesc) -> Result<ImageID, RhiError>;
+
+  /// 2D view of a single face of a cube texture, with all its mip levels
+  fn create_cube_face_view(&mut self, image: ImageID, face: u32) -> Result<ImageViewID, RhiError>;
+
+  /// View of a mip and layer range of `image`. A full 6 layer range of a cube texture is viewed
+  /// as a cube, other multi layer ranges as a 2D array
+  fn create_image_view(
+    &mut self,
+    image: ImageID,
+    base_mip: u32,
+    mip_count: u32,
+    base_layer: u32,
+    layer_count: u32,
+  ) -> Result<ImageViewID, RhiError>;
+
+  fn destroy_image_view(&mut self, image_view_id: ImageViewID) -> Result<(), RhiError>;
+
+  /// Creates a sampled texture from tightly packed texels and waits for the upload.
+  /// The texture is left in the shader read only layout
+  fn upload_texture(
+    &mut self,
+    data: &[u8],
+    res: Resolution2D,
+    format: ImageFormat,
+  ) -> Result<ImageID, RhiError>;
+
+  /// Swapchain images can't be destroyed. Views made from the image are destroyed with it
+  fn destroy_image(&mut self, image_id: ImageID) -> Result<(), RhiError>;
+
+  /// Reads a SPIR-V file into a shader module that any number of pipelines can share
+  fn create_shader(&mut self, path: PathBuf) -> Result<ShaderID, RhiError>;
+
+  /// `create_shader` with the file read asynchronously
+  async fn load_shader(&mut self, path: PathBuf) -> Result<ShaderID, RhiError>;
+
+  /// Pipelines made from the shader keep working after it is destroyed
+  fn destroy_shader(&mut self, shader_id: ShaderID) -> Result<(), RhiError>;
 
   async fn create_graphics_pipeline(
     &mut self,
-    raster_style: RasterStyle,
-    color_attachment_formats: Vec<ImageFormat>,
-    depth_attachment_formats: Option<ImageFormat>,
+    desc: GraphicsPipelineDesc
+  ) -> Result<PipelineID, RhiError>;
+
+  fn destroy_pipeline(&mut self, pipeline_id: PipelineID) -> Result<(), RhiError>;
+
+  async fn create_compute_pipeline(
+    &mut self,
+    shader: PathBuf,
     max_buffer_count: u32,
     max_texture_count: u32,
-    vertex_shader: PathBuf,
-    fragment_shader: PathBuf,
-  ) -> Result<PipelineID, String>;
+    storage_image_count: u32,
+  ) -> Result<ComputePipelineID, RhiError>;
+
+  fn create_ray_tracing_pipeline(
+    &mut self,
+    desc: RayTracingPipelineDesc
+  ) -> Result<RayTracingPipelineID, RhiError>;
+
+  fn destroy_ray_tracing_pipeline(
+    &mut self,
+    pipeline_id: RayTracingPipelineID
+  ) -> Result<(), RhiError>;
+
+  /// Builds the acceleration structure right away and waits for the build to finish
+  fn create_blas(&mut self, desc: BLASDesc) -> Result<AccelStructID, RhiError>;
+
+  /// Like `create_blas`. The instanced BLASes have to outlive the TLAS
+  fn create_tlas(&mut self, instances: Vec<TLASInstance>) -> Result<AccelStructID, RhiError>;
+
+  fn destroy_accel_struct(&mut self, accel_struct: AccelStructID) -> Result<(), RhiError>;
 
   fn create_frame_buffer(
     &mut self,
     pipeline_id: PipelineID,
     color_attachments: Vec<ImageID>,
     depth_attachment: Option<ImageID>,
-  ) -> Result<FramebufferID, String>;
+    resolve_attachments: Vec<ImageID>,
+  ) -> Result<FramebufferID, RhiError>;
+
+  fn destroy_framebuffer(&mut self, framebuffer_id: FramebufferID) -> Result<(), RhiError>;
 
-  fn create_input_set(&mut self, pipeline_id: PipelineID) -> Result<InputSetID, String>;
+  fn create_input_set(&mut self, pipeline_id: PipelineID) -> Result<InputSetID, RhiError>;
 
+  fn create_compute_input_set(
+    &mut self,
+    pipeline_id: ComputePipelineID
+  ) -> Result<InputSetID, RhiError>;
+
+  fn create_ray_tracing_input_set(
+    &mut self,
+    pipeline_id: RayTracingPipelineID
+  ) -> Result<InputSetID, RhiError>;
+
+  /// `buffers` are bound as storage buffers, `uniform_buffers` as uniform buffers
   fn update_input_set(
     &mut self,
     input_set: InputSetID,
     buffers: Vec<BufferID>,
-    textures: Vec<ImageID>
-  ) -> Result<(), String>;
+    uniform_buffers: Vec<BufferID>,
+    textures: Vec<(ImageViewID, SamplerID)>,
+    storage_images: Vec<ImageID>,
+  ) -> Result<(), RhiError>;
+
+  /// Rebinds the storage buffer at index `binding` of the set's buffer array
+  fn update_input_set_buffer(
+    &mut self,
+    set: InputSetID,
+    binding: u32,
+    buffer: BufferID
+  ) -> Result<(), RhiError>;
+
+  /// Rebinds the texture at index `binding` of the set's texture array
+  fn update_input_set_texture(
+    &mut self,
+    set: InputSetID,
+    binding: u32,
+    image_view: ImageViewID,
+    sampler: SamplerID
+  ) -> Result<(), RhiError>;
+
+  /// Writes one slot of a bindless texture array, sets may be in use by submitted commands.
+  /// Fails when the backend doesn't use bindless textures
+  fn register_texture_bindless(
+    &mut self,
+    set: InputSetID,
+    slot: u32,
+    image_view: ImageViewID,
+    sampler: SamplerID
+  ) -> Result<(), RhiError>;
+
+  fn destroy_input_set(&mut self, input_set: InputSetID) -> Result<(), RhiError>;
+
+  fn create_sampler(&mut self, desc: SamplerDesc) -> Result<SamplerID, RhiError>;
+
+  fn destroy_sampler(&mut self, sampler_id: SamplerID) -> Result<(), RhiError>;
+
+  fn create_fence(&mut self, signaled: bool) -> Result<FenceID, RhiError>;
+
+  async fn wait_for_fence(&self, fence_id: FenceID) -> Result<(), RhiError>;
+
+  fn reset_fence(&self, fence_id: FenceID) -> Result<(), RhiError>;
 
-  fn create_fence(&mut self, signaled: bool) -> Result<FenceID, String>;
+  fn destroy_fence(&mut self, fence_id: FenceID) -> Result<(), RhiError>;
 
-  async fn wait_for_fence(&self, fence_id: FenceID) -> Result<(), String>;
+  fn create_semaphore(&mut self) -> Result<SemaphoreID, RhiError>;
 
-  fn create_command_buffer(&mut self) -> Result<CommandBufferID, String>;
+  fn destroy_semaphore(&mut self, semaphore_id: SemaphoreID) -> Result<(), RhiError>;
+
+  fn create_timestamp_query_pool(&mut self, count: u32) -> Result<QueryPoolID, RhiError>;
+
+  fn destroy_query_pool(&mut self, pool: QueryPoolID) -> Result<(), RhiError>;
+
+  /// Blocks until all the requested timestamps are available
+  fn read_timestamps(&self, pool: QueryPoolID, first: u32, count: u32) -> Result<Vec<u64>, RhiError>;
+
+  /// Needs the `pipelineStatisticsQuery` device feature
+  fn create_pipeline_stats_query_pool(
+    &mut self,
+    count: u32,
+    stats: PipelineStatFlags
+  ) -> Result<QueryPoolID, RhiError>;
+
+  /// Blocks until the query's results are available
+  fn read_pipeline_stats(
+    &self,
+    pool: QueryPoolID,
+    index: u32
+  ) -> Result<PipelineStatsResult, RhiError>;
+
+  fn create_command_buffer(&mut self) -> Result<CommandBufferID, RhiError>;
+
+  /// Secondary command buffers only record draws, run them with `GPUCommands::ExecuteSecondary`
+  fn create_secondary_command_buffer(
+    &mut self,
+    inheritance: CommandBufferInheritance
+  ) -> Result<CommandBufferID, RhiError>;
+
+  /// `wait_before_reset` waits on the fence the command buffer was last run with
+  fn reset_command_buffer(
+    &mut self,
+    command_buffer: CommandBufferID,
+    wait_before_reset: bool
+  ) -> Result<(), RhiError>;
 
   fn compile_commands(
     &self,
     command_buffer: CommandBufferID,
     commands: Vec<GPUCommands>
-  ) -> Result<(), String>;
+  ) -> Result<(), RhiError>;
+
+  fn run_commands(
+    &mut self,
+    command_buffer: CommandBufferID,
+    fence_id: FenceID,
+    wait_semaphores: Vec<SemaphoreID>,
+    signal_semaphores: Vec<SemaphoreID>,
+  ) -> Result<(), RhiError>;
 
-  fn run_commands(&self, command_buffer: CommandBufferID, fence_id: FenceID) -> Result<(), String>;
+  /// Blocks until all work on the device is done
+  fn device_wait_idle(&self) -> Result<(), RhiError>;
+
+  /// Blocks until all work submitted through `run_commands` is done
+  fn queue_wait_idle(&self) -> Result<(), RhiError>;
 
   fn get_swapchain_images(&self) -> Vec<ImageID>;
 
-  fn present_swapchain_image(&self, id:u32) -> Result<bool, String>;
+  /// Recreates the swapchain to match the current surface extent, `width` and `height` are only
+  /// used when the surface leaves the extent to the swapchain.
+  /// Framebuffers made from the old swapchain images are invalid after this and need rebuilding.
+  fn rebuild_swapchain(&mut self, width: u32, height: u32) -> Result<(), RhiError>;
+
+  /// Returns true when the image was presented but the swapchain is suboptimal and should be
+  /// rebuilt. Fails with `RhiError::SwapchainOutOfDate` when the swapchain is out of date
+  fn present_swapchain_image(
+    &self,
+    image_index: u32,
+    wait_semaphores: &[SemaphoreID]
+  ) -> Result<bool, RhiError>;
+
+  fn acquire_present_image(
+    &self,
+    fence_id: FenceID,
+    signal_semaphore: Option<SemaphoreID>
+  ) -> Result<u32, RhiError>;
 
-  fn acquire_present_image(&self, fence_id: FenceID) -> Result<u32, String>;
+  /// Names show up in graphics debuggers. Does nothing when the backend has no debug support
+  fn set_debug_name(&self, resource: impl Into<DebugResource>, name: &str) -> Result<(), RhiError>;
 }
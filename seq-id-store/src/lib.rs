@@ -1,53 +1,184 @@
 use std::collections::HashMap;
 
+const SLOT_BITS: u32 = 16;
+const SLOT_MASK: u32 = (1 << SLOT_BITS) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreError {
+  /// The store already holds `capacity` items
+  CapacityExceeded,
+  /// The ID's item was removed, its slot may hold a newer item
+  StaleId,
+  NotFound,
+}
+
+impl std::fmt::Display for StoreError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      StoreError::CapacityExceeded => write!(f, "capacity exceeded"),
+      StoreError::StaleId => write!(f, "stale item id"),
+      StoreError::NotFound => write!(f, "item not found"),
+    }
+  }
+}
+
+impl std::error::Error for StoreError {}
+
+/// IDs pack a slot index in the low 16 bits and the slot's generation in the high 16 bits, so
+/// IDs of removed objects stop resolving once their slot is reused
 pub struct SequentialIDStore<T>{
+  capacity: u32,
   max_id: u32,
   store: HashMap<u32, T>,
   freed: Vec<u32>,
+  generations: Vec<u32>,
 }
 
 impl<T> SequentialIDStore<T>{
+  /// At most `capacity` items can be stored at once, capped by the 16 bit slot index
   pub fn new(capacity: u32) -> Self {
+    let capacity = capacity.min(SLOT_MASK + 1);
     Self {
+      capacity,
       max_id: 0,
       store: HashMap::with_capacity(capacity as _),
-      freed: Vec::with_capacity(capacity as _)
+      freed: Vec::with_capacity(capacity as _),
+      generations: Vec::with_capacity(capacity as _),
     }
   }
 
-  pub fn add_obj(&mut self, obj: T) -> Result<u32, &'static str>{
-    match self.freed.pop() {
-      Some(id) => {
-        self.store.insert(id, obj);
-        Ok(id)
-      },
+  fn make_id(&self, slot: u32) -> u32 {
+    (self.generations[slot as usize] << SLOT_BITS) | slot
+  }
+
+  fn check_generation(&self, id: u32) -> Result<(), StoreError> {
+    match self.generations.get((id & SLOT_MASK) as usize) {
+      Some(generation) if *generation == id >> SLOT_BITS => Ok(()),
+      Some(_) => Err(StoreError::StaleId),
+      None => Err(StoreError::NotFound),
+    }
+  }
+
+  /// `capacity` is a hard cap, adding past it fails with `CapacityExceeded` instead of growing
+  pub fn add_obj(&mut self, obj: T) -> Result<u32, StoreError>{
+    if self.store.len() >= self.capacity as usize {
+      return Err(StoreError::CapacityExceeded);
+    }
+    let slot = match self.freed.pop() {
+      Some(slot) => slot,
       None => {
-        if self.max_id == u32::MAX {
-          Err("max items reached")
-        } else {
-          self.max_id += 1;
-          self.store.insert(self.max_id - 1, obj);
-          Ok(self.max_id - 1)
+        self.max_id += 1;
+        if self.generations.len() < self.max_id as usize {
+          self.generations.push(0);
         }
+        self.max_id - 1
       },
-    }
+    };
+    let id = self.make_id(slot);
+    self.store.insert(id, obj);
+    Ok(id)
   }
 
-  pub fn remove_obj(&mut self, id: u32) -> Result<T, &'static str>{
-    let obj = self.store.remove(&id).ok_or("item not found")?;
-    self.freed.push(id);
+  pub fn remove_obj(&mut self, id: u32) -> Result<T, StoreError>{
+    self.check_generation(id)?;
+    let obj = self.store.remove(&id).ok_or(StoreError::NotFound)?;
+    let slot = id & SLOT_MASK;
+    let generation = &mut self.generations[slot as usize];
+    *generation = (*generation + 1) & SLOT_MASK;
+    self.freed.push(slot);
     Ok(obj)
   }
 
-  pub fn get_obj(&self, id: u32) -> Result<&T, &'static str>{
-    self.store.get(&id).ok_or("item not found")
+  pub fn get_obj(&self, id: u32) -> Result<&T, StoreError>{
+    self.check_generation(id)?;
+    self.store.get(&id).ok_or(StoreError::NotFound)
   }
 
-  pub fn get_obj_mut(&mut self, id: u32) -> Result<&mut T, &'static str>{
-    self.store.get_mut(&id).ok_or("item not found")
+  pub fn get_obj_mut(&mut self, id: u32) -> Result<&mut T, StoreError>{
+    self.check_generation(id)?;
+    self.store.get_mut(&id).ok_or(StoreError::NotFound)
   }
 
   pub fn get_all(&self) -> &HashMap<u32, T> {
     &self.store
   }
-}
\ No newline at end of file
+
+  pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+    self.store.iter().map(|(id, obj)| (*id, obj))
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
+    self.store.iter_mut().map(|(id, obj)| (*id, obj))
+  }
+
+  pub fn capacity(&self) -> u32 {
+    self.capacity
+  }
+
+  pub fn len(&self) -> usize {
+    self.store.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.store.is_empty()
+  }
+
+  pub fn contains(&self, id: u32) -> bool {
+    self.store.contains_key(&id)
+  }
+
+  /// Removes every item and resets ID allocation. Slot generations are kept, so IDs handed out
+  /// before the clear don't alias new items
+  pub fn clear(&mut self) -> Vec<T> {
+    for slot in self.store.keys().map(|id| id & SLOT_MASK) {
+      let generation = &mut self.generations[slot as usize];
+      *generation = (*generation + 1) & SLOT_MASK;
+    }
+    self.max_id = 0;
+    self.freed.clear();
+    self.store.drain().map(|(_, obj)| obj).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn capacity_is_a_hard_cap() {
+    let mut store = SequentialIDStore::new(2);
+    let first = store.add_obj('a').unwrap();
+    store.add_obj('b').unwrap();
+    assert_eq!(store.add_obj('c'), Err(StoreError::CapacityExceeded));
+    assert_eq!(store.remove_obj(first), Ok('a'));
+    assert!(store.add_obj('c').is_ok());
+    assert_eq!(store.len(), 2);
+  }
+
+  #[test]
+  fn capacity_is_capped_by_slot_bits() {
+    let store = SequentialIDStore::<()>::new(u32::MAX);
+    assert_eq!(store.capacity(), SLOT_MASK + 1);
+  }
+
+  #[test]
+  fn reused_slots_reject_stale_ids() {
+    let mut store = SequentialIDStore::new(4);
+    let old = store.add_obj(1).unwrap();
+    store.remove_obj(old).unwrap();
+    let new = store.add_obj(2).unwrap();
+    assert_eq!(old & SLOT_MASK, new & SLOT_MASK);
+    assert_eq!(store.get_obj(old), Err(StoreError::StaleId));
+    assert_eq!(store.get_obj(new), Ok(&2));
+  }
+
+  #[test]
+  fn clear_keeps_old_ids_stale() {
+    let mut store = SequentialIDStore::new(4);
+    let old = store.add_obj(1).unwrap();
+    assert_eq!(store.clear(), vec![1]);
+    let new = store.add_obj(2).unwrap();
+    assert_eq!(store.get_obj(old), Err(StoreError::StaleId));
+    assert_eq!(store.get_obj(new), Ok(&2));
+  }
+}
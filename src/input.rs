@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta};
+use winit::keyboard::Key;
+
+/// Pixel scroll deltas are converted to lines at this rate
+const PIXELS_PER_SCROLL_LINE: f64 = 20.0;
+
+/// Window input collected between two `flush` calls
+#[derive(Default)]
+pub struct InputAggregator {
+  held_keys: HashSet<Key>,
+  just_pressed_keys: HashSet<Key>,
+  held_buttons: HashSet<MouseButton>,
+  mouse_delta: (f64, f64),
+  /// In lines
+  scroll_delta: (f64, f64),
+}
+
+impl InputAggregator {
+  pub fn update_key_pressed(&mut self, key: Key) {
+    // key repeats of a held key aren't new presses
+    if self.held_keys.insert(key.clone()) {
+      self.just_pressed_keys.insert(key);
+    }
+  }
+
+  /// A key released before the next `flush` still counts as just pressed until then
+  pub fn update_key_released(&mut self, key: Key) {
+    self.held_keys.remove(&key);
+  }
+
+  pub fn update_mouse_moved(&mut self, delta: (f64, f64)) {
+    self.mouse_delta.0 += delta.0;
+    self.mouse_delta.1 += delta.1;
+  }
+
+  pub fn update_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+    match state {
+      ElementState::Pressed => {
+        self.held_buttons.insert(button);
+      }
+      ElementState::Released => {
+        self.held_buttons.remove(&button);
+      }
+    }
+  }
+
+  pub fn update_scroll(&mut self, delta: MouseScrollDelta) {
+    let (x, y) = match delta {
+      MouseScrollDelta::LineDelta(x, y) => (x as f64, y as f64),
+      MouseScrollDelta::PixelDelta(pos) => {
+        (pos.x / PIXELS_PER_SCROLL_LINE, pos.y / PIXELS_PER_SCROLL_LINE)
+      }
+    };
+    self.scroll_delta.0 += x;
+    self.scroll_delta.1 += y;
+  }
+
+  /// Pressed since the last `flush`, even if already released again
+  pub fn is_key_just_pressed(&self, key: &Key) -> bool {
+    self.just_pressed_keys.contains(key)
+  }
+
+  /// Call once per frame after the input was read: just pressed keys are forgotten and the mouse
+  /// and scroll deltas restart from zero
+  pub fn flush(&mut self) {
+    self.just_pressed_keys.clear();
+    self.mouse_delta = (0.0, 0.0);
+    self.scroll_delta = (0.0, 0.0);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn key(c: &str) -> Key {
+    Key::Character(c.into())
+  }
+
+  #[test]
+  fn press_then_release_in_one_frame() {
+    let mut input = InputAggregator::default();
+    input.update_key_pressed(key("f"));
+    input.update_key_released(key("f"));
+    assert!(input.is_key_just_pressed(&key("f")));
+    assert!(!input.held_keys.contains(&key("f")));
+    input.flush();
+    assert!(!input.is_key_just_pressed(&key("f")));
+  }
+
+  #[test]
+  fn repeats_of_a_held_key_are_not_new_presses() {
+    let mut input = InputAggregator::default();
+    input.update_key_pressed(key("w"));
+    input.flush();
+    input.update_key_pressed(key("w"));
+    assert!(!input.is_key_just_pressed(&key("w")));
+    assert!(input.held_keys.contains(&key("w")));
+  }
+
+  #[test]
+  fn flush_resets_deltas() {
+    let mut input = InputAggregator::default();
+    input.update_mouse_moved((1.0, 2.0));
+    input.update_mouse_moved((3.0, -1.0));
+    input.update_scroll(MouseScrollDelta::LineDelta(0.0, 2.0));
+    assert_eq!(input.mouse_delta, (4.0, 1.0));
+    assert_eq!(input.scroll_delta, (0.0, 2.0));
+    input.flush();
+    assert_eq!(input.mouse_delta, (0.0, 0.0));
+    assert_eq!(input.scroll_delta, (0.0, 0.0));
+  }
+}
@@ -1,5 +1,10 @@
+mod input;
 mod winit_wrapper;
 
-fn main() {
-    println!("Hello, world!");
+use winit::event_loop::EventLoop;
+
+fn main() -> Result<(), String> {
+  let event_loop = EventLoop::new().map_err(|e| format!("at event loop create: {e}"))?;
+  let mut app = winit_wrapper::AppActivity::new()?;
+  event_loop.run_app(&mut app).map_err(|e| format!("at event loop run: {e}"))
 }
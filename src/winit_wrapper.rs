@@ -1,30 +1,110 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::input::InputAggregator;
+use renderer::Renderer;
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+use vulkan_rhi_backend::{VulkanBackend, VulkanBackendConfig};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::dpi::PhysicalSize;
+use winit::event::{DeviceEvent, DeviceId, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
-use winit::window::{Window, WindowAttributes, WindowId};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::{Fullscreen, Icon, Window, WindowAttributes, WindowId};
 
 static WINDOW_ICON_BYTES: &[u8] = include_bytes!("../assets/icon.ico");
+static PIPELINE_CACHE_FILE: &str = "plind_pipeline_cache.bin";
+#[cfg(feature = "hot-reload")]
+static SHADER_DIR: &str = "assets/shaders";
+
+/// Decodes the largest image in `WINDOW_ICON_BYTES`
+fn load_window_icon() -> Result<Icon, String> {
+  let icon_dir = ico::IconDir::read(std::io::Cursor::new(WINDOW_ICON_BYTES))
+    .map_err(|e| format!("at icon read: {e}"))?;
+  let entry = icon_dir
+    .entries()
+    .iter()
+    .max_by_key(|x| x.width())
+    .ok_or_else(|| "at icon read: icon has no images".to_string())?;
+  let image = entry.decode().map_err(|e| format!("at icon decode: {e}"))?;
+  Icon::from_rgba(image.rgba_data().to_vec(), image.width(), image.height())
+    .map_err(|e| format!("at icon create: {e}"))
+}
 
 pub struct AppActivity {
+  // declared before `window` so the surface is destroyed before the window it was made from
+  renderer: Option<Renderer<VulkanBackend>>,
+  /// The renderer's backend, for the shader reloads between frames
+  #[cfg(feature = "hot-reload")]
+  backend: Option<Arc<Mutex<VulkanBackend>>>,
   window: Option<Window>,
+  /// Drives the renderer's futures
+  runtime: Runtime,
+  input_aggregator: InputAggregator,
+  /// Set when the swapchain was rebuilt, the next redraw is skipped
+  skip_frame: bool,
+  fullscreen_key: Key,
+  exit_fullscreen_on_focus_loss: bool,
 }
 
 impl AppActivity {
   pub fn new() -> Result<Self, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+      .build()
+      .map_err(|e| format!("at tokio runtime create: {e}"))?;
     Ok(Self {
+      renderer: None,
+      #[cfg(feature = "hot-reload")]
+      backend: None,
       window: None,
+      runtime,
+      input_aggregator: InputAggregator::default(),
+      skip_frame: false,
+      fullscreen_key: Key::Named(NamedKey::F11),
+      exit_fullscreen_on_focus_loss: false,
     })
   }
+
+  /// Switches between windowed and borderless fullscreen on the current monitor
+  fn toggle_fullscreen(&mut self) {
+    let Some(window) = self.window.as_ref() else {
+      return;
+    };
+    match window.fullscreen() {
+      None => window.set_fullscreen(Some(Fullscreen::Borderless(None))),
+      Some(_) => window.set_fullscreen(None),
+    }
+    let size = window.inner_size();
+    self.rebuild_swapchain(size);
+  }
+
+  /// Minimized windows report a zero size, their swapchain is kept until they're restored
+  fn rebuild_swapchain(&mut self, size: PhysicalSize<u32>) {
+    let Some(renderer) = self.renderer.as_mut() else {
+      return;
+    };
+    if size.width == 0 || size.height == 0 {
+      return;
+    }
+    let _ = renderer
+      .rebuild_swapchain(size.width, size.height)
+      .inspect_err(|e| eprintln!("error rebuilding swapchain: {e}"));
+    self.skip_frame = true;
+  }
 }
 
 impl ApplicationHandler for AppActivity {
   fn resumed(&mut self, event_loop: &ActiveEventLoop) {
     if self.window.is_none() {
+      let icon = load_window_icon()
+        .inspect_err(|e| eprintln!("error loading window icon: {e}"))
+        .ok();
       let Ok(w) = event_loop
         .create_window(
           WindowAttributes::default()
             //.with_taskbar_icon(icon.clone())
-            //.with_window_icon(icon)
+            .with_window_icon(icon)
             .with_title("Plint Engine"),
         )
         .inspect_err(|e| eprintln!("error creating window: {e}"))
@@ -32,6 +112,35 @@ impl ApplicationHandler for AppActivity {
         event_loop.exit();
         return;
       };
+      let backend_config = VulkanBackendConfig {
+        pipeline_cache_path: Some(PathBuf::from(PIPELINE_CACHE_FILE)),
+        ..Default::default()
+      };
+      let Ok(backend) = VulkanBackend::new(&w, backend_config)
+        .inspect_err(|e| eprintln!("error creating render backend: {e}"))
+      else {
+        event_loop.exit();
+        return;
+      };
+      let backend = Arc::new(Mutex::new(backend));
+      #[cfg(feature = "hot-reload")]
+      {
+        let _ = backend
+          .blocking_lock()
+          .watch_shader_dir(PathBuf::from(SHADER_DIR))
+          .inspect_err(|e| eprintln!("error watching shaders: {e}"));
+        self.backend = Some(backend.clone());
+      }
+      let Ok(renderer) = self
+        .runtime
+        .block_on(Renderer::new(backend))
+        .inspect_err(|e| eprintln!("error creating renderer: {e}"))
+      else {
+        event_loop.exit();
+        return;
+      };
+      self.renderer = Some(renderer);
+      self.window = Some(w);
     }
   }
 
@@ -44,7 +153,7 @@ impl ApplicationHandler for AppActivity {
     // println!("event: {event:?}");
     match event {
       WindowEvent::ActivationTokenDone { .. } => {}
-      WindowEvent::Resized(_) => {}
+      WindowEvent::Resized(new_size) => self.rebuild_swapchain(new_size),
       WindowEvent::Moved(_) => {}
       WindowEvent::CloseRequested => {
         // #[cfg(target_os = "macos")]
@@ -55,13 +164,18 @@ impl ApplicationHandler for AppActivity {
       WindowEvent::DroppedFile(_) => {}
       WindowEvent::HoveredFile(_) => {}
       WindowEvent::HoveredFileCancelled => {}
-      WindowEvent::Focused(_) => {}
-      WindowEvent::KeyboardInput { device_id, event, is_synthetic } => match event.state {
+      WindowEvent::Focused(focused) => {
+        let is_fullscreen = self.window.as_ref().is_some_and(|w| w.fullscreen().is_some());
+        if !focused && is_fullscreen && self.exit_fullscreen_on_focus_loss {
+          self.toggle_fullscreen();
+        }
+      }
+      WindowEvent::KeyboardInput { event, .. } => match event.state {
         winit::event::ElementState::Pressed => {
-          //self.input_aggregator.update_key_pressed(event.key_without_modifiers());
+          self.input_aggregator.update_key_pressed(event.logical_key);
         }
         winit::event::ElementState::Released => {
-          //self.input_aggregator.update_key_released(event.key_without_modifiers());
+          self.input_aggregator.update_key_released(event.logical_key);
         }
       },
       WindowEvent::ModifiersChanged(_) => {}
@@ -69,8 +183,10 @@ impl ApplicationHandler for AppActivity {
       WindowEvent::CursorMoved { .. } => {}
       WindowEvent::CursorEntered { .. } => {}
       WindowEvent::CursorLeft { .. } => {}
-      WindowEvent::MouseWheel { .. } => {}
-      WindowEvent::MouseInput { .. } => {}
+      WindowEvent::MouseWheel { delta, .. } => self.input_aggregator.update_scroll(delta),
+      WindowEvent::MouseInput { state, button, .. } => {
+        self.input_aggregator.update_mouse_button(button, state)
+      }
       WindowEvent::PinchGesture { .. } => {}
       WindowEvent::PanGesture { .. } => {}
       WindowEvent::DoubleTapGesture { .. } => {}
@@ -78,12 +194,55 @@ impl ApplicationHandler for AppActivity {
       WindowEvent::TouchpadPressure { .. } => {}
       WindowEvent::AxisMotion { .. } => {}
       WindowEvent::Touch(_) => {}
-      WindowEvent::ScaleFactorChanged { .. } => {}
+      WindowEvent::ScaleFactorChanged { .. } => {
+        // the swapchain is sized in physical pixels, which change with the scale factor
+        if let Some(size) = self.window.as_ref().map(|w| w.inner_size()) {
+          self.rebuild_swapchain(size);
+        }
+      }
       WindowEvent::ThemeChanged(_) => {}
       WindowEvent::Occluded(_) => {}
-      WindowEvent::RedrawRequested => {}
+      WindowEvent::RedrawRequested => {
+        // the frame after a swapchain rebuild only clears the flag
+        if std::mem::take(&mut self.skip_frame) {
+          return;
+        }
+        #[cfg(feature = "hot-reload")]
+        if let Some(backend) = self.backend.as_ref() {
+          let _ = backend
+            .blocking_lock()
+            .reload_changed_shaders()
+            .inspect_err(|e| eprintln!("error reloading shaders: {e}"));
+        }
+        let Some(renderer) = self.renderer.as_mut() else {
+          return;
+        };
+        let _ = self
+          .runtime
+          .block_on(renderer.render_frame())
+          .inspect_err(|e| eprintln!("error rendering frame: {e}"));
+      }
     }
   }
 
-  fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {}
+  fn device_event(
+    &mut self,
+    _event_loop: &ActiveEventLoop,
+    _device_id: DeviceId,
+    event: DeviceEvent,
+  ) {
+    if let DeviceEvent::MouseMotion { delta } = event {
+      self.input_aggregator.update_mouse_moved(delta);
+    }
+  }
+
+  fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    if self.input_aggregator.is_key_just_pressed(&self.fullscreen_key) {
+      self.toggle_fullscreen();
+    }
+    self.input_aggregator.flush();
+    if let Some(window) = self.window.as_ref() {
+      window.request_redraw();
+    }
+  }
 }
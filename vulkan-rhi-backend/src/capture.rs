@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+use crate::VulkanBackend;
+
+pub(crate) struct RenderDocState {
+  /// The newest API version the `renderdoc` crate wraps
+  api: renderdoc::RenderDoc<renderdoc::V141>,
+  /// Whether each command buffer was last compiled with a `BeginDebugCapture` and an
+  /// `EndDebugCapture`
+  capture_marks: HashMap<u32, (bool, bool)>,
+}
+
+/// `None` unless the app runs under RenderDoc
+pub(crate) fn load_renderdoc() -> Option<Mutex<RenderDocState>> {
+  renderdoc::RenderDoc::new()
+    .inspect_err(|e| log::info!("RenderDoc API not available: {e}"))
+    .ok()
+    .map(|api| Mutex::new(RenderDocState { api, capture_marks: HashMap::new() }))
+}
+
+impl VulkanBackend {
+  fn with_renderdoc<T: Default>(&self, f: impl FnOnce(&mut RenderDocState) -> T) -> T {
+    match self.renderdoc.as_ref().map(|x| x.lock()) {
+      Some(Ok(mut state)) => f(&mut state),
+      _ => T::default(),
+    }
+  }
+
+  /// Captures the next presented frame. Like the other capture calls, does nothing unless the
+  /// app runs under RenderDoc
+  pub fn trigger_renderdoc_capture(&self) {
+    self.with_renderdoc(|x| x.api.trigger_capture())
+  }
+
+  /// Captures every API call up to `end_renderdoc_capture`
+  pub fn start_renderdoc_capture(&self) {
+    self.with_renderdoc(|x| {
+      x.api.start_frame_capture(std::ptr::null::<c_void>(), std::ptr::null())
+    })
+  }
+
+  pub fn end_renderdoc_capture(&self) {
+    self.with_renderdoc(|x| x.api.end_frame_capture(std::ptr::null::<c_void>(), std::ptr::null()))
+  }
+
+  pub(crate) fn set_capture_marks(&self, command_buffer: u32, commands: &[rhi::GPUCommands]) {
+    let begins = commands.iter().any(|x| matches!(x, rhi::GPUCommands::BeginDebugCapture));
+    let ends = commands.iter().any(|x| matches!(x, rhi::GPUCommands::EndDebugCapture));
+    self.with_renderdoc(|x| {
+      x.capture_marks.insert(command_buffer, (begins, ends));
+    })
+  }
+
+  pub(crate) fn capture_marks(&self, command_buffer: u32) -> (bool, bool) {
+    self.with_renderdoc(|x| x.capture_marks.get(&command_buffer).cloned().unwrap_or_default())
+  }
+}
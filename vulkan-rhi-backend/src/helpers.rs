@@ -1,7 +1,12 @@
+use std::ffi::CStr;
+
 use ash::{ext, khr, vk};
 
-pub unsafe fn create_vk_instance() -> Result<(ash::Entry, ash::Instance), String> {
-  let ash_entry = ash::Entry::load().map_err(|e| format!("at VK load: {e}"))?;
+use crate::translate_vk_error;
+
+pub unsafe fn create_vk_instance() -> Result<(ash::Entry, ash::Instance), rhi::RhiError> {
+  let ash_entry = ash::Entry::load()
+    .map_err(|e| rhi::RhiError::BackendError(format!("at VK load: {e}")))?;
   let layers = [
     #[cfg(debug_assertions)]
     c"VK_LAYER_KHRONOS_validation".as_ptr(),
@@ -30,7 +35,8 @@ pub unsafe fn create_vk_instance() -> Result<(ash::Entry, ash::Instance), String
     .application_version(0)
     .engine_name(c"Plind Engine")
     .engine_version(0)
-    .api_version(vk::API_VERSION_1_0);
+    // 1.1 for VK_KHR_spirv_1_4, which mesh shaders need. Everything newer is an extension
+    .api_version(vk::API_VERSION_1_1);
 
   #[cfg(target_os = "macos")]
   let vk_instance_create_info = vk::InstanceCreateInfo::default()
@@ -47,20 +53,355 @@ pub unsafe fn create_vk_instance() -> Result<(ash::Entry, ash::Instance), String
 
   let ash_instance = ash_entry
     .create_instance(&vk_instance_create_info, None)
-    .map_err(|e| format!("at instance create: {e}"))?;
+    .map_err(|e| translate_vk_error("at instance create", e))?;
   Ok((ash_entry, ash_instance))
 }
 
+/// `GPUInfo::id` is the GPU's index in `enumerate_physical_devices`
+pub unsafe fn list_gpus(ash_instance: &ash::Instance) -> Result<Vec<rhi::GPUInfo>, rhi::RhiError> {
+  let vk_gpus = ash_instance
+    .enumerate_physical_devices()
+    .map_err(|e| translate_vk_error("at getting GPU list", e))?;
+  let gpu_infos = vk_gpus
+    .into_iter()
+    .enumerate()
+    .map(|(i, gpu)| {
+      let props = ash_instance.get_physical_device_properties(gpu);
+      rhi::GPUInfo {
+        id: i as u32,
+        name: props
+          .device_name_as_c_str()
+          .unwrap_or(c"Unknown Device")
+          .to_string_lossy()
+          .to_string(),
+        integrated: props.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU,
+      }
+    })
+    .collect();
+  Ok(gpu_infos)
+}
+
+#[cfg(debug_assertions)]
+unsafe extern "system" fn debug_messenger_callback(
+  severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+  message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+  callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+  _user_data: *mut std::ffi::c_void,
+) -> vk::Bool32 {
+  let message = callback_data
+    .as_ref()
+    .and_then(|data| data.message_as_c_str())
+    .map(|m| m.to_string_lossy())
+    .unwrap_or_default();
+  if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+    log::error!("[vk {message_types:?}] {message}");
+  } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+    log::warn!("[vk {message_types:?}] {message}");
+  } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+    log::info!("[vk {message_types:?}] {message}");
+  } else {
+    log::debug!("[vk {message_types:?}] {message}");
+  }
+  vk::FALSE
+}
+
+#[cfg(debug_assertions)]
+pub unsafe fn create_debug_messenger(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+) -> Result<(ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT), rhi::RhiError> {
+  let debug_utils_instance = ext::debug_utils::Instance::new(ash_entry, ash_instance);
+  let debug_messenger = debug_utils_instance
+    .create_debug_utils_messenger(
+      &vk::DebugUtilsMessengerCreateInfoEXT::default()
+        .message_severity(
+          vk::DebugUtilsMessageSeverityFlagsEXT::ERROR |
+            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING |
+            vk::DebugUtilsMessageSeverityFlagsEXT::INFO |
+            vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+        )
+        .message_type(
+          vk::DebugUtilsMessageTypeFlagsEXT::GENERAL |
+            vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION |
+            vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+        )
+        .pfn_user_callback(Some(debug_messenger_callback)),
+      None
+    )
+    .map_err(|e| translate_vk_error("at debug messenger creation", e))?;
+  Ok((debug_utils_instance, debug_messenger))
+}
+
+/// `VK_KHR_dynamic_rendering` and the extensions it depends on for a Vulkan 1.0 device
+pub const DYNAMIC_RENDERING_EXTENSIONS: [&CStr; 5] = [
+  khr::dynamic_rendering::NAME,
+  khr::depth_stencil_resolve::NAME,
+  khr::create_renderpass2::NAME,
+  khr::multiview::NAME,
+  khr::maintenance2::NAME,
+];
+
+pub unsafe fn supports_device_extensions(
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+  names: &[&CStr],
+) -> Result<bool, rhi::RhiError> {
+  let supported_extensions = ash_instance
+    .enumerate_device_extension_properties(gpu)
+    .map_err(|e| translate_vk_error("at getting device extensions", e))?;
+  Ok(names.iter().all(|name| {
+    supported_extensions
+      .iter()
+      .any(|ext| ext.extension_name_as_c_str().is_ok_and(|x| x == *name))
+  }))
+}
+
+pub unsafe fn supports_dynamic_rendering(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<bool, rhi::RhiError> {
+  if !supports_device_extensions(ash_instance, gpu, &DYNAMIC_RENDERING_EXTENSIONS)? {
+    return Ok(false);
+  }
+  let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeaturesKHR::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default()
+    .push_next(&mut dynamic_rendering_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  Ok(dynamic_rendering_features.dynamic_rendering == vk::TRUE)
+}
+
+/// `VK_EXT_descriptor_indexing` and the extension it depends on for a Vulkan 1.0 device
+pub const DESCRIPTOR_INDEXING_EXTENSIONS: [&CStr; 2] = [
+  ext::descriptor_indexing::NAME,
+  khr::maintenance3::NAME,
+];
+
+/// Largest update after bind texture array a set layout can have, `None` when the device can't do
+/// partially bound, variable sized texture arrays
+pub unsafe fn bindless_texture_limit(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<Option<u32>, rhi::RhiError> {
+  if !supports_device_extensions(ash_instance, gpu, &DESCRIPTOR_INDEXING_EXTENSIONS)? {
+    return Ok(None);
+  }
+  let properties2_instance =
+    khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance);
+  let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeaturesEXT::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default().push_next(&mut indexing_features);
+  properties2_instance.get_physical_device_features2(gpu, &mut features);
+  let supported = indexing_features.descriptor_binding_partially_bound == vk::TRUE &&
+    indexing_features.descriptor_binding_sampled_image_update_after_bind == vk::TRUE &&
+    indexing_features.descriptor_binding_variable_descriptor_count == vk::TRUE &&
+    indexing_features.runtime_descriptor_array == vk::TRUE &&
+    indexing_features.shader_sampled_image_array_non_uniform_indexing == vk::TRUE;
+  if !supported {
+    return Ok(None);
+  }
+  let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingPropertiesEXT::default();
+  let mut properties =
+    vk::PhysicalDeviceProperties2KHR::default().push_next(&mut indexing_properties);
+  properties2_instance.get_physical_device_properties2(gpu, &mut properties);
+  // Combined image samplers count as both a sampler and a sampled image
+  let limit = [
+    indexing_properties.max_descriptor_set_update_after_bind_sampled_images,
+    indexing_properties.max_descriptor_set_update_after_bind_samplers,
+    indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+    indexing_properties.max_per_stage_descriptor_update_after_bind_samplers,
+    indexing_properties.max_per_stage_update_after_bind_resources,
+  ]
+    .into_iter()
+    .min()
+    .unwrap_or(0);
+  Ok(Some(limit))
+}
+
+pub unsafe fn supports_synchronization2(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<bool, rhi::RhiError> {
+  if !supports_device_extensions(ash_instance, gpu, &[khr::synchronization2::NAME])? {
+    return Ok(false);
+  }
+  let mut synchronization2_features = vk::PhysicalDeviceSynchronization2FeaturesKHR::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default()
+    .push_next(&mut synchronization2_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  Ok(synchronization2_features.synchronization2 == vk::TRUE)
+}
+
+pub unsafe fn supports_multiview(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<bool, rhi::RhiError> {
+  if !supports_device_extensions(ash_instance, gpu, &[khr::multiview::NAME])? {
+    return Ok(false);
+  }
+  let mut multiview_features = vk::PhysicalDeviceMultiviewFeaturesKHR::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default().push_next(&mut multiview_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  Ok(multiview_features.multiview == vk::TRUE)
+}
+
+pub unsafe fn supports_buffer_device_address(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<bool, rhi::RhiError> {
+  let api_version = ash_instance.get_physical_device_properties(gpu).api_version;
+  if api_version < vk::API_VERSION_1_1 ||
+    !supports_device_extensions(ash_instance, gpu, &[khr::buffer_device_address::NAME])? {
+    return Ok(false);
+  }
+  let mut buffer_device_address_features =
+    vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR::default();
+  let mut features =
+    vk::PhysicalDeviceFeatures2KHR::default().push_next(&mut buffer_device_address_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  Ok(buffer_device_address_features.buffer_device_address == vk::TRUE)
+}
+
+/// `VK_EXT_mesh_shader` and the extensions it depends on
+pub const MESH_SHADER_EXTENSIONS: [&CStr; 3] = [
+  ext::mesh_shader::NAME,
+  khr::spirv_1_4::NAME,
+  khr::shader_float_controls::NAME,
+];
+
+/// `VK_KHR_ray_tracing_pipeline` and the extensions it depends on
+pub const RAY_TRACING_EXTENSIONS: [&CStr; 8] = [
+  khr::ray_tracing_pipeline::NAME,
+  khr::acceleration_structure::NAME,
+  khr::deferred_host_operations::NAME,
+  khr::buffer_device_address::NAME,
+  ext::descriptor_indexing::NAME,
+  khr::maintenance3::NAME,
+  khr::spirv_1_4::NAME,
+  khr::shader_float_controls::NAME,
+];
+
+/// Ray tracing pipelines need acceleration structures and buffer device addresses along with them
+pub unsafe fn supports_ray_tracing(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<bool, rhi::RhiError> {
+  let api_version = ash_instance.get_physical_device_properties(gpu).api_version;
+  if api_version < vk::API_VERSION_1_1 ||
+    !supports_device_extensions(ash_instance, gpu, &RAY_TRACING_EXTENSIONS)? {
+    return Ok(false);
+  }
+  let mut ray_tracing_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
+  let mut acceleration_structure_features =
+    vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+  let mut buffer_device_address_features =
+    vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default()
+    .push_next(&mut ray_tracing_features)
+    .push_next(&mut acceleration_structure_features)
+    .push_next(&mut buffer_device_address_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  Ok(ray_tracing_features.ray_tracing_pipeline == vk::TRUE &&
+    acceleration_structure_features.acceleration_structure == vk::TRUE &&
+    buffer_device_address_features.buffer_device_address == vk::TRUE)
+}
+
+/// Whether the GPU has mesh shaders, and task shaders alongside them
+pub unsafe fn supports_mesh_shader(
+  ash_entry: &ash::Entry,
+  ash_instance: &ash::Instance,
+  gpu: vk::PhysicalDevice,
+) -> Result<(bool, bool), rhi::RhiError> {
+  let api_version = ash_instance.get_physical_device_properties(gpu).api_version;
+  if api_version < vk::API_VERSION_1_1 ||
+    !supports_device_extensions(ash_instance, gpu, &MESH_SHADER_EXTENSIONS)? {
+    return Ok((false, false));
+  }
+  let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+  let mut features = vk::PhysicalDeviceFeatures2KHR::default()
+    .push_next(&mut mesh_shader_features);
+  khr::get_physical_device_properties2::Instance::new(ash_entry, ash_instance)
+    .get_physical_device_features2(gpu, &mut features);
+  let mesh_shader = mesh_shader_features.mesh_shader == vk::TRUE;
+  Ok((mesh_shader, mesh_shader && mesh_shader_features.task_shader == vk::TRUE))
+}
+
+/// Descriptor counts other than samplers scale 1:1 with `max_sets`, textures get 16 per set.
+/// `update_after_bind` needs `VK_EXT_descriptor_indexing`
+pub unsafe fn create_descriptor_pool(
+  ash_device: &ash::Device,
+  max_sets: u32,
+  update_after_bind: bool,
+) -> Result<vk::DescriptorPool, rhi::RhiError> {
+  let flags = if update_after_bind {
+    vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND |
+      vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
+  } else {
+    vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
+  };
+  ash_device
+    .create_descriptor_pool(
+      &vk::DescriptorPoolCreateInfo::default()
+        .flags(flags)
+        .pool_sizes(&[
+          vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(max_sets),
+          vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(max_sets),
+          vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(max_sets.saturating_mul(16)),
+          vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(max_sets),
+        ])
+        .max_sets(max_sets),
+      None
+    )
+    .map_err(|e| translate_vk_error("at vk descriptor pool create", e))
+}
+
 pub unsafe fn make_swapchain(
   gpu: vk::PhysicalDevice,
   surface_instance: &khr::surface::Instance,
   surface: vk::SurfaceKHR,
   swapchain_device: &khr::swapchain::Device,
-) -> Result<(vk::Extent2D, vk::SurfaceFormatKHR, u32, vk::SwapchainKHR), String> {
+  fallback_res: vk::Extent2D,
+  preferred_present_mode: vk::PresentModeKHR,
+  old_swapchain: vk::SwapchainKHR,
+) -> Result<
+  (vk::Extent2D, vk::SurfaceFormatKHR, u32, vk::PresentModeKHR, vk::SwapchainKHR),
+  rhi::RhiError
+> {
   let surface_caps = surface_instance
     .get_physical_device_surface_capabilities(gpu, surface)
-    .map_err(|e| format!("at getting surface capabilities: {e}"))?;
-  let swapchain_res = surface_caps.current_extent;
+    .map_err(|e| translate_vk_error("at getting surface capabilities", e))?;
+  // u32::MAX means the surface takes whatever extent the swapchain is created with
+  let swapchain_res = if surface_caps.current_extent.width == u32::MAX {
+    vk::Extent2D {
+      width: fallback_res.width.clamp(
+        surface_caps.min_image_extent.width,
+        surface_caps.max_image_extent.width
+      ),
+      height: fallback_res.height.clamp(
+        surface_caps.min_image_extent.height,
+        surface_caps.max_image_extent.height
+      ),
+    }
+  } else {
+    surface_caps.current_extent
+  };
   let swapchain_img_count = std::cmp::min(
     surface_caps.min_image_count + 1,
     if surface_caps.max_image_count == 0 {
@@ -71,15 +412,21 @@ pub unsafe fn make_swapchain(
   );
   let surface_formats = surface_instance
     .get_physical_device_surface_formats(gpu, surface)
-    .map_err(|e| format!("at getting surface formats: {e}"))?;
+    .map_err(|e| translate_vk_error("at getting surface formats", e))?;
   let surface_format = surface_formats
     .iter()
     .find(|f| f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
     .cloned()
     .unwrap_or(surface_formats[0]);
-  let present_mode = surface_instance
+  let present_modes = surface_instance
     .get_physical_device_surface_present_modes(gpu, surface)
-    .map_err(|e| format!("at getting surface present mode: {e}"))?;
+    .map_err(|e| translate_vk_error("at getting surface present mode", e))?;
+  // FIFO is the only mode every surface has to support
+  let present_mode = if present_modes.contains(&preferred_present_mode) {
+    preferred_present_mode
+  } else {
+    vk::PresentModeKHR::FIFO
+  };
   let swapchain = swapchain_device.create_swapchain(
     &vk::SwapchainCreateInfoKHR::default()
       .surface(surface)
@@ -93,9 +440,82 @@ pub unsafe fn make_swapchain(
           vk::ImageUsageFlags::TRANSFER_DST |
           vk::ImageUsageFlags::STORAGE
       )
-      .image_array_layers(1),
+      .image_array_layers(1)
+      .pre_transform(surface_caps.current_transform)
+      .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+      .present_mode(present_mode)
+      .clipped(true)
+      .old_swapchain(old_swapchain),
     None
   )
-    .map_err(|e| format!("at swapchain creation: {e}"))?;
-  Ok((swapchain_res, surface_format, swapchain_img_count, swapchain))
+    .map_err(|e| translate_vk_error("at swapchain creation", e))?;
+  Ok((swapchain_res, surface_format, swapchain_img_count, present_mode, swapchain))
+}
+
+/// Range rules of `vkCmdUpdateBuffer` for command `i` of a compile
+pub fn check_update_buffer_range(
+  i: usize,
+  offset: u64,
+  data_size: u64,
+  buffer_size: u64
+) -> Result<(), rhi::RhiError> {
+  let bad_size = !data_size.is_multiple_of(4) || data_size == 0 || data_size > 65536;
+  if !offset.is_multiple_of(4) || bad_size {
+    return Err(rhi::RhiError::BackendError(format!(
+      "at compile commands: command {i} updates {data_size} bytes from offset {offset}, \
+      both must be multiples of 4 and the size between 4 and 65536"
+    )));
+  }
+  if offset.checked_add(data_size).is_none_or(|end| end > buffer_size) {
+    return Err(rhi::RhiError::BackendError(format!(
+      "at compile commands: command {i} updates {data_size} bytes from offset {offset} of \
+      a {buffer_size} byte buffer"
+    )));
+  }
+  Ok(())
+}
+
+/// Range rules of `vkCmdDispatchIndirect` args for command `i` of a compile
+pub fn check_dispatch_args_range(
+  i: usize,
+  args_offset: u64,
+  args_size: u64
+) -> Result<(), rhi::RhiError> {
+  let args_end = args_offset.checked_add(size_of::<vk::DispatchIndirectCommand>() as u64);
+  if !args_offset.is_multiple_of(4) || args_end.is_none_or(|end| end > args_size) {
+    return Err(rhi::RhiError::BackendError(format!(
+      "at compile commands: command {i} reads dispatch args at offset {args_offset} \
+      of a {args_size} byte buffer, the offset must be a multiple of 4"
+    )));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn update_buffer_range() {
+    assert!(check_update_buffer_range(0, 0, 4, 4).is_ok());
+    assert!(check_update_buffer_range(0, 60, 4, 64).is_ok());
+    assert!(check_update_buffer_range(0, 0, 65536, 65536).is_ok());
+    // Unaligned offset or size, empty or oversized data
+    assert!(check_update_buffer_range(0, 2, 4, 64).is_err());
+    assert!(check_update_buffer_range(0, 0, 6, 64).is_err());
+    assert!(check_update_buffer_range(0, 0, 0, 64).is_err());
+    assert!(check_update_buffer_range(0, 0, 65540, 1 << 20).is_err());
+    // Past the end, and an offset that overflows
+    assert!(check_update_buffer_range(0, 64, 4, 64).is_err());
+    assert!(check_update_buffer_range(0, u64::MAX - 3, 4, u64::MAX).is_err());
+  }
+
+  #[test]
+  fn dispatch_args_range() {
+    assert!(check_dispatch_args_range(0, 0, 12).is_ok());
+    assert!(check_dispatch_args_range(0, 4, 16).is_ok());
+    assert!(check_dispatch_args_range(0, 2, 16).is_err());
+    assert!(check_dispatch_args_range(0, 8, 16).is_err());
+    assert!(check_dispatch_args_range(0, u64::MAX - 3, u64::MAX).is_err());
+  }
 }
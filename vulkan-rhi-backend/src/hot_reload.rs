@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ash::vk;
+use notify::Watcher;
+
+use crate::{translate_vk_error, GraphicsPipelineState, VulkanBackend};
+
+/// What `rebuild_pipeline` remakes a pipeline from
+pub(crate) struct PipelineSource {
+  vertex_shader_path: PathBuf,
+  fragment_shader_path: PathBuf,
+  state: GraphicsPipelineState,
+}
+
+pub(crate) struct ShaderWatcher {
+  watcher: notify::RecommendedWatcher,
+  /// Filled from the watcher's thread, drained by `reload_changed_shaders`
+  changed_files: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl ShaderWatcher {
+  fn new() -> Result<Self, rhi::RhiError> {
+    let changed_files = Arc::new(Mutex::new(HashSet::new()));
+    let watcher_changed_files = changed_files.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+      // An error only means a missed change, saving the shader again retries it
+      let Ok(event) = event else {
+        return;
+      };
+      if !matches!(event.kind, notify::EventKind::Create(_) | notify::EventKind::Modify(_)) {
+        return;
+      }
+      if let Ok(mut changed_files) = watcher_changed_files.lock() {
+        changed_files.extend(event.paths.into_iter().filter(|x| is_spirv(x)));
+      }
+    })
+      .map_err(|e| translate_notify_error("at create shader watcher", e))?;
+    Ok(Self { watcher, changed_files })
+  }
+}
+
+fn translate_notify_error(context: &str, e: notify::Error) -> rhi::RhiError {
+  rhi::RhiError::BackendError(format!("{context}: {e}"))
+}
+
+fn is_spirv(path: &Path) -> bool {
+  path.extension().is_some_and(|x| x.eq_ignore_ascii_case("spv"))
+}
+
+/// The watcher reports paths under the watched directory, which needn't be spelled like the
+/// ones shaders were loaded from
+fn same_file(a: &Path, b: &Path) -> bool {
+  match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  }
+}
+
+impl VulkanBackend {
+  /// Watches `dir` and its subdirectories for changed SPIR-V files, see `reload_changed_shaders`
+  pub fn watch_shader_dir(&mut self, dir: PathBuf) -> Result<(), rhi::RhiError> {
+    if self.shader_watcher.is_none() {
+      self.shader_watcher = Some(ShaderWatcher::new()?);
+    }
+    if let Some(shader_watcher) = self.shader_watcher.as_mut() {
+      shader_watcher
+        .watcher
+        .watch(&dir, notify::RecursiveMode::Recursive)
+        .map_err(|e| translate_notify_error(&format!("at watch shader dir {dir:?}"), e))?;
+    }
+    Ok(())
+  }
+
+  /// Rebuilds the pipelines whose vertex or fragment shader file changed since the last call,
+  /// meant to be called once per frame before its commands are compiled. Pipelines whose
+  /// shaders fail to build keep the old ones and are only logged, files are often seen half
+  /// written. Returns the rebuilt pipelines
+  pub fn reload_changed_shaders(&mut self) -> Result<Vec<rhi::PipelineID>, rhi::RhiError> {
+    let Some(shader_watcher) = self.shader_watcher.as_ref() else {
+      return Ok(vec![]);
+    };
+    let changed_files = shader_watcher
+      .changed_files
+      .lock()
+      .map(|mut x| std::mem::take(&mut *x))
+      .map_err(|e| rhi::RhiError::BackendError(format!("at changed shaders lock: {e}")))?;
+    if changed_files.is_empty() {
+      return Ok(vec![]);
+    }
+    let pipeline_ids = self
+      .pipelines
+      .iter()
+      .filter(|(_, x)| {
+        x.reload_source.as_ref().is_some_and(|source| {
+          changed_files.iter().any(|path| {
+            same_file(path, &source.vertex_shader_path) ||
+              same_file(path, &source.fragment_shader_path)
+          })
+        })
+      })
+      .map(|(id, _)| rhi::PipelineID(id))
+      .collect::<Vec<_>>();
+    let mut rebuilt = vec![];
+    for pipeline_id in pipeline_ids {
+      match self.rebuild_pipeline(pipeline_id) {
+        Ok(()) => rebuilt.push(pipeline_id),
+        Err(e) => log::error!("shader reload of pipeline {} failed: {e}", pipeline_id.0),
+      }
+    }
+    Ok(rebuilt)
+  }
+
+  /// Remakes only the `vk::Pipeline` of `pipeline_id` from its shader files, the layout and
+  /// render pass are kept. Waits for the device to go idle before the old pipeline is
+  /// destroyed, command buffers compiled before have to be compiled again
+  pub fn rebuild_pipeline(&mut self, pipeline_id: rhi::PipelineID) -> Result<(), rhi::RhiError> {
+    let g_pipeline = self.pipelines.get_obj(pipeline_id.0)?;
+    let source = g_pipeline.reload_source.as_ref().ok_or(rhi::RhiError::BackendError(
+      "at rebuild pipeline: pipeline wasn't made from only vertex and fragment shader files"
+        .to_string()
+    ))?;
+    unsafe {
+      let vertex_shader_vk = self.read_shader_module(&source.vertex_shader_path)?;
+      let pipeline = self
+        .read_shader_module(&source.fragment_shader_path)
+        .and_then(|fragment_shader_vk| {
+          let pipeline = self.build_graphics_pipeline_vk(
+            &source.state,
+            g_pipeline.pipeline_layout,
+            g_pipeline.render_pass,
+            (vk::ShaderStageFlags::VERTEX, vertex_shader_vk),
+            fragment_shader_vk,
+            &[]
+          );
+          self.ash_device.destroy_shader_module(fragment_shader_vk, None);
+          pipeline
+        });
+      self.ash_device.destroy_shader_module(vertex_shader_vk, None);
+      let pipeline = pipeline?;
+      if let Err(e) = self.ash_device.device_wait_idle() {
+        self.ash_device.destroy_pipeline(pipeline, None);
+        return Err(translate_vk_error("at rebuild pipeline device wait idle", e));
+      }
+      let old_pipeline =
+        std::mem::replace(&mut self.pipelines.get_obj_mut(pipeline_id.0)?.pipeline, pipeline);
+      self.ash_device.destroy_pipeline(old_pipeline, None);
+    }
+    Ok(())
+  }
+
+  unsafe fn read_shader_module(&self, path: &Path) -> Result<vk::ShaderModule, rhi::RhiError> {
+    let code = std::fs::read(path)
+      .map_err(|e| rhi::RhiError::BackendError(format!("at read shader file {path:?}: {e}")))?;
+    let code = ash::util::read_spv(&mut std::io::Cursor::new(code))
+      .map_err(|e| rhi::RhiError::BackendError(format!("at read shader {path:?}: {e}")))?;
+    self
+      .ash_device
+      .create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&code), None)
+      .map_err(|e| translate_vk_error("at shader module creation", e))
+  }
+
+  /// `None` unless the pipeline only has a vertex and a fragment shader, both read from files
+  pub(crate) fn pipeline_source(
+    &self,
+    pipeline_type: rhi::PipelineType,
+    fragment_shader: rhi::ShaderID,
+    has_extra_stages: bool,
+    state: GraphicsPipelineState,
+  ) -> Option<PipelineSource> {
+    let rhi::PipelineType::VertexPipeline { vertex_shader } = pipeline_type else {
+      return None;
+    };
+    if has_extra_stages {
+      return None;
+    }
+    Some(PipelineSource {
+      vertex_shader_path: self.shader_paths.get(&vertex_shader.0)?.clone(),
+      fragment_shader_path: self.shader_paths.get(&fragment_shader.0)?.clone(),
+      state,
+    })
+  }
+}
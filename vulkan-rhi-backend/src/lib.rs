@@ -1,9 +1,20 @@
 mod helpers;
+#[cfg(feature = "openxr-backend")]
+mod xr;
+#[cfg(feature = "renderdoc")]
+mod capture;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
 use std::path::PathBuf;
 pub use rhi;
-use ash::{vk, khr};
+#[cfg(feature = "openxr-backend")]
+pub use openxr;
+#[cfg(feature = "openxr-backend")]
+pub use xr::{OpenXrDevice, OpenXrSwapchainDesc};
+use ash::{vk, ext, khr};
 use gpu_allocator::MemoryLocation;
 use gpu_allocator::vulkan::{
   Allocation,
@@ -13,28 +24,74 @@ use gpu_allocator::vulkan::{
   AllocatorCreateDesc
 };
 use rhi::{HasDisplayHandle, HasWindowHandle};
-use seq_id_store::SequentialIDStore;
+use seq_id_store::{SequentialIDStore, StoreError};
 use tokio::fs;
 
+fn translate_vk_error(context: &str, e: vk::Result) -> rhi::RhiError {
+  match e {
+    vk::Result::ERROR_OUT_OF_HOST_MEMORY |
+    vk::Result::ERROR_OUT_OF_DEVICE_MEMORY |
+    vk::Result::ERROR_OUT_OF_POOL_MEMORY => rhi::RhiError::OutOfMemory,
+    vk::Result::ERROR_DEVICE_LOST => rhi::RhiError::DeviceLost,
+    vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => {
+      rhi::RhiError::SwapchainOutOfDate
+    }
+    e => rhi::RhiError::BackendError(format!("{context}: {e}")),
+  }
+}
+
+fn translate_present_mode(present_mode: rhi::PresentMode) -> vk::PresentModeKHR {
+  match present_mode {
+    rhi::PresentMode::Fifo => {vk::PresentModeKHR::FIFO}
+    rhi::PresentMode::Mailbox => {vk::PresentModeKHR::MAILBOX}
+    rhi::PresentMode::Immediate => {vk::PresentModeKHR::IMMEDIATE}
+  }
+}
+
 fn translate_memory_location(memory_location: rhi::MemoryLocation) -> MemoryLocation {
   match memory_location {
     rhi::MemoryLocation::Any => {MemoryLocation::GpuOnly}
     rhi::MemoryLocation::GPU => {MemoryLocation::GpuOnly}
-    rhi::MemoryLocation::Shared => {MemoryLocation::CpuToGpu}
+    rhi::MemoryLocation::CPUToGPU => {MemoryLocation::CpuToGpu}
+    rhi::MemoryLocation::CPUReadback => {MemoryLocation::GpuToCpu}
   }
 }
 
 fn translate_image_format(format: rhi::ImageFormat) -> vk::Format {
   match format {
     rhi::ImageFormat::Texture => {vk::Format::R8G8B8A8_UNORM}
-    rhi::ImageFormat::Float => {vk::Format::R32G32B32_SFLOAT}
-    rhi::ImageFormat::Depth => {vk::Format::D24_UNORM_S8_UINT}
+    rhi::ImageFormat::Float => {vk::Format::R32G32B32A32_SFLOAT}
     rhi::ImageFormat::RenderIntermediate => {vk::Format::R8G8B8A8_UNORM}
     rhi::ImageFormat::Presentation => {vk::Format::B8G8R8A8_SRGB}
+    rhi::ImageFormat::R8Unorm => {vk::Format::R8_UNORM}
+    rhi::ImageFormat::Rg8Unorm => {vk::Format::R8G8_UNORM}
+    rhi::ImageFormat::Rgba8Unorm => {vk::Format::R8G8B8A8_UNORM}
+    rhi::ImageFormat::Rgba8Srgb => {vk::Format::R8G8B8A8_SRGB}
+    rhi::ImageFormat::R16Sfloat => {vk::Format::R16_SFLOAT}
+    rhi::ImageFormat::Rg16Sfloat => {vk::Format::R16G16_SFLOAT}
+    rhi::ImageFormat::Rgba16Sfloat => {vk::Format::R16G16B16A16_SFLOAT}
+    rhi::ImageFormat::R32Sfloat => {vk::Format::R32_SFLOAT}
+    rhi::ImageFormat::Rg32Sfloat => {vk::Format::R32G32_SFLOAT}
+    rhi::ImageFormat::Rgba32Sfloat => {vk::Format::R32G32B32A32_SFLOAT}
+    rhi::ImageFormat::R32Uint => {vk::Format::R32_UINT}
+    rhi::ImageFormat::R32Sint => {vk::Format::R32_SINT}
+    rhi::ImageFormat::Hdr => {vk::Format::R16G16B16A16_SFLOAT}
+    rhi::ImageFormat::Depth32Sfloat => {vk::Format::D32_SFLOAT}
+    rhi::ImageFormat::Depth24Stencil8 => {vk::Format::D24_UNORM_S8_UINT}
+  }
+}
+
+fn translate_sample_count(sample_count: rhi::ImageSampleCount) -> vk::SampleCountFlags {
+  match sample_count {
+    rhi::ImageSampleCount::E1 => {vk::SampleCountFlags::TYPE_1}
+    rhi::ImageSampleCount::E2 => {vk::SampleCountFlags::TYPE_2}
+    rhi::ImageSampleCount::E4 => {vk::SampleCountFlags::TYPE_4}
+    rhi::ImageSampleCount::E8 => {vk::SampleCountFlags::TYPE_8}
+    rhi::ImageSampleCount::E16 => {vk::SampleCountFlags::TYPE_16}
   }
 }
 
-fn translate_image_usage(usage: rhi::ImageUsage) -> vk::ImageUsageFlags {
+fn translate_image_usage(usage: rhi::ImageUsage, format: rhi::ImageFormat) -> vk::ImageUsageFlags {
   let mut flags = vk::ImageUsageFlags::empty();
   if usage.contains(rhi::ImageUsage::COPY_SRC){
     flags |= vk::ImageUsageFlags::TRANSFER_SRC;
@@ -48,6 +105,127 @@ fn translate_image_usage(usage: rhi::ImageUsage) -> vk::ImageUsageFlags {
   if usage.contains(rhi::ImageUsage::SHADER_STORAGE) {
     flags |= vk::ImageUsageFlags::STORAGE;
   }
+  if usage.contains(rhi::ImageUsage::RENDER_TARGET) {
+    if get_aspect_mask(format).contains(vk::ImageAspectFlags::DEPTH) {
+      flags |= vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+    } else {
+      flags |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
+    }
+  }
+  flags
+}
+
+fn translate_image_tiling(tiling: rhi::ImageTiling) -> vk::ImageTiling {
+  match tiling {
+    rhi::ImageTiling::Optimal => {vk::ImageTiling::OPTIMAL}
+    rhi::ImageTiling::Linear => {vk::ImageTiling::LINEAR}
+  }
+}
+
+/// Format features an image needs for `usage`
+fn image_usage_format_features(
+  usage: rhi::ImageUsage,
+  format: rhi::ImageFormat
+) -> vk::FormatFeatureFlags {
+  let mut flags = vk::FormatFeatureFlags::empty();
+  if usage.contains(rhi::ImageUsage::COPY_SRC) {
+    flags |= vk::FormatFeatureFlags::TRANSFER_SRC;
+  }
+  if usage.contains(rhi::ImageUsage::COPY_DST) {
+    flags |= vk::FormatFeatureFlags::TRANSFER_DST;
+  }
+  if usage.contains(rhi::ImageUsage::BLIT_SRC) {
+    flags |= vk::FormatFeatureFlags::BLIT_SRC;
+  }
+  if usage.contains(rhi::ImageUsage::BLIT_DST) {
+    flags |= vk::FormatFeatureFlags::BLIT_DST;
+  }
+  if usage.contains(rhi::ImageUsage::SHADER_SAMPLED) {
+    flags |= vk::FormatFeatureFlags::SAMPLED_IMAGE;
+  }
+  if usage.contains(rhi::ImageUsage::SHADER_STORAGE) {
+    flags |= vk::FormatFeatureFlags::STORAGE_IMAGE;
+  }
+  if usage.contains(rhi::ImageUsage::RENDER_TARGET) {
+    if get_aspect_mask(format).contains(vk::ImageAspectFlags::DEPTH) {
+      flags |= vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT;
+    } else {
+      flags |= vk::FormatFeatureFlags::COLOR_ATTACHMENT;
+    }
+  }
+  flags
+}
+
+/// Highest sample count in `counts`
+fn max_sample_count(counts: vk::SampleCountFlags) -> rhi::ImageSampleCount {
+  [
+    (vk::SampleCountFlags::TYPE_16, rhi::ImageSampleCount::E16),
+    (vk::SampleCountFlags::TYPE_8, rhi::ImageSampleCount::E8),
+    (vk::SampleCountFlags::TYPE_4, rhi::ImageSampleCount::E4),
+    (vk::SampleCountFlags::TYPE_2, rhi::ImageSampleCount::E2),
+  ]
+    .into_iter()
+    .find(|(flag, _)| counts.contains(*flag))
+    .map(|(_, count)| count)
+    .unwrap_or(rhi::ImageSampleCount::E1)
+}
+
+fn translate_memory_heap_flags(flags: vk::MemoryHeapFlags) -> rhi::MemoryHeapFlags {
+  let mut heap_flags = rhi::MemoryHeapFlags::empty();
+  if flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+    heap_flags |= rhi::MemoryHeapFlags::DEVICE_LOCAL;
+  }
+  if flags.contains(vk::MemoryHeapFlags::MULTI_INSTANCE) {
+    heap_flags |= rhi::MemoryHeapFlags::MULTI_INSTANCE;
+  }
+  heap_flags
+}
+
+fn translate_memory_property_flags(flags: vk::MemoryPropertyFlags) -> rhi::MemoryPropertyFlags {
+  let mut property_flags = rhi::MemoryPropertyFlags::empty();
+  if flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL) {
+    property_flags |= rhi::MemoryPropertyFlags::DEVICE_LOCAL;
+  }
+  if flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+    property_flags |= rhi::MemoryPropertyFlags::HOST_VISIBLE;
+  }
+  if flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+    property_flags |= rhi::MemoryPropertyFlags::HOST_COHERENT;
+  }
+  if flags.contains(vk::MemoryPropertyFlags::HOST_CACHED) {
+    property_flags |= rhi::MemoryPropertyFlags::HOST_CACHED;
+  }
+  if flags.contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED) {
+    property_flags |= rhi::MemoryPropertyFlags::LAZILY_ALLOCATED;
+  }
+  property_flags
+}
+
+fn translate_pipeline_stat_flags(
+  stats: rhi::PipelineStatFlags
+) -> vk::QueryPipelineStatisticFlags {
+  let mut flags = vk::QueryPipelineStatisticFlags::empty();
+  if stats.contains(rhi::PipelineStatFlags::INPUT_ASSEMBLY_VERTICES) {
+    flags |= vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES;
+  }
+  if stats.contains(rhi::PipelineStatFlags::INPUT_ASSEMBLY_PRIMITIVES) {
+    flags |= vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES;
+  }
+  if stats.contains(rhi::PipelineStatFlags::VERTEX_SHADER_INVOCATIONS) {
+    flags |= vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS;
+  }
+  if stats.contains(rhi::PipelineStatFlags::CLIPPING_INVOCATIONS) {
+    flags |= vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS;
+  }
+  if stats.contains(rhi::PipelineStatFlags::CLIPPING_PRIMITIVES) {
+    flags |= vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES;
+  }
+  if stats.contains(rhi::PipelineStatFlags::FRAGMENT_SHADER_INVOCATIONS) {
+    flags |= vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS;
+  }
+  if stats.contains(rhi::PipelineStatFlags::COMPUTE_SHADER_INVOCATIONS) {
+    flags |= vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS;
+  }
   flags
 }
 
@@ -65,9 +243,32 @@ fn translate_buffer_usage(usage: rhi::BufferUsage) -> vk::BufferUsageFlags {
   if usage.contains(rhi::BufferUsage::UNIFORM) {
     flags |= vk::BufferUsageFlags::UNIFORM_BUFFER;
   }
+  if usage.contains(rhi::BufferUsage::VERTEX) {
+    flags |= vk::BufferUsageFlags::VERTEX_BUFFER;
+  }
+  if usage.contains(rhi::BufferUsage::INDEX) {
+    flags |= vk::BufferUsageFlags::INDEX_BUFFER;
+  }
+  if usage.contains(rhi::BufferUsage::INDIRECT) {
+    flags |= vk::BufferUsageFlags::INDIRECT_BUFFER;
+  }
+  if usage.contains(rhi::BufferUsage::ACCEL_STRUCT_INPUT) {
+    flags |= vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR |
+      vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+  }
+  if usage.contains(rhi::BufferUsage::DEVICE_ADDRESS) {
+    flags |= vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+  }
   flags
 }
 
+fn translate_index_type(index_type: rhi::IndexType) -> vk::IndexType {
+  match index_type {
+    rhi::IndexType::Uint16 => {vk::IndexType::UINT16}
+    rhi::IndexType::Uint32 => {vk::IndexType::UINT32}
+  }
+}
+
 fn translate_descriptor_type(_type: rhi::DescriptorType) -> vk::DescriptorType {
   match _type {
     rhi::DescriptorType::Uniform => {
@@ -76,9 +277,40 @@ fn translate_descriptor_type(_type: rhi::DescriptorType) -> vk::DescriptorType {
     rhi::DescriptorType::Storage => {
       vk::DescriptorType::STORAGE_BUFFER
     }
-    rhi::DescriptorType::Sampler2D => {
+    rhi::DescriptorType::Sampler => {
+      vk::DescriptorType::SAMPLER
+    }
+    rhi::DescriptorType::CombinedImageSampler => {
       vk::DescriptorType::COMBINED_IMAGE_SAMPLER
     }
+    rhi::DescriptorType::StorageImage => {
+      vk::DescriptorType::STORAGE_IMAGE
+    }
+  }
+}
+
+fn translate_sampler_filter(filter: rhi::SamplerFilter) -> vk::Filter {
+  match filter {
+    rhi::SamplerFilter::Linear => {vk::Filter::LINEAR}
+    rhi::SamplerFilter::Nearest => {vk::Filter::NEAREST}
+  }
+}
+
+fn translate_sampler_mip_mode(mip_mode: rhi::SamplerMipMode) -> vk::SamplerMipmapMode {
+  match mip_mode {
+    rhi::SamplerMipMode::Linear => {vk::SamplerMipmapMode::LINEAR}
+    rhi::SamplerMipMode::Nearest => {vk::SamplerMipmapMode::NEAREST}
+  }
+}
+
+fn translate_sampler_address_mode(
+  address_mode: rhi::SamplerAddressMode
+) -> vk::SamplerAddressMode {
+  match address_mode {
+    rhi::SamplerAddressMode::Repeat => {vk::SamplerAddressMode::REPEAT}
+    rhi::SamplerAddressMode::MirroredRepeat => {vk::SamplerAddressMode::MIRRORED_REPEAT}
+    rhi::SamplerAddressMode::ClampToEdge => {vk::SamplerAddressMode::CLAMP_TO_EDGE}
+    rhi::SamplerAddressMode::ClampToBorder => {vk::SamplerAddressMode::CLAMP_TO_BORDER}
   }
 }
 
@@ -92,55 +324,389 @@ fn translate_shader_stage_flags(
   if shader_stage_flags.contains(rhi::ShaderStageFlags::FRAGMENT) {
     flags |= vk::ShaderStageFlags::FRAGMENT;
   }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::COMPUTE) {
+    flags |= vk::ShaderStageFlags::COMPUTE;
+  }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::TESSELLATION_CONTROL) {
+    flags |= vk::ShaderStageFlags::TESSELLATION_CONTROL;
+  }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::TESSELLATION_EVALUATION) {
+    flags |= vk::ShaderStageFlags::TESSELLATION_EVALUATION;
+  }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::GEOMETRY) {
+    flags |= vk::ShaderStageFlags::GEOMETRY;
+  }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::TASK) {
+    flags |= vk::ShaderStageFlags::TASK_EXT;
+  }
+  if shader_stage_flags.contains(rhi::ShaderStageFlags::MESH) {
+    flags |= vk::ShaderStageFlags::MESH_EXT;
+  }
   flags
 }
 
-fn translate_raster_style<'a>(
-  raster_style: rhi::RasterStyle
+fn translate_cull_mode(cull_mode: rhi::CullMode) -> vk::CullModeFlags {
+  match cull_mode {
+    rhi::CullMode::None => {vk::CullModeFlags::NONE}
+    rhi::CullMode::Back => {vk::CullModeFlags::BACK}
+    rhi::CullMode::Front => {vk::CullModeFlags::FRONT}
+    rhi::CullMode::FrontAndBack => {vk::CullModeFlags::FRONT_AND_BACK}
+  }
+}
+
+fn translate_front_face(front_face: rhi::FrontFace) -> vk::FrontFace {
+  match front_face {
+    rhi::FrontFace::Clockwise => {vk::FrontFace::CLOCKWISE}
+    rhi::FrontFace::CounterClockwise => {vk::FrontFace::COUNTER_CLOCKWISE}
+  }
+}
+
+fn translate_raster_config<'a>(
+  raster_config: rhi::RasterConfig
 ) -> vk::PipelineRasterizationStateCreateInfo<'a>{
-  match raster_style {
-    rhi::RasterStyle::Fill => {
-      vk::PipelineRasterizationStateCreateInfo::default()
-        .polygon_mode(vk::PolygonMode::FILL)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .line_width(1.0)
-    }
+  let (polygon_mode, default_cull_mode, line_width) = match raster_config.style {
+    rhi::RasterStyle::Fill => {(vk::PolygonMode::FILL, rhi::CullMode::Back, 1.0)}
     rhi::RasterStyle::WireFrame { thickness } => {
-      vk::PipelineRasterizationStateCreateInfo::default()
-        .polygon_mode(vk::PolygonMode::FILL)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .line_width(thickness as f32)
+      (vk::PolygonMode::LINE, rhi::CullMode::None, thickness)
     }
+    rhi::RasterStyle::Point { .. } => {(vk::PolygonMode::POINT, rhi::CullMode::None, 1.0)}
+    rhi::RasterStyle::FillConservative => {(vk::PolygonMode::FILL, rhi::CullMode::Back, 1.0)}
+  };
+  vk::PipelineRasterizationStateCreateInfo::default()
+    .polygon_mode(polygon_mode)
+    .front_face(translate_front_face(raster_config.front_face))
+    .cull_mode(translate_cull_mode(raster_config.cull_mode.unwrap_or(default_cull_mode)))
+    .line_width(line_width)
+    .depth_clamp_enable(raster_config.depth_clamp)
+    .depth_bias_enable(raster_config.depth_bias.is_some())
+}
+
+fn translate_blit_filter(filter: rhi::BlitFilter) -> vk::Filter {
+  match filter {
+    rhi::BlitFilter::Linear => {vk::Filter::LINEAR}
+    rhi::BlitFilter::Nearest => {vk::Filter::NEAREST}
   }
 }
 
 fn get_aspect_mask(format: rhi::ImageFormat) -> vk::ImageAspectFlags {
   match format {
-    rhi::ImageFormat::Texture => { vk::ImageAspectFlags::COLOR }
-    rhi::ImageFormat::Float => { vk::ImageAspectFlags::COLOR }
-    rhi::ImageFormat::Depth => { vk::ImageAspectFlags::DEPTH }
-    rhi::ImageFormat::RenderIntermediate => { vk::ImageAspectFlags::COLOR }
-    rhi::ImageFormat::Presentation => { vk::ImageAspectFlags::COLOR }
+    rhi::ImageFormat::Depth32Sfloat => { vk::ImageAspectFlags::DEPTH }
+    rhi::ImageFormat::Depth24Stencil8 => {
+      vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+    }
+    _ => { vk::ImageAspectFlags::COLOR }
+  }
+}
+
+/// Bytes per texel of a single aspect, depth of depth-stencil formats is copied as 4 bytes
+fn get_texel_size(format: rhi::ImageFormat) -> u64 {
+  match format {
+    rhi::ImageFormat::Texture => {4}
+    rhi::ImageFormat::Float => {16}
+    rhi::ImageFormat::RenderIntermediate => {4}
+    rhi::ImageFormat::Presentation => {4}
+    rhi::ImageFormat::R8Unorm => {1}
+    rhi::ImageFormat::Rg8Unorm => {2}
+    rhi::ImageFormat::Rgba8Unorm => {4}
+    rhi::ImageFormat::Rgba8Srgb => {4}
+    rhi::ImageFormat::R16Sfloat => {2}
+    rhi::ImageFormat::Rg16Sfloat => {4}
+    rhi::ImageFormat::Rgba16Sfloat => {8}
+    rhi::ImageFormat::R32Sfloat => {4}
+    rhi::ImageFormat::Rg32Sfloat => {8}
+    rhi::ImageFormat::Rgba32Sfloat => {16}
+    rhi::ImageFormat::R32Uint => {4}
+    rhi::ImageFormat::R32Sint => {4}
+    rhi::ImageFormat::Hdr => {8}
+    rhi::ImageFormat::Depth32Sfloat => {4}
+    rhi::ImageFormat::Depth24Stencil8 => {4}
   }
 }
 
-fn infer_access_from_layout(layout: vk::ImageLayout) -> vk::AccessFlags{
+fn infer_access_from_layout(layout: vk::ImageLayout) -> vk::AccessFlags2{
   if layout == vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL{
-    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-  } else if layout == vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL{
-    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+    vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+  } else if layout == vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL ||
+    layout == vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
+    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+  } else if layout == vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL {
+    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
   } else if layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL{
-    vk::AccessFlags::SHADER_READ
+    vk::AccessFlags2::SHADER_SAMPLED_READ
   } else if layout == vk::ImageLayout::TRANSFER_SRC_OPTIMAL {
-    vk::AccessFlags::TRANSFER_READ
+    vk::AccessFlags2::TRANSFER_READ
   } else if layout == vk::ImageLayout::TRANSFER_DST_OPTIMAL {
-    vk::AccessFlags::TRANSFER_WRITE
+    vk::AccessFlags2::TRANSFER_WRITE
+  } else if layout == vk::ImageLayout::GENERAL {
+    vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
   } else if layout == vk::ImageLayout::UNDEFINED {
-    vk::AccessFlags::NONE
+    vk::AccessFlags2::NONE
   } else {
-    vk::AccessFlags::NONE
+    vk::AccessFlags2::NONE
+  }
+}
+
+/// Access flags for `cmd_pipeline_barrier`, the lower 32 bits of synchronization2 flags are the
+/// old ones and its finer shader accesses fold back into shader read and write
+fn downgrade_access(access: vk::AccessFlags2) -> vk::AccessFlags {
+  let mut flags = vk::AccessFlags::from_raw(access.as_raw() as u32);
+  let shader_reads = vk::AccessFlags2::SHADER_SAMPLED_READ | vk::AccessFlags2::SHADER_STORAGE_READ;
+  if access.intersects(shader_reads) {
+    flags |= vk::AccessFlags::SHADER_READ;
+  }
+  if access.contains(vk::AccessFlags2::SHADER_STORAGE_WRITE) {
+    flags |= vk::AccessFlags::SHADER_WRITE;
+  }
+  flags
+}
+
+fn translate_blend_factor(factor: rhi::BlendFactor) -> vk::BlendFactor {
+  match factor {
+    rhi::BlendFactor::Zero => {vk::BlendFactor::ZERO}
+    rhi::BlendFactor::One => {vk::BlendFactor::ONE}
+    rhi::BlendFactor::SrcColor => {vk::BlendFactor::SRC_COLOR}
+    rhi::BlendFactor::OneMinusSrcColor => {vk::BlendFactor::ONE_MINUS_SRC_COLOR}
+    rhi::BlendFactor::DstColor => {vk::BlendFactor::DST_COLOR}
+    rhi::BlendFactor::OneMinusDstColor => {vk::BlendFactor::ONE_MINUS_DST_COLOR}
+    rhi::BlendFactor::SrcAlpha => {vk::BlendFactor::SRC_ALPHA}
+    rhi::BlendFactor::OneMinusSrcAlpha => {vk::BlendFactor::ONE_MINUS_SRC_ALPHA}
+    rhi::BlendFactor::DstAlpha => {vk::BlendFactor::DST_ALPHA}
+    rhi::BlendFactor::OneMinusDstAlpha => {vk::BlendFactor::ONE_MINUS_DST_ALPHA}
+  }
+}
+
+fn translate_blend_op(op: rhi::BlendOp) -> vk::BlendOp {
+  match op {
+    rhi::BlendOp::Add => {vk::BlendOp::ADD}
+    rhi::BlendOp::Subtract => {vk::BlendOp::SUBTRACT}
+    rhi::BlendOp::ReverseSubtract => {vk::BlendOp::REVERSE_SUBTRACT}
+    rhi::BlendOp::Min => {vk::BlendOp::MIN}
+    rhi::BlendOp::Max => {vk::BlendOp::MAX}
+  }
+}
+
+fn translate_load_op(op: rhi::LoadOp) -> vk::AttachmentLoadOp {
+  match op {
+    rhi::LoadOp::Clear(_) => {vk::AttachmentLoadOp::CLEAR}
+    rhi::LoadOp::Load => {vk::AttachmentLoadOp::LOAD}
+    rhi::LoadOp::DontCare => {vk::AttachmentLoadOp::DONT_CARE}
+  }
+}
+
+fn translate_store_op(op: rhi::StoreOp) -> vk::AttachmentStoreOp {
+  match op {
+    rhi::StoreOp::Store => {vk::AttachmentStoreOp::STORE}
+    rhi::StoreOp::DontCare => {vk::AttachmentStoreOp::DONT_CARE}
+  }
+}
+
+/// Unused by the driver unless the load op is `Clear`
+fn color_clear_value(ops: rhi::AttachmentOps) -> vk::ClearValue {
+  let rhi::LoadOp::Clear(color) = ops.load else {
+    return vk::ClearValue::default();
+  };
+  vk::ClearValue{ color: vk::ClearColorValue{ float32: color } }
+}
+
+fn depth_clear_value(ops: rhi::AttachmentOps) -> vk::ClearValue {
+  let rhi::LoadOp::Clear(values) = ops.load else {
+    return vk::ClearValue::default();
+  };
+  vk::ClearValue{
+    depth_stencil: vk::ClearDepthStencilValue{ depth: values[0], stencil: values[1] as u32 }
+  }
+}
+
+fn translate_color_components(components: rhi::ColorComponentFlags) -> vk::ColorComponentFlags {
+  let mut flags = vk::ColorComponentFlags::empty();
+  if components.contains(rhi::ColorComponentFlags::R) {
+    flags |= vk::ColorComponentFlags::R;
+  }
+  if components.contains(rhi::ColorComponentFlags::G) {
+    flags |= vk::ColorComponentFlags::G;
+  }
+  if components.contains(rhi::ColorComponentFlags::B) {
+    flags |= vk::ColorComponentFlags::B;
+  }
+  if components.contains(rhi::ColorComponentFlags::A) {
+    flags |= vk::ColorComponentFlags::A;
+  }
+  flags
+}
+
+fn translate_blend_state(blend_state: rhi::BlendState) -> vk::PipelineColorBlendAttachmentState {
+  vk::PipelineColorBlendAttachmentState::default()
+    .blend_enable(blend_state.blend_enable)
+    .src_color_blend_factor(translate_blend_factor(blend_state.src_color))
+    .dst_color_blend_factor(translate_blend_factor(blend_state.dst_color))
+    .color_blend_op(translate_blend_op(blend_state.color_op))
+    .src_alpha_blend_factor(translate_blend_factor(blend_state.src_alpha))
+    .dst_alpha_blend_factor(translate_blend_factor(blend_state.dst_alpha))
+    .alpha_blend_op(translate_blend_op(blend_state.alpha_op))
+    .color_write_mask(translate_color_components(blend_state.write_mask))
+}
+
+fn translate_compare_op(op: rhi::CompareOp) -> vk::CompareOp {
+  match op {
+    rhi::CompareOp::Less => {vk::CompareOp::LESS}
+    rhi::CompareOp::LessEqual => {vk::CompareOp::LESS_OR_EQUAL}
+    rhi::CompareOp::Greater => {vk::CompareOp::GREATER}
+    rhi::CompareOp::GreaterEqual => {vk::CompareOp::GREATER_OR_EQUAL}
+    rhi::CompareOp::Equal => {vk::CompareOp::EQUAL}
+    rhi::CompareOp::Always => {vk::CompareOp::ALWAYS}
+    rhi::CompareOp::Never => {vk::CompareOp::NEVER}
+  }
+}
+
+fn translate_stencil_op(op: rhi::StencilOp) -> vk::StencilOp {
+  match op {
+    rhi::StencilOp::Keep => {vk::StencilOp::KEEP}
+    rhi::StencilOp::Zero => {vk::StencilOp::ZERO}
+    rhi::StencilOp::Replace => {vk::StencilOp::REPLACE}
+    rhi::StencilOp::IncrementClamp => {vk::StencilOp::INCREMENT_AND_CLAMP}
+    rhi::StencilOp::DecrementClamp => {vk::StencilOp::DECREMENT_AND_CLAMP}
+    rhi::StencilOp::Invert => {vk::StencilOp::INVERT}
+    rhi::StencilOp::IncrementWrap => {vk::StencilOp::INCREMENT_AND_WRAP}
+    rhi::StencilOp::DecrementWrap => {vk::StencilOp::DECREMENT_AND_WRAP}
+  }
+}
+
+fn translate_stencil_op_state(state: rhi::StencilOpState) -> vk::StencilOpState {
+  vk::StencilOpState::default()
+    .fail_op(translate_stencil_op(state.fail_op))
+    .pass_op(translate_stencil_op(state.pass_op))
+    .depth_fail_op(translate_stencil_op(state.depth_fail_op))
+    .compare_op(translate_compare_op(state.compare_op))
+    .compare_mask(state.compare_mask)
+    .write_mask(state.write_mask)
+    .reference(state.reference)
+}
+
+fn translate_depth_state(
+  depth_state: Option<rhi::DepthState>
+) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+  let Some(depth_state) = depth_state else {
+    return vk::PipelineDepthStencilStateCreateInfo::default()
+      .depth_test_enable(false)
+      .depth_write_enable(false)
+      .depth_bounds_test_enable(false)
+      .stencil_test_enable(false);
+  };
+  vk::PipelineDepthStencilStateCreateInfo::default()
+    .depth_test_enable(depth_state.depth_test_enable)
+    .depth_write_enable(depth_state.depth_write_enable)
+    .depth_compare_op(translate_compare_op(depth_state.compare_op))
+    .depth_bounds_test_enable(false)
+    .stencil_test_enable(depth_state.stencil_enable)
+    .front(translate_stencil_op_state(depth_state.front))
+    .back(translate_stencil_op_state(depth_state.back))
+}
+
+/// Map entries and the data they point into, every constant takes 4 bytes
+fn translate_spec_constants(
+  constants: &[rhi::SpecializationConstant]
+) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+  let map_entries = constants
+    .iter()
+    .enumerate()
+    .map(|(i, x)| {
+      vk::SpecializationMapEntry::default()
+        .constant_id(x.id)
+        .offset(i as u32 * 4)
+        .size(4)
+    })
+    .collect();
+  let data = constants
+    .iter()
+    .flat_map(|x| match x.value {
+      rhi::SpecConstValue::Bool(v) => {(if v { vk::TRUE } else { vk::FALSE }).to_ne_bytes()}
+      rhi::SpecConstValue::Int(v) => {v.to_ne_bytes()}
+      rhi::SpecConstValue::Uint(v) => {v.to_ne_bytes()}
+      rhi::SpecConstValue::Float(v) => {v.to_ne_bytes()}
+    })
+    .collect();
+  (map_entries, data)
+}
+
+fn translate_pipeline_stage(stage: rhi::PipelineStage) -> vk::PipelineStageFlags {
+  let mut flags = vk::PipelineStageFlags::empty();
+  if stage.contains(rhi::PipelineStage::TOP_OF_PIPE) {
+    flags |= vk::PipelineStageFlags::TOP_OF_PIPE;
+  }
+  if stage.contains(rhi::PipelineStage::DRAW_INDIRECT) {
+    flags |= vk::PipelineStageFlags::DRAW_INDIRECT;
+  }
+  if stage.contains(rhi::PipelineStage::VERTEX_INPUT) {
+    flags |= vk::PipelineStageFlags::VERTEX_INPUT;
+  }
+  if stage.contains(rhi::PipelineStage::VERTEX_SHADER) {
+    flags |= vk::PipelineStageFlags::VERTEX_SHADER;
+  }
+  if stage.contains(rhi::PipelineStage::FRAGMENT_SHADER) {
+    flags |= vk::PipelineStageFlags::FRAGMENT_SHADER;
+  }
+  if stage.contains(rhi::PipelineStage::EARLY_FRAGMENT_TESTS) {
+    flags |= vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS;
+  }
+  if stage.contains(rhi::PipelineStage::LATE_FRAGMENT_TESTS) {
+    flags |= vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+  }
+  if stage.contains(rhi::PipelineStage::COLOR_ATTACHMENT_OUTPUT) {
+    flags |= vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+  }
+  if stage.contains(rhi::PipelineStage::COMPUTE_SHADER) {
+    flags |= vk::PipelineStageFlags::COMPUTE_SHADER;
+  }
+  if stage.contains(rhi::PipelineStage::TRANSFER) {
+    flags |= vk::PipelineStageFlags::TRANSFER;
+  }
+  if stage.contains(rhi::PipelineStage::BOTTOM_OF_PIPE) {
+    flags |= vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+  }
+  if stage.contains(rhi::PipelineStage::HOST) {
+    flags |= vk::PipelineStageFlags::HOST;
+  }
+  if stage.contains(rhi::PipelineStage::ALL_GRAPHICS) {
+    flags |= vk::PipelineStageFlags::ALL_GRAPHICS;
+  }
+  if stage.contains(rhi::PipelineStage::ALL_COMMANDS) {
+    flags |= vk::PipelineStageFlags::ALL_COMMANDS;
+  }
+  flags
+}
+
+fn translate_mem_access(access: rhi::MemAccessType) -> vk::AccessFlags2 {
+  match access {
+    rhi::MemAccessType::TransferRead => {vk::AccessFlags2::TRANSFER_READ}
+    rhi::MemAccessType::TransferWrite => {vk::AccessFlags2::TRANSFER_WRITE}
+    rhi::MemAccessType::HostRead => {vk::AccessFlags2::HOST_READ}
+    rhi::MemAccessType::HostWrite => {vk::AccessFlags2::HOST_WRITE}
+    rhi::MemAccessType::ShaderRead => {vk::AccessFlags2::SHADER_READ}
+    rhi::MemAccessType::ShaderWrite => {vk::AccessFlags2::SHADER_WRITE}
+    rhi::MemAccessType::ColorAttachmentRead => {vk::AccessFlags2::COLOR_ATTACHMENT_READ}
+    rhi::MemAccessType::ColorAttachmentWrite => {vk::AccessFlags2::COLOR_ATTACHMENT_WRITE}
+    rhi::MemAccessType::DepthAttachmentRead => {vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ}
+    rhi::MemAccessType::DepthAttachmentWrite => {vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE}
+    rhi::MemAccessType::MemoryRead => {vk::AccessFlags2::MEMORY_READ}
+    rhi::MemAccessType::MemoryWrite => {vk::AccessFlags2::MEMORY_WRITE}
+    rhi::MemAccessType::MemoryReadWrite => {
+      vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE
+    }
+    rhi::MemAccessType::None => {vk::AccessFlags2::NONE}
+  }
+}
+
+fn translate_image_layout(layout: rhi::ImageLayoutType) -> vk::ImageLayout {
+  match layout {
+    rhi::ImageLayoutType::Undefined => {vk::ImageLayout::UNDEFINED}
+    rhi::ImageLayoutType::General => {vk::ImageLayout::GENERAL}
+    rhi::ImageLayoutType::ColorAttachment => {vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL}
+    rhi::ImageLayoutType::DepthAttachment => {vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL}
+    rhi::ImageLayoutType::DepthRO => {vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL}
+    rhi::ImageLayoutType::ShaderRO => {vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL}
+    rhi::ImageLayoutType::TransferSrc => {vk::ImageLayout::TRANSFER_SRC_OPTIMAL}
+    rhi::ImageLayoutType::TransferDst => {vk::ImageLayout::TRANSFER_DST_OPTIMAL}
+    rhi::ImageLayoutType::Present => {vk::ImageLayout::PRESENT_SRC_KHR}
   }
 }
 
@@ -150,11 +716,44 @@ pub struct AllocatedBuffer{
   allocation: Option<Allocation>,
 }
 
+/// Descriptor sets in the first descriptor pool, every input set takes three
+const DESCRIPTOR_POOL_BASE_SETS: u32 = 512;
+
+/// Smallest staging buffer the pool allocates, so tiny uploads share a bucket
+const STAGING_MIN_BUCKET_SIZE: u64 = 64 * 1024;
+
+/// Index of a buffer in `StagingBufferPool`
+#[derive(Debug, Clone, Copy)]
+pub struct StagingPoolHandle(usize);
+
+/// CPU visible upload buffers with power of two sizes, reused across uploads
+#[derive(Default)]
+pub struct StagingBufferPool{
+  buffers: Vec<AllocatedBuffer>,
+  in_use: Vec<bool>,
+}
+
+impl StagingBufferPool {
+  fn smallest_free(&self, size: u64) -> Option<usize> {
+    self
+      .buffers
+      .iter()
+      .enumerate()
+      .filter(|(i, x)| !self.in_use[*i] && x.size >= size)
+      .min_by_key(|(_, x)| x.size)
+      .map(|(i, _)| i)
+  }
+}
+
 pub struct AllocatedTexture{
   image: vk::Image,
   view: vk::ImageView,
   resolution: rhi::Resolution2D,
   format: rhi::ImageFormat,
+  depth: u32,
+  mip_levels: u32,
+  layer_count: u32,
+  is_cube: bool,
   allocation: Option<Allocation>,
 }
 
@@ -162,75 +761,346 @@ pub struct GraphicsPipeline{
   pipeline: vk::Pipeline,
   pipeline_layout: vk::PipelineLayout,
   render_pass: vk::RenderPass,
+  sample_count: vk::SampleCountFlags,
+  color_ops: Vec<rhi::AttachmentOps>,
+  depth_ops: Option<rhi::AttachmentOps>,
+  color_final_layouts: Vec<vk::ImageLayout>,
+  depth_final_layout: Option<vk::ImageLayout>,
+  buffer_set_layout: vk::DescriptorSetLayout,
+  texture_set_layout: vk::DescriptorSetLayout,
+  storage_image_set_layout: vk::DescriptorSetLayout,
+  uniform_buffer_count: u32,
+  max_texture_count: u32,
+  push_constant_size: u32,
+  push_constant_stages: vk::ShaderStageFlags,
+  /// Front and back stencil reference, set when the pipeline is bound
+  stencil_reference: (u32, u32),
+  /// Constant, clamp and slope depth bias, set when the pipeline is bound
+  depth_bias: (f32, f32, f32),
+  multiview_mask: Option<u32>,
+  /// Made from a mesh shader, drawn only by `DrawMeshTasks`
+  mesh_shading: bool,
+  /// Reads `DrawInfo::instance_buffer` at vertex input binding 1
+  instance_input: bool,
+  /// `None` when `rebuild_pipeline` can't remake the pipeline from its shader files
+  #[cfg(feature = "hot-reload")]
+  reload_source: Option<hot_reload::PipelineSource>,
+}
+
+/// What `create_graphics_pipeline` bakes into the `vk::Pipeline`, besides the shaders and the
+/// layout and render pass
+pub(crate) struct GraphicsPipelineState {
+  raster_config: rhi::RasterConfig,
+  multisample: rhi::MultisampleState,
+  blend_states: Vec<rhi::BlendState>,
+  depth_state: Option<rhi::DepthState>,
+  vertex_spec_constants: Vec<rhi::SpecializationConstant>,
+  fragment_spec_constants: Vec<rhi::SpecializationConstant>,
+  /// Set when the pipeline has tessellation shaders
+  patch_control_points: Option<u32>,
+  color_formats: Vec<rhi::ImageFormat>,
+  depth_format: Option<rhi::ImageFormat>,
+  multiview_mask: Option<u32>,
+  instance_input: Option<rhi::InstanceInput>,
+}
+
+pub struct AllocatedAccelStruct{
+  accel_struct: vk::AccelerationStructureKHR,
+  /// Backing storage, in `VulkanBackend::buffers`
+  buffer: rhi::BufferID,
+  /// What TLAS instances reference the structure by
+  address: u64,
+}
+
+pub struct RayTracingPipeline{
+  pipeline: vk::Pipeline,
+  pipeline_layout: vk::PipelineLayout,
+  buffer_set_layout: vk::DescriptorSetLayout,
+  texture_set_layout: vk::DescriptorSetLayout,
+  storage_image_set_layout: vk::DescriptorSetLayout,
+  max_texture_count: u32,
+  /// Holds the raygen, miss and hit group handles, in that order
+  sbt_buffer: rhi::BufferID,
+  raygen_region: vk::StridedDeviceAddressRegionKHR,
+  miss_region: vk::StridedDeviceAddressRegionKHR,
+  hit_region: vk::StridedDeviceAddressRegionKHR,
+}
+
+pub struct ComputePipeline{
+  pipeline: vk::Pipeline,
+  pipeline_layout: vk::PipelineLayout,
   buffer_set_layout: vk::DescriptorSetLayout,
   texture_set_layout: vk::DescriptorSetLayout,
+  storage_image_set_layout: vk::DescriptorSetLayout,
+  max_texture_count: u32,
 }
 
 pub struct InputSetVK {
+  /// Index in `VulkanBackend::descriptor_pools` of the pool the sets came from
+  pool_index: u32,
   buffer_set: vk::DescriptorSet,
   texture_set: vk::DescriptorSet,
-  bound_buffers: Vec<rhi::BufferID>,
-  bound_textures: Vec<rhi::ImageID>,
+  storage_image_set: vk::DescriptorSet,
+  uniform_buffer_count: u32,
+  texture_count: u32,
+  /// Keyed by index in the set's array
+  bound_buffers: HashMap<u32, rhi::BufferID>,
+  bound_textures: HashMap<u32, rhi::ImageID>,
+  bound_storage_images: Vec<rhi::ImageID>,
+}
+
+pub struct CommandBufferVK {
+  command_buffer: vk::CommandBuffer,
+  /// Set for secondary command buffers
+  inheritance: Option<rhi::CommandBufferInheritance>,
+  /// Fence of the last `run_commands` submit
+  last_fence: Option<vk::Fence>,
+}
+
+/// Dynamic state set by `SetViewport` and friends, applied to every later graphics run
+#[derive(Default)]
+struct DynamicStateOverrides {
+  viewport: Option<vk::Viewport>,
+  scissor: Option<vk::Rect2D>,
+  stencil_reference: Option<(u32, u32)>,
+  depth_bias: Option<(f32, f32, f32)>,
 }
 
 pub struct FramebufferVK {
   framebuffer: vk::Framebuffer,
   color_attachments: Vec<rhi::ImageID>,
   depth_attachment: Option<rhi::ImageID>,
+  resolve_attachments: Vec<rhi::ImageID>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum GpuSelector {
+  /// First discrete GPU, or the first GPU if there is none
+  #[default]
+  PreferDiscrete,
+  /// First integrated GPU, or the first GPU if there is none
+  PreferIntegrated,
+  ByName(String),
+  /// Index into the list returned by `VulkanBackend::enumerate_gpus`
+  ByIndex(usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VulkanBackendConfig {
+  pub gpu_selector: GpuSelector,
+  /// Where the driver's pipeline cache is loaded from and saved back to on drop. `None` starts
+  /// with an empty cache every run
+  pub pipeline_cache_path: Option<PathBuf>,
+  /// Render without render pass and framebuffer objects. Falls back to render passes when the
+  /// device doesn't support `VK_KHR_dynamic_rendering`
+  pub use_dynamic_rendering: bool,
+  /// Texture arrays are partially bound and can be updated while in use, up to the device's
+  /// update after bind limit. Falls back to fixed arrays without `VK_EXT_descriptor_indexing`
+  pub bindless_textures: bool,
+  /// Falls back to FIFO when the surface doesn't support it
+  pub preferred_present_mode: rhi::PresentMode,
+}
+
+/// Instance and device level state, created by `new` or handed over by OpenXR
+struct DeviceSetup {
+  ash_entry: ash::Entry,
+  ash_instance: ash::Instance,
+  debug_utils: Option<(ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
+  gpu: vk::PhysicalDevice,
+  gpu_info: rhi::GPUInfo,
+  ash_device: ash::Device,
+  graphics_queue_family_id: u32,
+  transfer_queue_family_id: u32,
+  use_dynamic_rendering: bool,
+  use_synchronization2: bool,
+  bindless_texture_limit: Option<u32>,
+  memory_budget_supported: bool,
+  conservative_rasterization_supported: bool,
+  multiview_supported: bool,
+  /// `VK_EXT_mesh_shader` is enabled, with the mesh shader feature and maybe the task shader one
+  mesh_shader_supported: bool,
+  task_shader_supported: bool,
+  /// `VK_KHR_ray_tracing_pipeline` is enabled, with acceleration structures
+  ray_tracing_supported: bool,
+  /// `VK_KHR_buffer_device_address` is enabled, always the case with ray tracing
+  buffer_device_address_supported: bool,
+  /// The features enabled on `ash_device`
+  device_features: vk::PhysicalDeviceFeatures,
 }
 
 pub struct VulkanBackend {
-  command_buffers: SequentialIDStore<vk::CommandBuffer>,
+  command_buffers: SequentialIDStore<CommandBufferVK>,
   command_pool: vk::CommandPool,
+  transfer_command_pool: vk::CommandPool,
+  staging_pool: StagingBufferPool,
   fences: SequentialIDStore<vk::Fence>,
+  semaphores: SequentialIDStore<vk::Semaphore>,
   descriptor_sets: SequentialIDStore<InputSetVK>,
   frame_buffers: SequentialIDStore<FramebufferVK>,
+  shaders: SequentialIDStore<vk::ShaderModule>,
   pipelines: SequentialIDStore<GraphicsPipeline>,
+  compute_pipelines: SequentialIDStore<ComputePipeline>,
+  ray_tracing_pipelines: SequentialIDStore<RayTracingPipeline>,
+  accel_structs: SequentialIDStore<AllocatedAccelStruct>,
   images: SequentialIDStore<AllocatedTexture>,
+  /// Extra views, with the image they view
+  image_views: SequentialIDStore<(vk::ImageView, rhi::ImageID)>,
+  samplers: SequentialIDStore<vk::Sampler>,
+  /// With the statistics counted by pipeline statistics pools, empty for timestamp pools
+  query_pools: SequentialIDStore<(vk::QueryPool, rhi::PipelineStatFlags)>,
   buffers: SequentialIDStore<AllocatedBuffer>,
   allocator: Allocator,
-  descriptor_pool: vk::DescriptorPool,
+  /// Allocation goes to the last pool, a new one with twice the sets is added once it's full
+  descriptor_pools: Vec<vk::DescriptorPool>,
+  last_descriptor_pool_max_sets: u32,
+  pipeline_cache: vk::PipelineCache,
+  pipeline_cache_path: Option<PathBuf>,
   graphics_queue: vk::Queue,
   graphics_queue_family_id: u32,
+  transfer_queue: vk::Queue,
+  transfer_queue_family_id: u32,
   gpu: vk::PhysicalDevice,
+  gpu_info: rhi::GPUInfo,
+  device_features: vk::PhysicalDeviceFeatures,
+  /// `VK_EXT_memory_budget` is enabled
+  memory_budget_supported: bool,
+  /// `VK_EXT_conservative_rasterization` is enabled
+  conservative_rasterization_supported: bool,
+  /// `VK_KHR_multiview` is enabled
+  multiview_supported: bool,
+  /// Texture array size of the set layouts, set when bindless textures are in use
+  bindless_texture_limit: Option<u32>,
+  max_uniform_buffer_range: u32,
+  timestamp_period: f32,
   swapchain_images: Vec<rhi::ImageID>,
   swapchain: vk::SwapchainKHR,
   swapchain_res: vk::Extent2D,
   surface_format: vk::SurfaceFormatKHR,
+  preferred_present_mode: rhi::PresentMode,
+  present_mode: vk::PresentModeKHR,
   swapchain_device: khr::swapchain::Device,
+  dynamic_rendering_device: Option<khr::dynamic_rendering::Device>,
+  synchronization2_device: Option<khr::synchronization2::Device>,
+  mesh_shader_device: Option<ext::mesh_shader::Device>,
+  task_shader_supported: bool,
+  ray_tracing_device: Option<khr::ray_tracing_pipeline::Device>,
+  ray_tracing_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'static>,
+  accel_struct_device: Option<khr::acceleration_structure::Device>,
+  accel_struct_scratch_alignment: u32,
+  buffer_device_address_device: Option<khr::buffer_device_address::Device>,
+  debug_utils_device: Option<ext::debug_utils::Device>,
   surface: vk::SurfaceKHR,
   ash_device: ash::Device,
   surface_instance: khr::surface::Instance,
+  /// Only set up in debug builds, and only if the instance has `VK_EXT_debug_utils`
+  debug_utils: Option<(ext::debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
+  #[cfg(feature = "openxr-backend")]
+  xr: Option<xr::OpenXrState>,
+  #[cfg(feature = "renderdoc")]
+  renderdoc: Option<std::sync::Mutex<capture::RenderDocState>>,
+  #[cfg(feature = "tracy")]
+  tracy: tracy_client::Client,
+  /// Files the shaders made by `create_shader` and `load_shader` were read from
+  #[cfg(feature = "hot-reload")]
+  shader_paths: HashMap<u32, PathBuf>,
+  #[cfg(feature = "hot-reload")]
+  shader_watcher: Option<hot_reload::ShaderWatcher>,
   ash_instance: ash::Instance,
   ash_entry: ash::Entry,
 }
 
 impl VulkanBackend {
-  fn new(window: &(impl HasWindowHandle + HasDisplayHandle)) -> Result<Self, String> {
+  /// The gpu_allocator report of live allocations and memory blocks
+  pub fn get_allocator_stats(&self) -> String {
+    format!("{:?}", self.allocator.generate_report())
+  }
+
+  /// Frees the buffers the staging pool keeps around, e.g. once a level finished loading
+  pub fn flush_staging_pool(&mut self) {
+    for staging_buffer in self.staging_pool.buffers.drain(..) {
+      unsafe {
+        self.ash_device.destroy_buffer(staging_buffer.buffer, None);
+      }
+      staging_buffer.allocation.map(|a| self.allocator.free(a));
+    }
+    self.staging_pool.in_use.clear();
+  }
+
+  /// Destroys descriptor pools that no input set uses anymore, except the one allocated from
+  pub fn compact_descriptor_pools(&mut self) {
+    let last_pool_index = self.descriptor_pools.len() - 1;
+    let mut in_use = vec![false; self.descriptor_pools.len()];
+    in_use[last_pool_index] = true;
+    for (_, input_set) in self.descriptor_sets.iter() {
+      in_use[input_set.pool_index as usize] = true;
+    }
+    // Old index to index once the empty pools are gone
+    let mut new_indices = vec![0; self.descriptor_pools.len()];
+    let mut kept_pools = vec![];
+    for (i, pool) in self.descriptor_pools.drain(..).enumerate() {
+      if in_use[i] {
+        new_indices[i] = kept_pools.len() as u32;
+        kept_pools.push(pool);
+      } else {
+        unsafe {
+          self.ash_device.destroy_descriptor_pool(pool, None);
+        }
+      }
+    }
+    self.descriptor_pools = kept_pools;
+    for (_, input_set) in self.descriptor_sets.iter_mut() {
+      input_set.pool_index = new_indices[input_set.pool_index as usize];
+    }
+  }
+
+  /// Lists the available GPUs without creating a device, to pick one for `GpuSelector`
+  pub fn enumerate_gpus() -> Result<Vec<rhi::GPUInfo>, rhi::RhiError> {
+    unsafe {
+      let (_ash_entry, ash_instance) = helpers::create_vk_instance()?;
+      let gpu_infos = helpers::list_gpus(&ash_instance);
+      ash_instance.destroy_instance(None);
+      gpu_infos
+    }
+  }
+
+  pub fn new(
+    window: &(impl HasWindowHandle + HasDisplayHandle),
+    config: VulkanBackendConfig,
+  ) -> Result<Self, rhi::RhiError> {
     unsafe {
       let (ash_entry, ash_instance) = helpers::create_vk_instance()?;
+      #[cfg(debug_assertions)]
+      let debug_utils = Some(helpers::create_debug_messenger(&ash_entry, &ash_instance)?);
+      #[cfg(not(debug_assertions))]
+      let debug_utils = None;
       let vk_gpus = ash_instance
         .enumerate_physical_devices()
-        .map_err(|e| format!("at getting GPU list: {e}"))?;
-      let gpu_info = vk_gpus
-        .into_iter()
-        .map(|gpu| {
-          let props = ash_instance.get_physical_device_properties(gpu);
-          (
-            gpu,
-            props
-              .device_name_as_c_str()
-              .unwrap_or(c"Unknown Device")
-              .to_string_lossy()
-              .to_string(),
-            props.device_type == vk::PhysicalDeviceType::DISCRETE_GPU,
-          )
-        })
-        .collect::<Vec<_>>();
-      if gpu_info.is_empty() {
-        return Err(String::from("no GPU found"));
+        .map_err(|e| translate_vk_error("at getting GPU list", e))?;
+      let gpu_infos = helpers::list_gpus(&ash_instance)?;
+      if gpu_infos.is_empty() {
+        return Err(rhi::RhiError::BackendError(String::from("no GPU found")));
       }
-      let gpu = gpu_info.iter().find(|info| {info.2}).map(|x| x.0).unwrap_or(gpu_info[0].0);
+      let gpu_index = match &config.gpu_selector {
+        GpuSelector::PreferDiscrete => vk_gpus
+          .iter()
+          .position(|gpu| {
+            ash_instance.get_physical_device_properties(*gpu).device_type ==
+              vk::PhysicalDeviceType::DISCRETE_GPU
+          })
+          .unwrap_or(0),
+        GpuSelector::PreferIntegrated => gpu_infos.iter().position(|x| x.integrated).unwrap_or(0),
+        GpuSelector::ByName(name) => gpu_infos
+          .iter()
+          .position(|x| &x.name == name)
+          .ok_or(rhi::RhiError::BackendError(format!("no GPU named {name}")))?,
+        GpuSelector::ByIndex(index) => {
+          if *index >= vk_gpus.len() {
+            return Err(rhi::RhiError::BackendError(format!("no GPU at index {index}")));
+          }
+          *index
+        }
+      };
+      let gpu = vk_gpus[gpu_index];
 
       let gpu_queue_family_props = ash_instance.get_physical_device_queue_family_properties(gpu);
       let graphics_queue_family_id = gpu_queue_family_props
@@ -239,473 +1109,3252 @@ impl VulkanBackend {
         .filter(|(_, x)| x.queue_flags.contains(vk::QueueFlags::GRAPHICS))
         .max_by_key(|(_, x)| x.queue_count)
         .map(|(x, _)| x as u32)
-        .ok_or("no suitable GPU graphics queue found".to_string())?;
-      let device_extensions = [
+        .ok_or(rhi::RhiError::BackendError("no suitable GPU graphics queue found".to_string()))?;
+      // A dedicated transfer family (not graphics, ideally not compute either) runs uploads
+      // alongside rendering. Fall back to the graphics queue without one
+      let transfer_queue_family_id = gpu_queue_family_props
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| {
+          x.queue_flags.contains(vk::QueueFlags::TRANSFER) &&
+            !x.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .min_by_key(|(_, x)| x.queue_flags.contains(vk::QueueFlags::COMPUTE))
+        .map(|(x, _)| x as u32)
+        .unwrap_or(graphics_queue_family_id);
+      let use_dynamic_rendering = config.use_dynamic_rendering &&
+        helpers::supports_dynamic_rendering(&ash_entry, &ash_instance, gpu)?;
+      if config.use_dynamic_rendering && !use_dynamic_rendering {
+        log::warn!("dynamic rendering not supported, falling back to render passes");
+      }
+      let mut device_extensions = vec![
         khr::swapchain::NAME.as_ptr(),
         #[cfg(target_os = "macos")]
         khr::portability_subset::NAME.as_ptr(),
       ];
-      let ash_device = ash_instance
-        .create_device(
-          gpu,
-          &vk::DeviceCreateInfo::default()
-          .queue_create_infos(&[
-            vk::DeviceQueueCreateInfo::default()
-              .queue_family_index(graphics_queue_family_id)
-              .queue_priorities(&[1.0])
-          ])
-          .enabled_extension_names(&device_extensions)
-          .enabled_features(&vk::PhysicalDeviceFeatures::default()),
-          None
-        )
-        .map_err(|e| format!("at vk device create: {e}"))?;
-      let graphics_queue = ash_device.get_device_queue(graphics_queue_family_id, 0);
-      let surface_instance = khr::surface::Instance::new(&ash_entry, &ash_instance);
-      let surface = ash_window::create_surface(
-        &ash_entry,
-        &ash_instance,
-        window.display_handle().map_err(|_| "invalid window".to_string())?.as_raw(),
-        window.window_handle().map_err(|_| "invalid window".to_string())?.as_raw(),
+      if use_dynamic_rendering {
+        device_extensions.extend(helpers::DYNAMIC_RENDERING_EXTENSIONS.iter().map(|x| x.as_ptr()));
+      }
+      let bindless_texture_limit = if config.bindless_textures {
+        helpers::bindless_texture_limit(&ash_entry, &ash_instance, gpu)?
+      } else {
         None
-      )
-        .map_err(|e| format!("at surface creation: {e}"))?;
-      let swapchain_device = khr::swapchain::Device::new(&ash_instance, &ash_device);
-
-      let allocator = Allocator::new(
-        &AllocatorCreateDesc {
-          instance: ash_instance.clone(),
-          device: ash_device.clone(),
-          physical_device: gpu,
-          debug_settings: Default::default(),
-          buffer_device_address: false,
-          allocation_sizes: Default::default(),
-        }
-      )
-        .map_err(|e| format!("at allocator create: {e}"))?;
-
-      let descriptor_pool = ash_device
-        .create_descriptor_pool(
-          &vk::DescriptorPoolCreateInfo::default()
-          .flags(
-            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND |
-            vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET
-          )
-          .pool_sizes(
-            &[
-              vk::DescriptorPoolSize::default()
-                .ty(vk::DescriptorType::STORAGE_BUFFER)
-                .descriptor_count(512),
-              vk::DescriptorPoolSize::default()
-                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(8192),
-            ]
-          )
-          .max_sets(512),
-          None
+      };
+      if config.bindless_textures && bindless_texture_limit.is_none() {
+        log::warn!("descriptor indexing not supported, falling back to fixed texture arrays");
+      }
+      if bindless_texture_limit.is_some() {
+        device_extensions
+          .extend(helpers::DESCRIPTOR_INDEXING_EXTENSIONS.iter().map(|x| x.as_ptr()));
+      }
+      let use_synchronization2 =
+        helpers::supports_synchronization2(&ash_entry, &ash_instance, gpu)?;
+      if use_synchronization2 {
+        device_extensions.push(khr::synchronization2::NAME.as_ptr());
+      }
+      let memory_budget_supported =
+        helpers::supports_device_extensions(&ash_instance, gpu, &[ext::memory_budget::NAME])?;
+      if memory_budget_supported {
+        device_extensions.push(ext::memory_budget::NAME.as_ptr());
+      }
+      let conservative_rasterization_supported = helpers::supports_device_extensions(
+        &ash_instance,
+        gpu,
+        &[ext::conservative_rasterization::NAME]
+      )?;
+      if conservative_rasterization_supported {
+        device_extensions.push(ext::conservative_rasterization::NAME.as_ptr());
+      }
+      let multiview_supported = helpers::supports_multiview(&ash_entry, &ash_instance, gpu)?;
+      if multiview_supported {
+        device_extensions.push(khr::multiview::NAME.as_ptr());
+      }
+      let (mesh_shader_supported, task_shader_supported) =
+        helpers::supports_mesh_shader(&ash_entry, &ash_instance, gpu)?;
+      if mesh_shader_supported {
+        device_extensions.extend(helpers::MESH_SHADER_EXTENSIONS.iter().map(|x| x.as_ptr()));
+      }
+      let ray_tracing_supported = helpers::supports_ray_tracing(&ash_entry, &ash_instance, gpu)?;
+      if ray_tracing_supported {
+        device_extensions.extend(helpers::RAY_TRACING_EXTENSIONS.iter().map(|x| x.as_ptr()));
+      }
+      let buffer_device_address_supported =
+        helpers::supports_buffer_device_address(&ash_entry, &ash_instance, gpu)?;
+      if buffer_device_address_supported {
+        device_extensions.push(khr::buffer_device_address::NAME.as_ptr());
+      }
+      // Extension groups share dependencies, which can only be enabled once
+      let mut enabled_extensions = HashSet::new();
+      device_extensions.retain(|x| enabled_extensions.insert(CStr::from_ptr(*x)));
+      let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeaturesKHR::default()
+        .dynamic_rendering(true);
+      let mut synchronization2_features = vk::PhysicalDeviceSynchronization2FeaturesKHR::default()
+        .synchronization2(true);
+      let mut multiview_features =
+        vk::PhysicalDeviceMultiviewFeaturesKHR::default().multiview(true);
+      let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default()
+        .mesh_shader(true)
+        .task_shader(task_shader_supported);
+      let mut ray_tracing_features = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default()
+        .ray_tracing_pipeline(true);
+      let mut acceleration_structure_features =
+        vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default().acceleration_structure(true);
+      let mut buffer_device_address_features =
+        vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR::default().buffer_device_address(true);
+      let mut descriptor_indexing_features =
+        vk::PhysicalDeviceDescriptorIndexingFeaturesEXT::default()
+          .descriptor_binding_partially_bound(true)
+          .descriptor_binding_sampled_image_update_after_bind(true)
+          .descriptor_binding_variable_descriptor_count(true)
+          .runtime_descriptor_array(true)
+          .shader_sampled_image_array_non_uniform_indexing(true);
+      let supported_features = ash_instance.get_physical_device_features(gpu);
+      let device_features = vk::PhysicalDeviceFeatures::default()
+        .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+        .multi_draw_indirect(supported_features.multi_draw_indirect == vk::TRUE)
+        .fill_mode_non_solid(supported_features.fill_mode_non_solid == vk::TRUE)
+        .wide_lines(supported_features.wide_lines == vk::TRUE)
+        .large_points(supported_features.large_points == vk::TRUE)
+        .depth_clamp(supported_features.depth_clamp == vk::TRUE)
+        .depth_bias_clamp(supported_features.depth_bias_clamp == vk::TRUE)
+        .alpha_to_one(supported_features.alpha_to_one == vk::TRUE)
+        .geometry_shader(supported_features.geometry_shader == vk::TRUE)
+        .tessellation_shader(supported_features.tessellation_shader == vk::TRUE)
+        .pipeline_statistics_query(supported_features.pipeline_statistics_query == vk::TRUE);
+      let queue_priorities = [1.0];
+      let mut queue_create_infos = vec![
+        vk::DeviceQueueCreateInfo::default()
+          .queue_family_index(graphics_queue_family_id)
+          .queue_priorities(&queue_priorities)
+      ];
+      if transfer_queue_family_id != graphics_queue_family_id {
+        queue_create_infos.push(
+          vk::DeviceQueueCreateInfo::default()
+            .queue_family_index(transfer_queue_family_id)
+            .queue_priorities(&queue_priorities)
+        );
+      }
+      let device_create_info = vk::DeviceCreateInfo::default()
+        .queue_create_infos(&queue_create_infos)
+        .enabled_extension_names(&device_extensions)
+        .enabled_features(&device_features);
+      let device_create_info = if use_dynamic_rendering {
+        device_create_info.push_next(&mut dynamic_rendering_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if bindless_texture_limit.is_some() {
+        device_create_info.push_next(&mut descriptor_indexing_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if use_synchronization2 {
+        device_create_info.push_next(&mut synchronization2_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if multiview_supported {
+        device_create_info.push_next(&mut multiview_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if mesh_shader_supported {
+        device_create_info.push_next(&mut mesh_shader_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if ray_tracing_supported {
+        device_create_info
+          .push_next(&mut ray_tracing_features)
+          .push_next(&mut acceleration_structure_features)
+      } else {
+        device_create_info
+      };
+      let device_create_info = if buffer_device_address_supported {
+        device_create_info.push_next(&mut buffer_device_address_features)
+      } else {
+        device_create_info
+      };
+      let ash_device = ash_instance
+        .create_device(gpu, &device_create_info, None)
+        .map_err(|e| translate_vk_error("at vk device create", e))?;
+      Self::from_device(window, config, DeviceSetup {
+        ash_entry,
+        ash_instance,
+        debug_utils,
+        gpu,
+        gpu_info: gpu_infos[gpu_index].clone(),
+        ash_device,
+        graphics_queue_family_id,
+        transfer_queue_family_id,
+        use_dynamic_rendering,
+        use_synchronization2,
+        bindless_texture_limit,
+        memory_budget_supported,
+        conservative_rasterization_supported,
+        multiview_supported,
+        mesh_shader_supported,
+        task_shader_supported,
+        ray_tracing_supported,
+        buffer_device_address_supported,
+        device_features,
+      })
+    }
+  }
+
+  /// Sets up the surface, swapchain, allocator and pools on an already created device
+  fn from_device(
+    window: &(impl HasWindowHandle + HasDisplayHandle),
+    config: VulkanBackendConfig,
+    setup: DeviceSetup,
+  ) -> Result<Self, rhi::RhiError> {
+    let DeviceSetup {
+      ash_entry,
+      ash_instance,
+      debug_utils,
+      gpu,
+      gpu_info,
+      ash_device,
+      graphics_queue_family_id,
+      transfer_queue_family_id,
+      use_dynamic_rendering,
+      use_synchronization2,
+      bindless_texture_limit,
+      memory_budget_supported,
+      conservative_rasterization_supported,
+      multiview_supported,
+      mesh_shader_supported,
+      task_shader_supported,
+      ray_tracing_supported,
+      buffer_device_address_supported,
+      device_features,
+    } = setup;
+    unsafe {
+      let gpu_limits = ash_instance.get_physical_device_properties(gpu).limits;
+      let timestamp_period = gpu_limits.timestamp_period;
+      let graphics_queue = ash_device.get_device_queue(graphics_queue_family_id, 0);
+      let transfer_queue = ash_device.get_device_queue(transfer_queue_family_id, 0);
+      let surface_instance = khr::surface::Instance::new(&ash_entry, &ash_instance);
+      let surface = ash_window::create_surface(
+        &ash_entry,
+        &ash_instance,
+        window
+          .display_handle()
+          .map_err(|_| rhi::RhiError::BackendError("invalid window".to_string()))?
+          .as_raw(),
+        window
+          .window_handle()
+          .map_err(|_| rhi::RhiError::BackendError("invalid window".to_string()))?
+          .as_raw(),
+        None
+      )
+        .map_err(|e| translate_vk_error("at surface creation", e))?;
+      let swapchain_device = khr::swapchain::Device::new(&ash_instance, &ash_device);
+      let dynamic_rendering_device = use_dynamic_rendering
+        .then(|| khr::dynamic_rendering::Device::new(&ash_instance, &ash_device));
+      let synchronization2_device = use_synchronization2
+        .then(|| khr::synchronization2::Device::new(&ash_instance, &ash_device));
+      let mesh_shader_device = mesh_shader_supported
+        .then(|| ext::mesh_shader::Device::new(&ash_instance, &ash_device));
+      let ray_tracing_device = ray_tracing_supported
+        .then(|| khr::ray_tracing_pipeline::Device::new(&ash_instance, &ash_device));
+      let buffer_device_address_device = buffer_device_address_supported
+        .then(|| khr::buffer_device_address::Device::new(&ash_instance, &ash_device));
+      let accel_struct_device = ray_tracing_supported
+        .then(|| khr::acceleration_structure::Device::new(&ash_instance, &ash_device));
+      let mut ray_tracing_properties = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
+      let mut accel_struct_properties =
+        vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
+      if ray_tracing_supported {
+        let mut properties = vk::PhysicalDeviceProperties2KHR::default()
+          .push_next(&mut ray_tracing_properties)
+          .push_next(&mut accel_struct_properties);
+        khr::get_physical_device_properties2::Instance::new(&ash_entry, &ash_instance)
+          .get_physical_device_properties2(gpu, &mut properties);
+      }
+      let debug_utils_device = debug_utils
+        .is_some()
+        .then(|| ext::debug_utils::Device::new(&ash_instance, &ash_device));
+
+      let allocator = Allocator::new(
+        &AllocatorCreateDesc {
+          instance: ash_instance.clone(),
+          device: ash_device.clone(),
+          physical_device: gpu,
+          debug_settings: Default::default(),
+          buffer_device_address: buffer_device_address_supported,
+          allocation_sizes: Default::default(),
+        }
+      )
+        .map_err(|e| rhi::RhiError::AllocationFailed(format!("at allocator create: {e}")))?;
+
+      let descriptor_pool =
+        helpers::create_descriptor_pool(
+          &ash_device,
+          DESCRIPTOR_POOL_BASE_SETS,
+          bindless_texture_limit.is_some()
+        )?;
+      let pipeline_cache_path = config.pipeline_cache_path;
+      // Not `tokio::fs`, `new` is synchronous and may run outside a tokio runtime, which
+      // `tokio::fs` needs. It's a single read at startup
+      let pipeline_cache_data = pipeline_cache_path
+        .as_ref()
+        .and_then(|path| std::fs::read(path).ok())
+        .unwrap_or_default();
+      // A blob from another driver or GPU may be rejected, fall back to an empty cache then
+      let pipeline_cache = ash_device
+        .create_pipeline_cache(
+          &vk::PipelineCacheCreateInfo::default().initial_data(&pipeline_cache_data),
+          None
         )
-        .map_err(|e| format!("at vk descriptor pool create: {e}"))?;
+        .or_else(|_| ash_device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None))
+        .map_err(|e| translate_vk_error("at pipeline cache create", e))?;
       let command_pool_info = vk::CommandPoolCreateInfo::default()
         .queue_family_index(graphics_queue_family_id)
         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
       let command_pool = ash_device
         .create_command_pool(&command_pool_info, None)
-        .map_err(|e| format!("at command pool create: {e}"))?;
-      let (swapchain_res, surface_format, swapchain_image_count, swapchain) =
-        helpers::make_swapchain(gpu, &surface_instance, surface, &swapchain_device)?;
-      let swapchain_images_vk = swapchain_device
-        .get_swapchain_images(swapchain)
-        .map_err(|e| format!("at getting swapchain images: {e}"))?;
-      let swapchain_image_views = swapchain_images_vk.iter().map(|x| {
-        ash_device.create_image_view(
-          &vk::ImageViewCreateInfo::default()
-            .image(*x)
-            .format(surface_format.format)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .subresource_range(
-              vk::ImageSubresourceRange::default()
-                .aspect_mask(get_aspect_mask(rhi::ImageFormat::Presentation))
-                .base_array_layer(0)
-                .layer_count(1)
-                .base_mip_level(0)
-                .level_count(1)
-            ),
+        .map_err(|e| translate_vk_error("at command pool create", e))?;
+      let transfer_command_pool = ash_device
+        .create_command_pool(
+          &vk::CommandPoolCreateInfo::default()
+            .queue_family_index(transfer_queue_family_id)
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT),
           None
-        ).map_err(|e| format!("at swapchain image view: {e}"))
-      }).collect::<Result<Vec<_>, String>>()?;
-      let mut images = SequentialIDStore::new(1024);
-      let swapchain_images = (0..swapchain_images_vk.len())
-        .map(|i| {
-          let a_image = AllocatedTexture{
-            image: swapchain_images_vk[i],
-            view: swapchain_image_views[i],
-            resolution: rhi::Resolution2D { width: swapchain_res.width, height: swapchain_res.height },
-            format: rhi::ImageFormat::Presentation,
-            allocation: None
-          };
-          images.add_obj(a_image).map(rhi::ImageID)
-        })
-        .collect::<Result<Vec<_>, &str>>()?;
+        )
+        .map_err(|e| translate_vk_error("at transfer command pool create", e))?;
+      let preferred_present_mode = config.preferred_present_mode;
+      let (swapchain_res, surface_format, _, present_mode, swapchain) = helpers::make_swapchain(
+        gpu,
+        &surface_instance,
+        surface,
+        &swapchain_device,
+        vk::Extent2D::default(),
+        translate_present_mode(preferred_present_mode),
+        vk::SwapchainKHR::null()
+      )?;
 
-      Ok(Self {
+      let mut backend = Self {
         command_buffers: SequentialIDStore::new(256),
         command_pool,
+        transfer_command_pool,
+        staging_pool: StagingBufferPool::default(),
         fences: SequentialIDStore::new(256),
+        semaphores: SequentialIDStore::new(256),
         descriptor_sets: SequentialIDStore::new(512),
         frame_buffers: SequentialIDStore::new(256),
+        shaders: SequentialIDStore::new(64),
         pipelines: SequentialIDStore::new(32),
-        images,
+        compute_pipelines: SequentialIDStore::new(32),
+        ray_tracing_pipelines: SequentialIDStore::new(32),
+        accel_structs: SequentialIDStore::new(1024),
+        images: SequentialIDStore::new(1024),
+        image_views: SequentialIDStore::new(1024),
+        samplers: SequentialIDStore::new(64),
+        query_pools: SequentialIDStore::new(16),
         buffers: SequentialIDStore::new(1024),
         allocator,
-        descriptor_pool,
+        descriptor_pools: vec![descriptor_pool],
+        last_descriptor_pool_max_sets: DESCRIPTOR_POOL_BASE_SETS,
+        pipeline_cache,
+        pipeline_cache_path,
         graphics_queue,
         graphics_queue_family_id,
+        transfer_queue,
+        transfer_queue_family_id,
         gpu,
-        swapchain_images,
+        gpu_info,
+        device_features,
+        memory_budget_supported,
+        conservative_rasterization_supported,
+        multiview_supported,
+        bindless_texture_limit,
+        max_uniform_buffer_range: gpu_limits.max_uniform_buffer_range,
+        timestamp_period,
+        swapchain_images: vec![],
         swapchain,
         swapchain_res,
         surface_format,
+        preferred_present_mode,
+        present_mode,
         swapchain_device,
+        dynamic_rendering_device,
+        synchronization2_device,
+        mesh_shader_device,
+        task_shader_supported,
+        ray_tracing_device,
+        ray_tracing_properties,
+        accel_struct_device,
+        accel_struct_scratch_alignment: accel_struct_properties
+          .min_acceleration_structure_scratch_offset_alignment,
+        buffer_device_address_device,
+        debug_utils_device,
         surface,
         ash_device,
         surface_instance,
+        debug_utils,
+        #[cfg(feature = "openxr-backend")]
+        xr: None,
+        #[cfg(feature = "renderdoc")]
+        renderdoc: capture::load_renderdoc(),
+        #[cfg(feature = "tracy")]
+        tracy: tracy_client::Client::start(),
+        #[cfg(feature = "hot-reload")]
+        shader_paths: HashMap::new(),
+        #[cfg(feature = "hot-reload")]
+        shader_watcher: None,
         ash_instance,
         ash_entry,
+      };
+      backend.register_swapchain_images()?;
+      Ok(backend)
+    }
+  }
+
+  unsafe fn register_swapchain_images(&mut self) -> Result<(), rhi::RhiError> {
+    let swapchain_images_vk = self
+      .swapchain_device
+      .get_swapchain_images(self.swapchain)
+      .map_err(|e| translate_vk_error("at getting swapchain images", e))?;
+    let resolution = rhi::Resolution2D {
+      width: self.swapchain_res.width,
+      height: self.swapchain_res.height
+    };
+    self.swapchain_images = swapchain_images_vk
+      .into_iter()
+      .map(|image| {
+        let view = self.ash_device.create_image_view(
+          &vk::ImageViewCreateInfo::default()
+            .image(image)
+            .format(self.surface_format.format)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .subresource_range(
+              vk::ImageSubresourceRange::default()
+                .aspect_mask(get_aspect_mask(rhi::ImageFormat::Presentation))
+                .base_array_layer(0)
+                .layer_count(1)
+                .base_mip_level(0)
+                .level_count(1)
+            ),
+          None
+        ).map_err(|e| translate_vk_error("at swapchain image view", e))?;
+        let a_image = AllocatedTexture{
+          image,
+          view,
+          resolution,
+          format: rhi::ImageFormat::Presentation,
+          depth: 1,
+          mip_levels: 1,
+          layer_count: 1,
+          is_cube: false,
+          allocation: None
+        };
+        self.images.add_obj(a_image).map(rhi::ImageID).map_err(rhi::RhiError::from)
       })
+      .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+    Ok(())
+  }
+
+  /// Images of the OpenXR swapchain, empty without an OpenXR session
+  pub fn xr_swapchain_images(&self) -> Vec<rhi::ImageID> {
+    #[cfg(feature = "openxr-backend")]
+    if let Some(xr) = self.xr.as_ref() {
+      return xr.images.clone();
     }
+    vec![]
   }
 
-  fn destroy_image(&mut self, image_id: rhi::ImageID) -> Result<(), String> {
-    let rhi::ImageID(image_id) = image_id;
-    let a_image = self.images.remove_obj(image_id)?;
-    unsafe {
+  /// Swapchain images are owned by the swapchain, so only their views get destroyed here
+  unsafe fn release_swapchain_images(&mut self) -> Result<(), rhi::RhiError> {
+    for image_id in std::mem::take(&mut self.swapchain_images) {
+      let a_image = self.images.remove_obj(image_id.0)?;
       self.ash_device.destroy_image_view(a_image.view, None);
-      self.ash_device.destroy_image(a_image.image, None);
-      a_image.allocation.map(|a| self.allocator.free(a));
     }
     Ok(())
   }
 
-  fn destroy_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<(), String> {
-    let rhi::BufferID(buffer_id) = buffer_id;
-    let a_buffer = self.buffers.remove_obj(buffer_id)?;
+  /// Copies a color image, or the depth aspect of a depth image, back to the CPU and waits for it.
+  /// Like a first `CopyImageToBuffer` in a command buffer, the image is expected to already be in
+  /// the transfer source layout, e.g. through a `PipelineBarrier` to `ImageLayoutType::TransferSrc`
+  pub fn readback_texture(&mut self, image_id: rhi::ImageID) -> Result<Vec<u8>, rhi::RhiError> {
+    let (image, resolution, format) = self
+      .images
+      .get_obj(image_id.0)
+      .map(|x| (x.image, x.resolution, x.format))?;
+    let size = resolution.width as u64 * resolution.height as u64 * get_texel_size(format);
+    let aspect_mask = if get_aspect_mask(format).contains(vk::ImageAspectFlags::DEPTH) {
+      vk::ImageAspectFlags::DEPTH
+    } else {
+      vk::ImageAspectFlags::COLOR
+    };
     unsafe {
-      self.ash_device.destroy_buffer(a_buffer.buffer, None);
-      a_buffer.allocation.map(|a| self.allocator.free(a));
+      let buffer = self
+        .ash_device
+        .create_buffer(
+          &vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(vk::BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE),
+          None
+        )
+        .map_err(|e| translate_vk_error("at vk readback buffer create", e))?;
+      let allocation = match self.allocator.allocate(
+        &AllocationCreateDesc{
+          name: "readback_buffer",
+          requirements: self.ash_device.get_buffer_memory_requirements(buffer),
+          location: MemoryLocation::GpuToCpu,
+          linear: true,
+          allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        }) {
+        Ok(allocation) => allocation,
+        Err(e) => {
+          self.ash_device.destroy_buffer(buffer, None);
+          return Err(rhi::RhiError::AllocationFailed(format!("at readback allocator alloc: {e}")));
+        }
+      };
+      let result = self
+        .ash_device
+        .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        .map_err(|e| translate_vk_error("at bind readback buffer memory", e))
+        .and_then(|_| {
+          self.run_one_shot_commands(self.command_pool, self.graphics_queue, |command_buffer| {
+            self.ash_device.cmd_copy_image_to_buffer(
+              command_buffer,
+              image,
+              vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+              buffer,
+              &[
+                vk::BufferImageCopy::default()
+                  .buffer_offset(0)
+                  .buffer_row_length(0)
+                  .buffer_image_height(0)
+                  .image_offset(vk::Offset3D::default())
+                  .image_extent(vk::Extent3D {
+                    width: resolution.width,
+                    height: resolution.height,
+                    depth: 1
+                  })
+                  .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(aspect_mask)
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .mip_level(0)
+                  )
+              ]
+            );
+          })
+        })
+        .and_then(|_| {
+          allocation
+            .mapped_slice()
+            .map(|x| x[..size as usize].to_vec())
+            .ok_or(rhi::RhiError::BackendError(
+              "at readback texture: readback buffer not mapped".to_string()
+            ))
+        });
+      self.ash_device.destroy_buffer(buffer, None);
+      self
+        .allocator
+        .free(allocation)
+        .map_err(|e| rhi::RhiError::AllocationFailed(format!("at readback allocator free: {e}")))?;
+      result
     }
-    Ok(())
   }
 
-  unsafe fn create_render_pass(
+  unsafe fn set_object_name<H: vk::Handle>(
     &self,
-    color_attachment_formats: &[rhi::ImageFormat],
-    depth_attachment_formats: Option<&rhi::ImageFormat>,
-  ) -> Result<vk::RenderPass, String> {
-    let mut attachments = color_attachment_formats
-      .into_iter()
-      .map(|x| vk::AttachmentDescription::default()
-        .format(translate_image_format(*x))
-        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-        .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
-        .samples(vk::SampleCountFlags::TYPE_1)
-      )
-      .collect::<Vec<_>>();
-    depth_attachment_formats.map(|x| attachments.push(
-      vk::AttachmentDescription::default()
-        .format(translate_image_format(*x))
-        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-        .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
-        .stencil_store_op(vk::AttachmentStoreOp::STORE)
-        .samples(vk::SampleCountFlags::TYPE_1)
-    ));
-    let subpass_color_attach_infos = (0..color_attachment_formats.len() as u32)
-      .map(|i| vk::AttachmentReference::default()
-        .attachment(i)
-        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+    handle: H,
+    name: &str
+  ) -> Result<(), rhi::RhiError> {
+    let Some(debug_utils_device) = self.debug_utils_device.as_ref() else {
+      return Ok(());
+    };
+    let name = CString::new(name)
+      .map_err(|e| rhi::RhiError::BackendError(format!("at debug name: {e}")))?;
+    debug_utils_device
+      .set_debug_utils_object_name(
+        &vk::DebugUtilsObjectNameInfoEXT::default()
+          .object_handle(handle)
+          .object_name(&name)
       )
-      .collect::<Vec<_>>();
-    let subpass_depth_attach_info = depth_attachment_formats
-      .map(|_| vk::AttachmentReference::default()
-        .attachment(color_attachment_formats.len() as _)
-        .layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
-      );
-    let subpass_desc = vk::SubpassDescription::default()
-      .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-      .color_attachments(&subpass_color_attach_infos);
-    let subpass_desc = match subpass_depth_attach_info.as_ref() {
-      None => subpass_desc,
-      Some(x) => {
-        subpass_desc.depth_stencil_attachment(x)},
+      .map_err(|e| translate_vk_error("at set debug utils object name", e))
+  }
+
+  /// Ends a frame in Tracy, every present does this
+  #[cfg(feature = "tracy")]
+  pub fn frame_mark(&self) {
+    self.tracy.frame_mark();
+  }
+
+  /// Buffers of `create_buffer`, and internal ones with usages the RHI doesn't expose
+  unsafe fn create_buffer_vk(
+    &mut self,
+    size: u64,
+    usage: vk::BufferUsageFlags,
+    memory_location: rhi::MemoryLocation
+  ) -> Result<rhi::BufferID, rhi::RhiError> {
+    // Shared between the graphics and transfer queues so uploads don't need ownership transfers
+    let queue_family_ids = [self.graphics_queue_family_id, self.transfer_queue_family_id];
+    let buffer_create_info = vk::BufferCreateInfo::default()
+      .size(size)
+      .usage(usage);
+    let buffer_create_info = if self.graphics_queue_family_id == self.transfer_queue_family_id {
+      buffer_create_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+    } else {
+      buffer_create_info
+        .sharing_mode(vk::SharingMode::CONCURRENT)
+        .queue_family_indices(&queue_family_ids)
     };
-    let subpass_descs = [subpass_desc];
-    let render_pass_create_info = vk::RenderPassCreateInfo::default()
-      .attachments(&attachments)
-      .subpasses(&subpass_descs);
+    let buffer = self
+      .ash_device
+      .create_buffer(&buffer_create_info, None)
+      .map_err(|e| translate_vk_error("at vk buffer create", e))?;
+    let memory_requirements = self.ash_device.get_buffer_memory_requirements(buffer);
+    let a_buffer = AllocatedBuffer{
+      buffer,
+      size,
+      allocation: None,
+    };
+    let buffer_id_u32 = self
+      .buffers
+      .add_obj(a_buffer)
+      .map_err(|e| rhi::RhiError::AllocationFailed(format!("max buffer count reached: {e}")))?;
+    let name = format!("buffer_{buffer_id_u32}");
+    self.set_object_name(buffer, &name)?;
+    let allocation = self
+      .allocator
+      .allocate(
+        &AllocationCreateDesc{
+          name: &name,
+          requirements: memory_requirements,
+          location: translate_memory_location(memory_location),
+          // Buffers are linear resources, this also keeps CPU visible ones persistently mapped
+          linear: true,
+          allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })
+      .map_err(|e| self.allocation_error(memory_requirements.size, e))?;
     self
       .ash_device
-      .create_render_pass(&render_pass_create_info, None)
-      .map_err(|e| format!("at render pass creation: {e}"))
+      .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+      .map_err(|e| translate_vk_error("at bind buffer memory", e))?;
+    self.buffers.get_obj_mut(buffer_id_u32)?.allocation = Some(allocation);
+    Ok(rhi::BufferID(buffer_id_u32))
   }
-}
 
-impl rhi::RenderBackend for VulkanBackend {
-  fn get_swapchain_info(&self) -> rhi::SwapchainInfo {
-    rhi::SwapchainInfo{
-      res: rhi::Resolution2D{width: self.swapchain_res.width, height: self.swapchain_res.height},
-      image_count: self.swapchain_images.len() as _,
-    }
+  /// Needs buffer device addresses, which ray tracing comes with
+  unsafe fn buffer_address(&self, buffer: rhi::BufferID) -> Result<u64, rhi::RhiError> {
+    let buffer_vk = self.buffers.get_obj(buffer.0)?.buffer;
+    let device = self.buffer_device_address_device.as_ref().ok_or(
+      rhi::RhiError::BackendError("at buffer address: no VK_KHR_buffer_device_address".to_string())
+    )?;
+    Ok(device.get_buffer_device_address(
+      &vk::BufferDeviceAddressInfoKHR::default().buffer(buffer_vk)
+    ))
   }
 
-  fn create_buffer(
+  /// Makes the shader binding table of `pipeline`, whose groups are one raygen group followed by
+  /// `miss_count` miss and `hit_count` hit groups. Returns the table's buffer with its raygen,
+  /// miss and hit regions
+  unsafe fn create_shader_binding_table(
     &mut self,
-    size: u64,
-    usage: rhi::BufferUsage,
-    memory_location: rhi::MemoryLocation
-  ) -> Result<rhi::BufferID, String> {
-    unsafe {
-      let buffer_create_info = vk::BufferCreateInfo::default()
-        .size(size)
-        .usage(translate_buffer_usage(usage))
-        .sharing_mode(vk::SharingMode::EXCLUSIVE);
-      let buffer = self
-        .ash_device
-        .create_buffer(&buffer_create_info, None)
-        .map_err(|e| format!("at vk buffer create: {e}"))?;
-      let memory_requirements = self.ash_device.get_buffer_memory_requirements(buffer);
-      let a_buffer = AllocatedBuffer{
-        buffer,
-        size,
-        allocation: None,
-      };
-      let buffer_id_u32 = self
-        .buffers
-        .add_obj(a_buffer)
-        .map_err(|e| format!("max buffer count reached: {e}"))?;
-      let allocation = self
-        .allocator
-        .allocate(
-          &AllocationCreateDesc{
-            name: &format!("buffer_{buffer_id_u32}"),
-            requirements: memory_requirements,
-            location: translate_memory_location(memory_location),
-            linear: false,
-            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-          })
-        .map_err(|e| format!("at allocator alloc: {e}"))?;
-      self.buffers.get_obj_mut(buffer_id_u32)?.allocation = Some(allocation);
-      Ok(rhi::BufferID(buffer_id_u32))
+    pipeline: vk::Pipeline,
+    miss_count: usize,
+    hit_count: usize
+  ) -> Result<(rhi::BufferID, [vk::StridedDeviceAddressRegionKHR; 3]), rhi::RhiError> {
+    let ray_tracing_device = self.ray_tracing_device.as_ref().ok_or(rhi::RhiError::BackendError(
+      "at create shader binding table: needs VK_KHR_ray_tracing_pipeline".to_string()
+    ))?;
+    let props = self.ray_tracing_properties;
+    let group_count = 1 + miss_count + hit_count;
+    // Every region starts at a base alignment boundary
+    let align = |x: u64, alignment: u32| x.div_ceil(alignment as u64) * alignment as u64;
+    let handle_size = props.shader_group_handle_size as usize;
+    let handle_stride = align(handle_size as u64, props.shader_group_handle_alignment);
+    let raygen_size = align(handle_stride, props.shader_group_base_alignment);
+    let miss_size = align(handle_stride * miss_count as u64, props.shader_group_base_alignment);
+    let hit_size = align(handle_stride * hit_count as u64, props.shader_group_base_alignment);
+    let handles = ray_tracing_device
+      .get_ray_tracing_shader_group_handles(
+        pipeline,
+        0,
+        group_count as u32,
+        group_count * handle_size
+      )
+      .map_err(|e| translate_vk_error("at get shader group handles", e))?;
+    // Buffer alignment can be lower than the base alignment, the table starts at the first
+    // aligned address
+    let sbt_size = raygen_size + miss_size + hit_size + props.shader_group_base_alignment as u64;
+    let sbt_buffer = self.create_buffer_vk(
+      sbt_size,
+      vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+      rhi::MemoryLocation::CPUToGPU
+    )?;
+    let mapped = self
+      .buffer_address(sbt_buffer)
+      .and_then(|address| Ok((address, rhi::RenderBackend::map_buffer(self, sbt_buffer)?)));
+    let (sbt_buffer_address, sbt_ptr) = match mapped {
+      Ok(x) => x,
+      Err(e) => {
+        rhi::RenderBackend::destroy_buffer(self, sbt_buffer)?;
+        return Err(e);
+      }
+    };
+    let sbt_address = align(sbt_buffer_address, props.shader_group_base_alignment);
+    let sbt_ptr = sbt_ptr.add((sbt_address - sbt_buffer_address) as usize);
+    // Raygen, miss and hit handles in group order, each region's handles `handle_stride` apart
+    let region_starts = std::iter::once(0)
+      .chain((0..miss_count as u64).map(|i| raygen_size + i * handle_stride))
+      .chain((0..hit_count as u64).map(|i| raygen_size + miss_size + i * handle_stride));
+    for (handle, start) in handles.chunks_exact(handle_size).zip(region_starts) {
+      std::ptr::copy_nonoverlapping(handle.as_ptr(), sbt_ptr.add(start as usize), handle_size);
     }
+    let region = |offset: u64, stride: u64, size: u64| vk::StridedDeviceAddressRegionKHR {
+      device_address: if size == 0 { 0 } else { sbt_address + offset },
+      stride,
+      size,
+    };
+    Ok((sbt_buffer, [
+      // The raygen region's stride has to match its size
+      region(0, raygen_size, raygen_size),
+      region(raygen_size, handle_stride, miss_size),
+      region(raygen_size + miss_size, handle_stride, hit_size),
+    ]))
   }
 
-  fn create_texture_2d(
+  /// Creates the structure with buffers sized for `geometry` and builds it on the graphics queue.
+  /// Builds are compute work, which the transfer queue needn't support, and there is no separate
+  /// compute queue
+  unsafe fn build_accel_struct(
     &mut self,
-    res: rhi::Resolution2D,
-    format: rhi::ImageFormat,
-    usage: rhi::ImageUsage,
-    memory_location: rhi::MemoryLocation
-  ) -> Result<rhi::ImageID, String> {
-    unsafe {
-      let image_create_info = vk::ImageCreateInfo::default()
-        .image_type(vk::ImageType::TYPE_2D)
-        .format(translate_image_format(format))
-        .usage(translate_image_usage(usage))
-        .sharing_mode(vk::SharingMode::EXCLUSIVE)
-        .initial_layout(vk::ImageLayout::UNDEFINED)
-        .tiling(vk::ImageTiling::OPTIMAL)
-        .extent(vk::Extent3D { width: res.width, height: res.height, depth: 1, });
-      let image = self
-        .ash_device
-        .create_image(&image_create_info, None)
-        .map_err(|e| format!("at vk image create: {e}"))?;
-      let view = self
-        .ash_device
-        .create_image_view(
-          &vk::ImageViewCreateInfo::default()
-            .image(image)
-            .format(translate_image_format(format))
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .subresource_range(
-              vk::ImageSubresourceRange::default()
-                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                .base_array_layer(0)
-                .layer_count(1)
-                .base_mip_level(0)
-                .level_count(1)
-            ),
-          None
-        )
-        .map_err(|e| format!("at view creation: {e}"))?;
-      let memory_requirements = self.ash_device.get_image_memory_requirements(image);
-      let a_image = AllocatedTexture{
-        image,
-        view,
-        resolution: res,
-        format,
-        allocation: None,
-      };
-      let image_id_u32 = self
-        .images
-        .add_obj(a_image)
-        .map_err(|e| format!("max image count reached: {e}"))?;
-      let allocation = self
-        .allocator
-        .allocate(
-          &AllocationCreateDesc{
-            name: &format!("image_{image_id_u32}"),
-            requirements: memory_requirements,
-            location: translate_memory_location(memory_location),
-            linear: false,
-            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-          })
-        .map_err(|e| format!("at allocator alloc: {e}"))?;
-      self.images.get_obj_mut(image_id_u32)?.allocation = Some(allocation);
-      Ok(rhi::ImageID(image_id_u32))
+    ty: vk::AccelerationStructureTypeKHR,
+    geometry: vk::AccelerationStructureGeometryKHR,
+    primitive_count: u32,
+    at: &str
+  ) -> Result<rhi::AccelStructID, rhi::RhiError> {
+    let accel_struct_device = self.accel_struct_device.clone().ok_or(
+      rhi::RhiError::BackendError(format!(
+        "{at}: needs VK_KHR_acceleration_structure, which the GPU lacks"
+      ))
+    )?;
+    let geometries = [geometry];
+    let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+      .ty(ty)
+      .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+      .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+      .geometries(&geometries);
+    let mut sizes = vk::AccelerationStructureBuildSizesInfoKHR::default();
+    accel_struct_device.get_acceleration_structure_build_sizes(
+      vk::AccelerationStructureBuildTypeKHR::DEVICE,
+      &build_info,
+      &[primitive_count],
+      &mut sizes
+    );
+    let buffer = self.create_buffer_vk(
+      sizes.acceleration_structure_size,
+      vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR |
+        vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+      rhi::MemoryLocation::GPU
+    )?;
+    let accel_struct = accel_struct_device
+      .create_acceleration_structure(
+        &vk::AccelerationStructureCreateInfoKHR::default()
+          .buffer(self.buffers.get_obj(buffer.0)?.buffer)
+          .size(sizes.acceleration_structure_size)
+          .ty(ty),
+        None
+      )
+      .map_err(|e| translate_vk_error(&format!("{at}: create acceleration structure"), e));
+    let accel_struct = match accel_struct {
+      Ok(x) => x,
+      Err(e) => {
+        rhi::RenderBackend::destroy_buffer(self, buffer)?;
+        return Err(e);
+      }
+    };
+    // Scratch space is only needed while building, at an aligned address
+    let scratch_alignment = self.accel_struct_scratch_alignment as u64;
+    let scratch_buffer = match self.create_buffer_vk(
+      sizes.build_scratch_size + scratch_alignment,
+      vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+      rhi::MemoryLocation::GPU
+    ) {
+      Ok(x) => x,
+      Err(e) => {
+        accel_struct_device.destroy_acceleration_structure(accel_struct, None);
+        rhi::RenderBackend::destroy_buffer(self, buffer)?;
+        return Err(e);
+      }
+    };
+    let scratch_address = match self.buffer_address(scratch_buffer) {
+      Ok(x) => x.div_ceil(scratch_alignment) * scratch_alignment,
+      Err(e) => {
+        rhi::RenderBackend::destroy_buffer(self, scratch_buffer)?;
+        accel_struct_device.destroy_acceleration_structure(accel_struct, None);
+        rhi::RenderBackend::destroy_buffer(self, buffer)?;
+        return Err(e);
+      }
+    };
+    let build_info = build_info
+      .dst_acceleration_structure(accel_struct)
+      .scratch_data(vk::DeviceOrHostAddressKHR { device_address: scratch_address });
+    let build_range = vk::AccelerationStructureBuildRangeInfoKHR::default()
+      .primitive_count(primitive_count);
+    let built = self.run_one_shot_commands(self.command_pool, self.graphics_queue, |cmd| {
+      accel_struct_device.cmd_build_acceleration_structures(cmd, &[build_info], &[&[build_range]]);
+    });
+    rhi::RenderBackend::destroy_buffer(self, scratch_buffer)?;
+    if let Err(e) = built {
+      accel_struct_device.destroy_acceleration_structure(accel_struct, None);
+      rhi::RenderBackend::destroy_buffer(self, buffer)?;
+      return Err(e);
     }
+    let address = accel_struct_device.get_acceleration_structure_device_address(
+      &vk::AccelerationStructureDeviceAddressInfoKHR::default().acceleration_structure(accel_struct)
+    );
+    let id = self.accel_structs.add_obj(AllocatedAccelStruct { accel_struct, buffer, address })?;
+    Ok(rhi::AccelStructID(id))
   }
 
-  async fn create_graphics_pipeline(
-    &mut self,
-    raster_style: rhi::RasterStyle,
-    color_attachment_formats: Vec<rhi::ImageFormat>,
-    depth_attachment_formats: Option<rhi::ImageFormat>,
+  /// `None` when markers can't be emitted, `VK_EXT_debug_utils` is only loaded in debug builds
+  fn debug_label(
+    &self,
+    label: &str,
+    at: &str
+  ) -> Result<Option<(&ext::debug_utils::Device, CString)>, rhi::RhiError> {
+    let Some(debug_utils_device) = self.debug_utils_device.as_ref() else {
+      return Ok(None);
+    };
+    let label = CString::new(label)
+      .map_err(|e| rhi::RhiError::BackendError(format!("{at}: bad debug label: {e}")))?;
+    Ok(Some((debug_utils_device, label)))
+  }
+
+  /// Builds the `vk::Pipeline` of a graphics pipeline. `extra_stages` are the shaders besides the
+  /// vertex or mesh shader and the fragment shader, they get no specialization constants
+  unsafe fn build_graphics_pipeline_vk(
+    &self,
+    state: &GraphicsPipelineState,
+    layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+    first_stage: (vk::ShaderStageFlags, vk::ShaderModule),
+    fragment_shader_vk: vk::ShaderModule,
+    extra_stages: &[(vk::ShaderStageFlags, vk::ShaderModule)],
+  ) -> Result<vk::Pipeline, rhi::RhiError> {
+    // Vertices are read from storage buffers, only per instance data is a vertex input
+    let instance_bindings = state
+      .instance_input
+      .iter()
+      .map(|x| vk::VertexInputBindingDescription {
+        binding: 1,
+        stride: x.stride,
+        input_rate: vk::VertexInputRate::INSTANCE,
+      })
+      .collect::<Vec<_>>();
+    let instance_attributes = state
+      .instance_input
+      .iter()
+      .flat_map(|x| x.attributes.iter())
+      .map(|x| vk::VertexInputAttributeDescription {
+        location: x.location,
+        binding: 1,
+        format: translate_image_format(x.format),
+        offset: x.offset,
+      })
+      .collect::<Vec<_>>();
+    let vert_input_info = vk::PipelineVertexInputStateCreateInfo::default()
+      .vertex_binding_descriptions(&instance_bindings)
+      .vertex_attribute_descriptions(&instance_attributes);
+    let topology = if state.patch_control_points.is_some() {
+      vk::PrimitiveTopology::PATCH_LIST
+    } else {
+      vk::PrimitiveTopology::TRIANGLE_LIST
+    };
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+      .topology(topology);
+    let tess_info = vk::PipelineTessellationStateCreateInfo::default()
+      .patch_control_points(state.patch_control_points.unwrap_or(0));
+    let dynamic_states = vk::PipelineDynamicStateCreateInfo::default()
+      .dynamic_states(&[
+        vk::DynamicState::VIEWPORT,
+        vk::DynamicState::SCISSOR,
+        vk::DynamicState::STENCIL_REFERENCE,
+        vk::DynamicState::DEPTH_BIAS,
+      ]);
+    let msaa_info = vk::PipelineMultisampleStateCreateInfo::default()
+      .rasterization_samples(translate_sample_count(state.multisample.sample_count))
+      .sample_shading_enable(false)
+      .alpha_to_coverage_enable(state.multisample.alpha_to_coverage)
+      .alpha_to_one_enable(state.multisample.alpha_to_one);
+    let vp_state = vk::PipelineViewportStateCreateInfo::default()
+      .viewport_count(1)
+      .scissor_count(1);
+    let mut conservative_info =
+      vk::PipelineRasterizationConservativeStateCreateInfoEXT::default()
+        .conservative_rasterization_mode(vk::ConservativeRasterizationModeEXT::OVERESTIMATE);
+    let raster_style_vk = translate_raster_config(state.raster_config);
+    let conservative =
+      matches!(state.raster_config.style, rhi::RasterStyle::FillConservative);
+    let raster_style_vk = if conservative {
+      raster_style_vk.push_next(&mut conservative_info)
+    } else {
+      raster_style_vk
+    };
+    let blend_attachments = state
+      .blend_states
+      .iter()
+      .copied()
+      .map(translate_blend_state)
+      .collect::<Vec<_>>();
+    let blend_info = vk::PipelineColorBlendStateCreateInfo::default()
+      .attachments(&blend_attachments);
+    let depth_info = translate_depth_state(state.depth_state);
+    let (vert_spec_entries, vert_spec_data) =
+      translate_spec_constants(&state.vertex_spec_constants);
+    let vert_spec_info = vk::SpecializationInfo::default()
+      .map_entries(&vert_spec_entries)
+      .data(&vert_spec_data);
+    let (frag_spec_entries, frag_spec_data) =
+      translate_spec_constants(&state.fragment_spec_constants);
+    let frag_spec_info = vk::SpecializationInfo::default()
+      .map_entries(&frag_spec_entries)
+      .data(&frag_spec_data);
+    let mut shader_stages = vec![
+      vk::PipelineShaderStageCreateInfo::default()
+        .name(c"main")
+        .stage(first_stage.0)
+        .module(first_stage.1)
+        .specialization_info(&vert_spec_info),
+      vk::PipelineShaderStageCreateInfo::default()
+        .name(c"main")
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(fragment_shader_vk)
+        .specialization_info(&frag_spec_info),
+    ];
+    shader_stages.extend(extra_stages.iter().map(|(stage, module)| {
+      vk::PipelineShaderStageCreateInfo::default().name(c"main").stage(*stage).module(*module)
+    }));
+    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
+      .render_pass(render_pass)
+      .subpass(0)
+      .layout(layout)
+      .vertex_input_state(&vert_input_info)
+      .input_assembly_state(&input_assembly_info)
+      .dynamic_state(&dynamic_states)
+      .multisample_state(&msaa_info)
+      .viewport_state(&vp_state)
+      .rasterization_state(&raster_style_vk)
+      .color_blend_state(&blend_info)
+      .depth_stencil_state(&depth_info)
+      .stages(&shader_stages);
+    let pipeline_create_info = if state.patch_control_points.is_some() {
+      pipeline_create_info.tessellation_state(&tess_info)
+    } else {
+      pipeline_create_info
+    };
+    let color_formats_vk = state
+      .color_formats
+      .iter()
+      .map(|x| translate_image_format(*x))
+      .collect::<Vec<_>>();
+    let depth_format = state.depth_format;
+    let depth_format_vk = depth_format
+      .map(translate_image_format)
+      .unwrap_or(vk::Format::UNDEFINED);
+    let stencil_format_vk = depth_format
+      .filter(|x| get_aspect_mask(*x).contains(vk::ImageAspectFlags::STENCIL))
+      .map(translate_image_format)
+      .unwrap_or(vk::Format::UNDEFINED);
+    let mut rendering_info = vk::PipelineRenderingCreateInfoKHR::default()
+      .color_attachment_formats(&color_formats_vk)
+      .depth_attachment_format(depth_format_vk)
+      .stencil_attachment_format(stencil_format_vk)
+      .view_mask(state.multiview_mask.unwrap_or(0));
+    let pipeline_create_info = if self.dynamic_rendering_device.is_some() {
+      pipeline_create_info.push_next(&mut rendering_info)
+    } else {
+      pipeline_create_info
+    };
+    let pipeline = self
+      .ash_device
+      .create_graphics_pipelines(
+        self.pipeline_cache,
+        &[pipeline_create_info],
+        None
+      )
+      .map_err(|e| translate_vk_error("at create pipeline", e.1))?
+      .remove(0);
+    Ok(pipeline)
+  }
+
+  fn create_shader_module(&mut self, code: &[u8]) -> Result<rhi::ShaderID, rhi::RhiError> {
+    let code = ash::util::read_spv(&mut std::io::Cursor::new(code))
+      .map_err(|e| rhi::RhiError::BackendError(format!("at read shader: {e}")))?;
+    let shader_vk = unsafe {
+      self
+        .ash_device
+        .create_shader_module(&vk::ShaderModuleCreateInfo::default().code(&code), None)
+        .map_err(|e| translate_vk_error("at shader module creation", e))?
+    };
+    let shader_id_u32 = self.shaders.add_obj(shader_vk)?;
+    Ok(rhi::ShaderID(shader_id_u32))
+  }
+
+  unsafe fn create_staging_buffer(&mut self, size: u64) -> Result<AllocatedBuffer, rhi::RhiError> {
+    let buffer = self
+      .ash_device
+      .create_buffer(
+        &vk::BufferCreateInfo::default()
+          .size(size)
+          .usage(vk::BufferUsageFlags::TRANSFER_SRC)
+          .sharing_mode(vk::SharingMode::EXCLUSIVE),
+        None
+      )
+      .map_err(|e| translate_vk_error("at vk staging buffer create", e))?;
+    let name = format!("staging_buffer_{}", self.staging_pool.buffers.len());
+    self.set_object_name(buffer, &name)?;
+    let allocation = self
+      .allocator
+      .allocate(
+        &AllocationCreateDesc{
+          name: &name,
+          requirements: self.ash_device.get_buffer_memory_requirements(buffer),
+          location: MemoryLocation::CpuToGpu,
+          linear: true,
+          allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+        })
+      .map_err(|e| rhi::RhiError::AllocationFailed(format!("at staging allocator alloc: {e}")))?;
+    self
+      .ash_device
+      .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+      .map_err(|e| translate_vk_error("at bind staging buffer memory", e))?;
+    Ok(AllocatedBuffer{ buffer, size, allocation: Some(allocation) })
+  }
+
+  /// Hands out the smallest free pooled buffer that fits, allocating a new bucket if none does.
+  /// Give it back with `release_staging_buffer` once the upload finished
+  unsafe fn acquire_staging_buffer(
+    &mut self,
+    min_size: u64
+  ) -> Result<(StagingPoolHandle, &mut AllocatedBuffer), rhi::RhiError> {
+    let bucket_size = min_size.max(STAGING_MIN_BUCKET_SIZE).next_power_of_two();
+    let index = match self.staging_pool.smallest_free(bucket_size) {
+      Some(i) => i,
+      None => {
+        let buffer = self.create_staging_buffer(bucket_size)?;
+        self.staging_pool.buffers.push(buffer);
+        self.staging_pool.in_use.push(false);
+        self.staging_pool.buffers.len() - 1
+      }
+    };
+    self.staging_pool.in_use[index] = true;
+    Ok((StagingPoolHandle(index), &mut self.staging_pool.buffers[index]))
+  }
+
+  fn release_staging_buffer(&mut self, handle: StagingPoolHandle) {
+    self.staging_pool.in_use[handle.0] = false;
+  }
+
+  /// Records the barriers through `VK_KHR_synchronization2` when the device has it, with the
+  /// stage masks of every barrier set to `src_stage` and `dst_stage`
+  unsafe fn emit_barriers(
+    &self,
+    command_buffer: vk::CommandBuffer,
+    src_stage: vk::PipelineStageFlags,
+    dst_stage: vk::PipelineStageFlags,
+    dependency_flags: vk::DependencyFlags,
+    buffer_barriers: &[vk::BufferMemoryBarrier2],
+    image_barriers: &[vk::ImageMemoryBarrier2],
+  ) {
+    match self.synchronization2_device.as_ref() {
+      Some(synchronization2_device) => {
+        // The old stages keep their bit values in synchronization2
+        let src_stage = vk::PipelineStageFlags2::from_raw(src_stage.as_raw() as u64);
+        let dst_stage = vk::PipelineStageFlags2::from_raw(dst_stage.as_raw() as u64);
+        let buffer_barriers = buffer_barriers
+          .iter()
+          .map(|x| x.src_stage_mask(src_stage).dst_stage_mask(dst_stage))
+          .collect::<Vec<_>>();
+        let image_barriers = image_barriers
+          .iter()
+          .map(|x| x.src_stage_mask(src_stage).dst_stage_mask(dst_stage))
+          .collect::<Vec<_>>();
+        synchronization2_device.cmd_pipeline_barrier2(
+          command_buffer,
+          &vk::DependencyInfo::default()
+            .dependency_flags(dependency_flags)
+            .buffer_memory_barriers(&buffer_barriers)
+            .image_memory_barriers(&image_barriers)
+        );
+      }
+      None => {
+        let buffer_barriers = buffer_barriers
+          .iter()
+          .map(|x| {
+            vk::BufferMemoryBarrier::default()
+              .buffer(x.buffer)
+              .offset(x.offset)
+              .size(x.size)
+              .src_access_mask(downgrade_access(x.src_access_mask))
+              .dst_access_mask(downgrade_access(x.dst_access_mask))
+              .src_queue_family_index(x.src_queue_family_index)
+              .dst_queue_family_index(x.dst_queue_family_index)
+          })
+          .collect::<Vec<_>>();
+        let image_barriers = image_barriers
+          .iter()
+          .map(|x| {
+            vk::ImageMemoryBarrier::default()
+              .image(x.image)
+              .old_layout(x.old_layout)
+              .new_layout(x.new_layout)
+              .src_access_mask(downgrade_access(x.src_access_mask))
+              .dst_access_mask(downgrade_access(x.dst_access_mask))
+              .src_queue_family_index(x.src_queue_family_index)
+              .dst_queue_family_index(x.dst_queue_family_index)
+              .subresource_range(x.subresource_range)
+          })
+          .collect::<Vec<_>>();
+        self.ash_device.cmd_pipeline_barrier(
+          command_buffer,
+          src_stage,
+          dst_stage,
+          dependency_flags,
+          &[],
+          &buffer_barriers,
+          &image_barriers
+        );
+      }
+    }
+  }
+
+  /// Makes a transfer write to part of a buffer visible to every later command
+  unsafe fn emit_buffer_write_barrier(
+    &self,
+    command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer,
+    offset: u64,
+    size: u64,
+  ) {
+    self.emit_barriers(
+      command_buffer,
+      vk::PipelineStageFlags::TRANSFER,
+      vk::PipelineStageFlags::ALL_COMMANDS,
+      vk::DependencyFlags::empty(),
+      &[vk::BufferMemoryBarrier2::default()
+        .buffer(buffer)
+        .offset(offset)
+        .size(size)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE)
+        .src_queue_family_index(self.graphics_queue_family_id)
+        .dst_queue_family_index(self.graphics_queue_family_id)],
+      &[]
+    );
+  }
+
+  unsafe fn run_transfer_commands(
+    &self,
+    record: impl FnOnce(vk::CommandBuffer)
+  ) -> Result<(), rhi::RhiError> {
+    self.run_one_shot_commands(self.transfer_command_pool, self.transfer_queue, record)
+  }
+
+  /// Records, submits and waits on a throwaway command buffer from `command_pool`
+  unsafe fn run_one_shot_commands(
+    &self,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    record: impl FnOnce(vk::CommandBuffer)
+  ) -> Result<(), rhi::RhiError> {
+    let command_buffer = self
+      .ash_device
+      .allocate_command_buffers(
+        &vk::CommandBufferAllocateInfo::default()
+          .command_pool(command_pool)
+          .level(vk::CommandBufferLevel::PRIMARY)
+          .command_buffer_count(1)
+      )
+      .map_err(|e| translate_vk_error("at allocate one shot command buffer", e))?[0];
+    let fence = self
+      .ash_device
+      .create_fence(&vk::FenceCreateInfo::default(), None)
+      .map_err(|e| translate_vk_error("at create one shot fence", e))?;
+    let result = self
+      .ash_device
+      .begin_command_buffer(
+        command_buffer,
+        &vk::CommandBufferBeginInfo::default().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+      )
+      .map_err(|e| translate_vk_error("at begin one shot command buffer", e))
+      .and_then(|_| {
+        record(command_buffer);
+        self
+          .ash_device
+          .end_command_buffer(command_buffer)
+          .map_err(|e| translate_vk_error("at end one shot command buffer", e))
+      })
+      .and_then(|_| {
+        self
+          .ash_device
+          .queue_submit(
+            queue,
+            &[vk::SubmitInfo::default().command_buffers(&[command_buffer])],
+            fence
+          )
+          .map_err(|e| translate_vk_error("at one shot queue submit", e))
+      })
+      .and_then(|_| {
+        self
+          .ash_device
+          .wait_for_fences(&[fence], true, u64::MAX)
+          .map_err(|e| translate_vk_error("at wait for one shot fence", e))
+      });
+    self.ash_device.destroy_fence(fence, None);
+    self.ash_device.free_command_buffers(command_pool, &[command_buffer]);
+    result
+  }
+
+  unsafe fn create_input_set_layouts(
+    &self,
     max_buffer_count: u32,
+    uniform_buffer_count: u32,
     max_texture_count: u32,
-    vertex_shader: PathBuf,
-    fragment_shader: PathBuf
-  ) -> Result<rhi::PipelineID, String> {
+    storage_image_count: u32,
+    stage_flags: vk::ShaderStageFlags,
+  ) -> Result<
+    (vk::DescriptorSetLayout, vk::DescriptorSetLayout, vk::DescriptorSetLayout),
+    rhi::RhiError
+  > {
+    let buffer_dset_bindings = [
+      vk::DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(max_buffer_count)
+        .stage_flags(stage_flags),
+      vk::DescriptorSetLayoutBinding::default()
+        .binding(1)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(uniform_buffer_count)
+        .stage_flags(stage_flags),
+    ];
+    let buffer_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+      .bindings(&buffer_dset_bindings);
+    if let Some(limit) = self.bindless_texture_limit {
+      if max_texture_count > limit {
+        return Err(rhi::RhiError::BackendError(format!(
+          "at texture set layout creation: {max_texture_count} textures exceed the limit {limit}"
+        )));
+      }
+    }
+    let buffer_set_layout = self
+      .ash_device
+      .create_descriptor_set_layout(&buffer_set_layout_info, None)
+      .map_err(|e| translate_vk_error("at buffer set layout creation", e))?;
+    // Bindless sets take their real texture count at allocation
+    let texture_dset_bindings = [
+      vk::DescriptorSetLayoutBinding::default()
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(self.bindless_texture_limit.unwrap_or(max_texture_count))
+        .stage_flags(stage_flags)
+    ];
+    let texture_binding_flags = [
+      vk::DescriptorBindingFlags::PARTIALLY_BOUND |
+        vk::DescriptorBindingFlags::UPDATE_AFTER_BIND |
+        vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+    ];
+    let mut texture_binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfoEXT::default()
+      .binding_flags(&texture_binding_flags);
+    let texture_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+      .bindings(&texture_dset_bindings);
+    let texture_set_layout_info = if self.bindless_texture_limit.is_some() {
+      texture_set_layout_info
+        .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+        .push_next(&mut texture_binding_flags_info)
+    } else {
+      texture_set_layout_info
+    };
+    let texture_set_layout =
+      match self.ash_device.create_descriptor_set_layout(&texture_set_layout_info, None) {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_descriptor_set_layout(buffer_set_layout, None);
+          return Err(translate_vk_error("at texture set layout creation", e));
+        }
+      };
+    let storage_image_dset_bindings = [
+      vk::DescriptorSetLayoutBinding::default()
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .descriptor_count(storage_image_count)
+        .stage_flags(stage_flags)
+    ];
+    let storage_image_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+      .bindings(&storage_image_dset_bindings);
+    let storage_image_set_layout =
+      match self.ash_device.create_descriptor_set_layout(&storage_image_set_layout_info, None) {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_descriptor_set_layout(buffer_set_layout, None);
+          self.ash_device.destroy_descriptor_set_layout(texture_set_layout, None);
+          return Err(translate_vk_error("at storage image set layout creation", e));
+        }
+      };
+    Ok((buffer_set_layout, texture_set_layout, storage_image_set_layout))
+  }
+
+  /// Undoes `create_input_set_layouts` and the pipeline layout made from its set layouts when
+  /// pipeline creation fails, a null `pipeline_layout` is skipped by Vulkan
+  unsafe fn destroy_pipeline_layouts(
+    &self,
+    pipeline_layout: vk::PipelineLayout,
+    set_layouts: [vk::DescriptorSetLayout; 3]
+  ) {
+    self.ash_device.destroy_pipeline_layout(pipeline_layout, None);
+    for set_layout in set_layouts {
+      self.ash_device.destroy_descriptor_set_layout(set_layout, None);
+    }
+  }
+
+  unsafe fn allocate_input_set(
+    &mut self,
+    buffer_set_layout: vk::DescriptorSetLayout,
+    texture_set_layout: vk::DescriptorSetLayout,
+    storage_image_set_layout: vk::DescriptorSetLayout,
+    uniform_buffer_count: u32,
+    texture_count: u32,
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    let set_layouts = [buffer_set_layout, texture_set_layout, storage_image_set_layout];
+    // Only the texture set has a variable count binding, the other counts are ignored
+    let variable_counts = [0, texture_count, 0];
+    let allocate_from_last_pool = |backend: &Self| {
+      let mut variable_count_info =
+        vk::DescriptorSetVariableDescriptorCountAllocateInfoEXT::default()
+          .descriptor_counts(&variable_counts);
+      let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(backend.descriptor_pools[backend.descriptor_pools.len() - 1])
+        .set_layouts(&set_layouts);
+      let allocate_info = if backend.bindless_texture_limit.is_some() {
+        allocate_info.push_next(&mut variable_count_info)
+      } else {
+        allocate_info
+      };
+      backend.ash_device.allocate_descriptor_sets(&allocate_info)
+    };
+    let desc_sets = match allocate_from_last_pool(self) {
+      Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+        let max_sets = self.last_descriptor_pool_max_sets.saturating_mul(2);
+        let pool = helpers::create_descriptor_pool(
+          &self.ash_device,
+          max_sets,
+          self.bindless_texture_limit.is_some()
+        )?;
+        self.descriptor_pools.push(pool);
+        self.last_descriptor_pool_max_sets = max_sets;
+        allocate_from_last_pool(self)
+      }
+      x => x,
+    }
+      .map_err(|e| translate_vk_error("at allocate buffer descriptor set", e))?;
+    let buffer_set = desc_sets[0];
+    let texture_set = desc_sets[1];
+    let storage_image_set = desc_sets[2];
+    let b_descriptor_sets = InputSetVK {
+      pool_index: self.descriptor_pools.len() as u32 - 1,
+      buffer_set,
+      texture_set,
+      storage_image_set,
+      uniform_buffer_count,
+      texture_count,
+      bound_buffers: HashMap::new(),
+      bound_textures: HashMap::new(),
+      bound_storage_images: vec![],
+    };
+    let bds_id_u32 = self.descriptor_sets.add_obj(b_descriptor_sets)?;
+    Ok(rhi::InputSetID(bds_id_u32))
+  }
+
+  unsafe fn create_render_pass(
+    &self,
+    pass: &rhi::RenderPassDesc,
+    sample_count: vk::SampleCountFlags,
+    multiview_mask: Option<u32>,
+  ) -> Result<vk::RenderPass, rhi::RhiError> {
+    let rhi::RenderPassDesc {
+      color_attachments,
+      depth_attachment,
+      color_ops,
+      depth_ops,
+      dependencies,
+    } = pass;
+    let mut attachments = color_attachments
+      .iter()
+      .zip(color_ops)
+      .map(|(x, ops)| vk::AttachmentDescription::default()
+        .format(translate_image_format(x.format))
+        .initial_layout(translate_image_layout(x.initial_layout))
+        .final_layout(translate_image_layout(x.final_layout))
+        .load_op(translate_load_op(ops.load))
+        .store_op(translate_store_op(ops.store))
+        .samples(sample_count)
+      )
+      .collect::<Vec<_>>();
+    if let (Some(x), Some(ops)) = (depth_attachment, depth_ops) {
+      // Stencil follows the depth ops
+      attachments.push(vk::AttachmentDescription::default()
+        .format(translate_image_format(x.format))
+        .initial_layout(translate_image_layout(x.initial_layout))
+        .final_layout(translate_image_layout(x.final_layout))
+        .load_op(translate_load_op(ops.load))
+        .store_op(translate_store_op(ops.store))
+        .stencil_load_op(translate_load_op(ops.load))
+        .stencil_store_op(translate_store_op(ops.store))
+        .samples(sample_count)
+      );
+    }
+    // Multisampled color attachments get a single sample resolve target each, placed after depth.
+    // The resolve targets share the layouts of their color attachment
+    let resolve_attachment_start = attachments.len() as u32;
+    if sample_count != vk::SampleCountFlags::TYPE_1 {
+      attachments.extend(color_attachments.iter().map(|x| vk::AttachmentDescription::default()
+        .format(translate_image_format(x.format))
+        .initial_layout(translate_image_layout(x.initial_layout))
+        .final_layout(translate_image_layout(x.final_layout))
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .samples(vk::SampleCountFlags::TYPE_1)
+      ));
+    }
+    let subpass_color_attach_infos = (0..color_attachments.len() as u32)
+      .map(|i| vk::AttachmentReference::default()
+        .attachment(i)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+      )
+      .collect::<Vec<_>>();
+    let subpass_resolve_attach_infos = (resolve_attachment_start..attachments.len() as u32)
+      .map(|i| vk::AttachmentReference::default()
+        .attachment(i)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+      )
+      .collect::<Vec<_>>();
+    let subpass_depth_attach_info = depth_attachment
+      .map(|_| vk::AttachmentReference::default()
+        .attachment(color_attachments.len() as _)
+        .layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+      );
+    let subpass_desc = vk::SubpassDescription::default()
+      .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+      .color_attachments(&subpass_color_attach_infos);
+    let subpass_desc = if subpass_resolve_attach_infos.is_empty() {
+      subpass_desc
+    } else {
+      subpass_desc.resolve_attachments(&subpass_resolve_attach_infos)
+    };
+    let subpass_desc = match subpass_depth_attach_info.as_ref() {
+      None => subpass_desc,
+      Some(x) => {
+        subpass_desc.depth_stencil_attachment(x)},
+    };
+    let subpass_descs = [subpass_desc];
+    let mut dependencies_vk = dependencies
+      .iter()
+      .map(|x| vk::SubpassDependency::default()
+        .src_subpass(x.src_subpass.unwrap_or(vk::SUBPASS_EXTERNAL))
+        .dst_subpass(x.dst_subpass.unwrap_or(vk::SUBPASS_EXTERNAL))
+        .src_stage_mask(translate_pipeline_stage(x.src_stage))
+        .dst_stage_mask(translate_pipeline_stage(x.dst_stage))
+        .src_access_mask(downgrade_access(translate_mem_access(x.src_access)))
+        .dst_access_mask(downgrade_access(translate_mem_access(x.dst_access)))
+        // Self dependencies are only valid per framebuffer region
+        .dependency_flags(if x.src_subpass.is_some() && x.dst_subpass.is_some() {
+          vk::DependencyFlags::BY_REGION
+        } else {
+          vk::DependencyFlags::empty()
+        })
+      )
+      .collect::<Vec<_>>();
+    if !dependencies.iter().any(|x| x.src_subpass.is_none() && x.dst_subpass.is_some()) {
+      // Attachment writes of earlier passes finish before this pass loads or writes them
+      let mut stages = vk::PipelineStageFlags::empty();
+      let mut src_access = vk::AccessFlags::empty();
+      let mut dst_access = vk::AccessFlags::empty();
+      if !color_attachments.is_empty() {
+        stages |= vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
+        src_access |= vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+        dst_access |=
+          vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE;
+      }
+      if depth_attachment.is_some() {
+        stages |= vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS |
+          vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        src_access |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        dst_access |= vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ |
+          vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+      }
+      if !stages.is_empty() {
+        dependencies_vk.push(vk::SubpassDependency::default()
+          .src_subpass(vk::SUBPASS_EXTERNAL)
+          .dst_subpass(0)
+          .src_stage_mask(stages)
+          .dst_stage_mask(stages)
+          .src_access_mask(src_access)
+          .dst_access_mask(dst_access)
+        );
+      }
+    }
+    let render_pass_create_info = vk::RenderPassCreateInfo::default()
+      .attachments(&attachments)
+      .subpasses(&subpass_descs)
+      .dependencies(&dependencies_vk);
+    let view_masks = [multiview_mask.unwrap_or(0)];
+    let mut multiview_info = vk::RenderPassMultiviewCreateInfoKHR::default()
+      .view_masks(&view_masks)
+      .correlation_masks(&view_masks);
+    let render_pass_create_info = if multiview_mask.is_some() {
+      render_pass_create_info.push_next(&mut multiview_info)
+    } else {
+      render_pass_create_info
+    };
+    self
+      .ash_device
+      .create_render_pass(&render_pass_create_info, None)
+      .map_err(|e| translate_vk_error("at render pass creation", e))
+  }
+
+  /// Secondaries executed together share one render pass, so they need the same framebuffer
+  fn secondary_inheritance(
+    &self,
+    secondaries: &[rhi::CommandBufferID]
+  ) -> Result<rhi::CommandBufferInheritance, rhi::RhiError> {
+    let mut inheritance: Option<rhi::CommandBufferInheritance> = None;
+    for secondary in secondaries {
+      let Some(curr) = self.command_buffers.get_obj(secondary.0)?.inheritance else {
+        return Err(rhi::RhiError::BackendError(
+          "at execute secondary: got a primary command buffer".to_string()
+        ));
+      };
+      if inheritance.is_some_and(|x| x.framebuffer.0 != curr.framebuffer.0) {
+        return Err(rhi::RhiError::BackendError(
+          "at execute secondary: secondaries use different framebuffers".to_string()
+        ));
+      }
+      inheritance = Some(curr);
+    }
+    inheritance.ok_or(rhi::RhiError::BackendError(
+      "at execute secondary: no secondary command buffers".to_string()
+    ))
+  }
+
+  fn framebuffer_render_area(
+    &self,
+    framebuffer_vk: &FramebufferVK
+  ) -> Result<vk::Rect2D, rhi::RhiError> {
+    let framebuffer_res =
+      self.images.get_obj(framebuffer_vk.color_attachments[0].0)?.resolution;
+    Ok(vk::Rect2D::default()
+      .offset(vk::Offset2D::default())
+      .extent(vk::Extent2D::default()
+        .width(framebuffer_res.width)
+        .height(framebuffer_res.height)))
+  }
+
+  /// Logs the allocator's state and reports the requested size with the space left in each heap
+  fn allocation_error(&self, size: u64, e: gpu_allocator::AllocationError) -> rhi::RhiError {
+    log::error!("allocation of {size} bytes failed: {e}, allocator state: {:?}", self.allocator);
+    let budgets = rhi::RenderBackend::get_memory_budget(self);
+    let heaps = rhi::RenderBackend::get_memory_info(self)
+      .heaps
+      .iter()
+      .enumerate()
+      .map(|(i, heap)| {
+        // Without budgets only the heap size is known
+        let available = budgets
+          .get(i)
+          .map(|x| x.budget.saturating_sub(x.heap_usage))
+          .unwrap_or(heap.size);
+        format!("heap {i}: {available} of {} bytes available", heap.size)
+      })
+      .collect::<Vec<_>>()
+      .join(", ");
+    rhi::RhiError::AllocationFailed(format!("at allocator alloc of {size} bytes: {e} ({heaps})"))
+  }
+
+  /// Begins the render pass, or dynamic rendering, that draws into `framebuffer_vk`
+  unsafe fn begin_framebuffer_pass(
+    &self,
+    command_buffer_vk: vk::CommandBuffer,
+    pipeline_vk: &GraphicsPipeline,
+    framebuffer_vk: &FramebufferVK,
+    render_area: vk::Rect2D,
+    contents: vk::SubpassContents,
+  ) -> Result<(), rhi::RhiError> {
+    if framebuffer_vk.color_attachments.len() != pipeline_vk.color_ops.len() ||
+      framebuffer_vk.depth_attachment.is_some() != pipeline_vk.depth_ops.is_some() {
+      return Err(rhi::RhiError::BackendError(
+        "at begin pass: framebuffer attachments don't match the pipeline's".to_string()
+      ));
+    }
+    if let Some(dynamic_rendering_device) = self.dynamic_rendering_device.as_ref() {
+      let color_attachment_infos = framebuffer_vk
+        .color_attachments
+        .iter()
+        .enumerate()
+        .map(|(i, att_id)| {
+          let attachment_info = vk::RenderingAttachmentInfoKHR::default()
+            .image_view(self.images.get_obj(att_id.0)?.view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(translate_load_op(pipeline_vk.color_ops[i].load))
+            .store_op(translate_store_op(pipeline_vk.color_ops[i].store))
+            .clear_value(color_clear_value(pipeline_vk.color_ops[i]));
+          Ok(match framebuffer_vk.resolve_attachments.get(i) {
+            None => attachment_info,
+            Some(resolve_id) => attachment_info
+              .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+              .resolve_image_view(self.images.get_obj(resolve_id.0)?.view)
+              .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+          })
+        })
+        .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+      let depth_attachment = framebuffer_vk
+        .depth_attachment
+        .map(|att_id| self.images.get_obj(att_id.0))
+        .transpose()?;
+      let depth_attachment_info = depth_attachment.zip(pipeline_vk.depth_ops).map(|(image, ops)| {
+        vk::RenderingAttachmentInfoKHR::default()
+          .image_view(image.view)
+          .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+          .load_op(translate_load_op(ops.load))
+          .store_op(translate_store_op(ops.store))
+          .clear_value(depth_clear_value(ops))
+      });
+      let rendering_flags = if contents == vk::SubpassContents::SECONDARY_COMMAND_BUFFERS {
+        vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS
+      } else {
+        vk::RenderingFlags::empty()
+      };
+      let rendering_info = vk::RenderingInfoKHR::default()
+        .flags(rendering_flags)
+        .render_area(render_area)
+        .layer_count(1)
+        .view_mask(pipeline_vk.multiview_mask.unwrap_or(0))
+        .color_attachments(&color_attachment_infos);
+      let rendering_info = match depth_attachment_info.as_ref() {
+        None => rendering_info,
+        Some(x) => {
+          let has_stencil = depth_attachment.is_some_and(|image| {
+            get_aspect_mask(image.format).contains(vk::ImageAspectFlags::STENCIL)
+          });
+          let rendering_info = rendering_info.depth_attachment(x);
+          if has_stencil { rendering_info.stencil_attachment(x) } else { rendering_info }
+        }
+      };
+      dynamic_rendering_device.cmd_begin_rendering(command_buffer_vk, &rendering_info);
+    } else {
+      let mut clear_values = pipeline_vk
+        .color_ops
+        .iter()
+        .map(|ops| color_clear_value(*ops))
+        .collect::<Vec<_>>();
+      clear_values.extend(pipeline_vk.depth_ops.map(depth_clear_value));
+      self.ash_device.cmd_begin_render_pass(
+        command_buffer_vk,
+        &vk::RenderPassBeginInfo::default()
+          .framebuffer(framebuffer_vk.framebuffer)
+          .render_pass(pipeline_vk.render_pass)
+          .render_area(render_area)
+          .clear_values(&clear_values),
+        contents,
+      );
+    }
+    Ok(())
+  }
+
+  /// Layouts the framebuffer's attachments are left in when `pipeline_vk`'s pass ends
+  fn attachment_end_states(
+    &self,
+    pipeline_vk: &GraphicsPipeline,
+    framebuffer_vk: &FramebufferVK,
+  ) -> Vec<(rhi::ImageID, (vk::ImageLayout, vk::PipelineStageFlags))> {
+    let mut states = framebuffer_vk
+      .color_attachments
+      .iter()
+      .zip(&pipeline_vk.color_final_layouts)
+      .map(|(att_id, layout)| (*att_id, (*layout, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)))
+      .collect::<Vec<_>>();
+    states.extend(
+      framebuffer_vk
+        .resolve_attachments
+        .iter()
+        .zip(&pipeline_vk.color_final_layouts)
+        .map(|(att_id, layout)| {
+          (*att_id, (*layout, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT))
+        })
+    );
+    if let (Some(att_id), Some(layout)) =
+      (framebuffer_vk.depth_attachment, pipeline_vk.depth_final_layout) {
+      states.push((att_id, (layout, vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)));
+    }
+    states
+  }
+
+  /// Ends the pass begun by `begin_framebuffer_pass` and records the attachments' final layouts
+  /// in `image_curr_state`
+  unsafe fn end_framebuffer_pass(
+    &self,
+    command_buffer_vk: vk::CommandBuffer,
+    pipeline_vk: &GraphicsPipeline,
+    framebuffer_vk: &FramebufferVK,
+    image_curr_state: &mut HashMap<rhi::ImageID, (vk::ImageLayout, vk::PipelineStageFlags)>,
+  ) -> Result<(), rhi::RhiError> {
+    let end_states = self.attachment_end_states(pipeline_vk, framebuffer_vk);
+    match self.dynamic_rendering_device.as_ref() {
+      Some(dynamic_rendering_device) => {
+        dynamic_rendering_device.cmd_end_rendering(command_buffer_vk);
+        // Dynamic rendering has no final layouts, the attachments are moved there by hand
+        let mut image_barriers = vec![];
+        for (att_id, (layout, _)) in end_states.iter() {
+          let image = self.images.get_obj(att_id.0)?;
+          let aspect_mask = get_aspect_mask(image.format);
+          let attachment_layout = if aspect_mask.contains(vk::ImageAspectFlags::DEPTH) {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+          } else {
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+          };
+          if *layout == attachment_layout {
+            continue;
+          }
+          image_barriers.push(vk::ImageMemoryBarrier2::default()
+            .image(image.image)
+            .old_layout(attachment_layout)
+            .new_layout(*layout)
+            .src_access_mask(infer_access_from_layout(attachment_layout))
+            .dst_access_mask(infer_access_from_layout(*layout))
+            .src_queue_family_index(self.graphics_queue_family_id)
+            .dst_queue_family_index(self.graphics_queue_family_id)
+            .subresource_range(
+              vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect_mask)
+                .base_mip_level(0)
+                .level_count(image.mip_levels)
+                .base_array_layer(0)
+                .layer_count(image.layer_count)
+            ));
+        }
+        if !image_barriers.is_empty() {
+          let attachment_stages = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT |
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+          self.emit_barriers(
+            command_buffer_vk,
+            attachment_stages,
+            attachment_stages,
+            vk::DependencyFlags::BY_REGION,
+            &[],
+            &image_barriers
+          );
+        }
+      }
+      None => self.ash_device.cmd_end_render_pass(command_buffer_vk),
+    }
+    image_curr_state.extend(end_states);
+    Ok(())
+  }
+
+  /// Begins the framebuffer's pass for primaries, then binds the pipeline, its dynamic state and
+  /// the input set
+  unsafe fn begin_graphics_run(
+    &self,
+    command_buffer_obj: &CommandBufferVK,
+    pipeline: rhi::PipelineID,
+    framebuffer: rhi::FramebufferID,
+    input_set: rhi::InputSetID,
+    overrides: &DynamicStateOverrides,
+  ) -> Result<&GraphicsPipeline, rhi::RhiError> {
+    let command_buffer_vk = command_buffer_obj.command_buffer;
+    let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
+    let pipeline_vk = self.pipelines.get_obj(pipeline.0)?;
+    let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
+    let render_area = self.framebuffer_render_area(framebuffer_vk)?;
+    // Secondaries run inside the render pass begun by the primary's ExecuteSecondary
+    if command_buffer_obj.inheritance.is_none() {
+      self.begin_framebuffer_pass(
+        command_buffer_vk,
+        pipeline_vk,
+        framebuffer_vk,
+        render_area,
+        vk::SubpassContents::INLINE
+      )?;
+    }
+    self.ash_device.cmd_bind_pipeline(
+      command_buffer_vk,
+      vk::PipelineBindPoint::GRAPHICS,
+      pipeline_vk.pipeline,
+    );
+    let viewport = overrides.viewport.unwrap_or(
+      vk::Viewport::default()
+        .x(0.0)
+        .y(0.0)
+        .width(render_area.extent.width as _)
+        .height(render_area.extent.height as _)
+        .min_depth(0.0)
+        .max_depth(1.0)
+    );
+    self.ash_device.cmd_set_viewport(command_buffer_vk, 0, &[viewport]);
+    self.ash_device.cmd_set_scissor(
+      command_buffer_vk,
+      0,
+      &[overrides.scissor.unwrap_or(render_area)]
+    );
+    let (stencil_front, stencil_back) =
+      overrides.stencil_reference.unwrap_or(pipeline_vk.stencil_reference);
+    self.ash_device.cmd_set_stencil_reference(
+      command_buffer_vk,
+      vk::StencilFaceFlags::FRONT,
+      stencil_front
+    );
+    self.ash_device.cmd_set_stencil_reference(
+      command_buffer_vk,
+      vk::StencilFaceFlags::BACK,
+      stencil_back
+    );
+    let (bias_constant, bias_clamp, bias_slope) =
+      overrides.depth_bias.unwrap_or(pipeline_vk.depth_bias);
+    self.ash_device.cmd_set_depth_bias(command_buffer_vk, bias_constant, bias_clamp, bias_slope);
+    self.ash_device.cmd_bind_descriptor_sets(
+      command_buffer_vk,
+      vk::PipelineBindPoint::GRAPHICS,
+      pipeline_vk.pipeline_layout,
+      0,
+      &[input_set_vk.buffer_set, input_set_vk.texture_set, input_set_vk.storage_image_set],
+      &[]
+    );
+    Ok(pipeline_vk)
+  }
+
+  unsafe fn bind_compute_run(
+    &self,
+    command_buffer: vk::CommandBuffer,
+    pipeline: rhi::ComputePipelineID,
+    input_set: rhi::InputSetID,
+  ) -> Result<(), rhi::RhiError> {
+    let pipeline_vk = self.compute_pipelines.get_obj(pipeline.0)?;
+    let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
+    self.ash_device.cmd_bind_pipeline(
+      command_buffer,
+      vk::PipelineBindPoint::COMPUTE,
+      pipeline_vk.pipeline,
+    );
+    self.ash_device.cmd_bind_descriptor_sets(
+      command_buffer,
+      vk::PipelineBindPoint::COMPUTE,
+      pipeline_vk.pipeline_layout,
+      0,
+      &[input_set_vk.buffer_set, input_set_vk.texture_set, input_set_vk.storage_image_set],
+      &[]
+    );
+    Ok(())
+  }
+
+  fn check_depth_bias_clamp(&self, clamp: f32, at: &str) -> Result<(), rhi::RhiError> {
+    if clamp != 0.0 && self.device_features.depth_bias_clamp != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(format!(
+        "{at}: depth bias clamp {clamp} needs depthBiasClamp, which the GPU lacks"
+      )));
+    }
+    Ok(())
+  }
+
+  fn check_indirect_draw_count(&self, draw_count: u32) -> Result<(), rhi::RhiError> {
+    if draw_count > 1 && self.device_features.multi_draw_indirect != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at indirect draw: {draw_count} draws need multiDrawIndirect, which the GPU lacks"
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl rhi::RenderBackend for VulkanBackend {
+  fn get_swapchain_info(&self) -> rhi::SwapchainInfo {
+    rhi::SwapchainInfo{
+      res: rhi::Resolution2D{width: self.swapchain_res.width, height: self.swapchain_res.height},
+      image_count: self.swapchain_images.len() as _,
+      present_mode: match self.present_mode {
+        vk::PresentModeKHR::MAILBOX => rhi::PresentMode::Mailbox,
+        vk::PresentModeKHR::IMMEDIATE => rhi::PresentMode::Immediate,
+        _ => rhi::PresentMode::Fifo,
+      },
+      is_preferred_present_mode:
+        self.present_mode == translate_present_mode(self.preferred_present_mode),
+    }
+  }
+
+  fn timestamp_period(&self) -> f32 {
+    self.timestamp_period
+  }
+
+  fn get_memory_info(&self) -> rhi::MemoryInfo {
+    let mem_props = unsafe { self.ash_instance.get_physical_device_memory_properties(self.gpu) };
+    rhi::MemoryInfo {
+      heaps: mem_props
+        .memory_heaps_as_slice()
+        .iter()
+        .map(|x| rhi::MemoryHeap { size: x.size, flags: translate_memory_heap_flags(x.flags) })
+        .collect(),
+      types: mem_props
+        .memory_types_as_slice()
+        .iter()
+        .map(|x| rhi::MemoryType {
+          property_flags: translate_memory_property_flags(x.property_flags),
+          heap_index: x.heap_index,
+        })
+        .collect(),
+    }
+  }
+
+  fn get_memory_budget(&self) -> Vec<rhi::MemoryHeapBudget> {
+    if !self.memory_budget_supported {
+      return vec![];
+    }
+    let mut budget_props = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut mem_props = vk::PhysicalDeviceMemoryProperties2KHR::default()
+      .push_next(&mut budget_props);
+    unsafe {
+      khr::get_physical_device_properties2::Instance::new(&self.ash_entry, &self.ash_instance)
+        .get_physical_device_memory_properties2(self.gpu, &mut mem_props);
+    }
+    let heap_sizes = mem_props
+      .memory_properties
+      .memory_heaps_as_slice()
+      .iter()
+      .map(|x| x.size)
+      .collect::<Vec<_>>();
+    heap_sizes
+      .into_iter()
+      .enumerate()
+      .map(|(i, heap_size)| {
+        let heap_budget = rhi::MemoryHeapBudget {
+          heap_size,
+          heap_usage: budget_props.heap_usage[i],
+          budget: budget_props.heap_budget[i],
+        };
+        if heap_budget.heap_usage * 5 > heap_budget.budget * 4 {
+          log::warn!(
+            "memory heap {i} is using {} of its {} byte budget",
+            heap_budget.heap_usage,
+            heap_budget.budget
+          );
+        }
+        heap_budget
+      })
+      .collect()
+  }
+
+  fn get_device_info(&self) -> (rhi::GPUInfo, rhi::DeviceLimits, rhi::DeviceFeatures) {
+    unsafe {
+      let limits = self.ash_instance.get_physical_device_properties(self.gpu).limits;
+      let features = self.ash_instance.get_physical_device_features(self.gpu);
+      let timestamp_valid_bits = self
+        .ash_instance
+        .get_physical_device_queue_family_properties(self.gpu)
+        .get(self.graphics_queue_family_id as usize)
+        .map(|x| x.timestamp_valid_bits)
+        .unwrap_or(0);
+      let device_limits = rhi::DeviceLimits {
+        max_texture_size: limits.max_image_dimension2_d,
+        max_push_constant_size: limits.max_push_constants_size,
+        max_compute_group_size: limits.max_compute_work_group_size,
+        min_uniform_buffer_alignment: limits.min_uniform_buffer_offset_alignment,
+        timestamp_valid_bits,
+      };
+      let device_features = rhi::DeviceFeatures {
+        anisotropy: features.sampler_anisotropy == vk::TRUE,
+        fill_mode_non_solid: features.fill_mode_non_solid == vk::TRUE,
+        multi_draw_indirect: features.multi_draw_indirect == vk::TRUE,
+        tessellation: features.tessellation_shader == vk::TRUE,
+        geometry_shader: features.geometry_shader == vk::TRUE,
+        buffer_device_address: self.buffer_device_address_device.is_some(),
+      };
+      (self.gpu_info.clone(), device_limits, device_features)
+    }
+  }
+
+  fn create_buffer(
+    &mut self,
+    size: u64,
+    usage: rhi::BufferUsage,
+    memory_location: rhi::MemoryLocation
+  ) -> Result<rhi::BufferID, rhi::RhiError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("create_buffer");
+    if usage.contains(rhi::BufferUsage::ACCEL_STRUCT_INPUT) && self.accel_struct_device.is_none() {
+      return Err(rhi::RhiError::BackendError(
+        "at create buffer: acceleration structure inputs need VK_KHR_acceleration_structure, \
+        which the GPU lacks".to_string()
+      ));
+    }
+    if usage.contains(rhi::BufferUsage::DEVICE_ADDRESS) &&
+      self.buffer_device_address_device.is_none() {
+      return Err(rhi::RhiError::BackendError(
+        "at create buffer: device addresses need VK_KHR_buffer_device_address, which the GPU \
+        lacks".to_string()
+      ));
+    }
+    unsafe { self.create_buffer_vk(size, translate_buffer_usage(usage), memory_location) }
+  }
+
+  fn map_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<*mut u8, rhi::RhiError> {
+    let a_buffer = self.buffers.get_obj(buffer_id.0)?;
+    a_buffer
+      .allocation
+      .as_ref()
+      .and_then(|a| a.mapped_ptr())
+      .map(|p| p.as_ptr() as *mut u8)
+      .ok_or(rhi::RhiError::BackendError(format!(
+        "at map buffer: buffer {} is not CPU visible",
+        buffer_id.0
+      )))
+  }
+
+  fn unmap_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<(), rhi::RhiError> {
+    // Allocations stay persistently mapped until freed, only validate the handle
+    self.buffers.get_obj(buffer_id.0)?;
+    Ok(())
+  }
+
+  fn write_buffer<T: bytemuck::Pod>(
+    &mut self,
+    buffer_id: rhi::BufferID,
+    data: &[T]
+  ) -> Result<(), rhi::RhiError> {
+    let bytes: &[u8] = bytemuck::cast_slice(data);
+    let buffer_size = self.buffers.get_obj(buffer_id.0)?.size;
+    if bytes.len() as u64 > buffer_size {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at write buffer: {} bytes don't fit in buffer of size {buffer_size}",
+        bytes.len()
+      )));
+    }
+    let ptr = self.map_buffer(buffer_id)?;
+    unsafe {
+      std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+    }
+    self.unmap_buffer(buffer_id)
+  }
+
+  fn upload_buffer_sync(&mut self, dst: rhi::BufferID, data: &[u8]) -> Result<(), rhi::RhiError> {
+    let dst_vk = self.buffers.get_obj(dst.0)?;
+    let dst_buffer = dst_vk.buffer;
+    if data.len() as u64 > dst_vk.size {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at upload buffer: {} bytes don't fit in buffer of size {}",
+        data.len(),
+        dst_vk.size
+      )));
+    }
+    unsafe {
+      let (staging_handle, staging_buffer) = self.acquire_staging_buffer(data.len() as u64)?;
+      let staging_vk_buffer = staging_buffer.buffer;
+      let mapped_ptr = staging_buffer
+        .allocation
+        .as_ref()
+        .and_then(|a| a.mapped_ptr())
+        .map(|p| p.as_ptr() as *mut u8);
+      let result = match mapped_ptr {
+        None => Err(rhi::RhiError::BackendError(
+          "at upload buffer: staging buffer not mapped".to_string()
+        )),
+        Some(ptr) => {
+          std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+          self.run_transfer_commands(|command_buffer| {
+            self.ash_device.cmd_copy_buffer(
+              command_buffer,
+              staging_vk_buffer,
+              dst_buffer,
+              &[vk::BufferCopy::default().size(data.len() as u64)]
+            );
+          })
+        }
+      };
+      self.release_staging_buffer(staging_handle);
+      result
+    }
+  }
+
+  fn destroy_buffer(&mut self, buffer_id: rhi::BufferID) -> Result<(), rhi::RhiError> {
+    let rhi::BufferID(buffer_id) = buffer_id;
+    let a_buffer = self.buffers.remove_obj(buffer_id)?;
+    unsafe {
+      self.ash_device.destroy_buffer(a_buffer.buffer, None);
+      a_buffer.allocation.map(|a| self.allocator.free(a));
+    }
+    Ok(())
+  }
+
+  fn get_buffer_device_address(&self, buffer_id: rhi::BufferID) -> Result<u64, rhi::RhiError> {
+    unsafe { self.buffer_address(buffer_id) }
+  }
+
+  fn query_image_format_support(
+    &self,
+    format: rhi::ImageFormat,
+    usage: rhi::ImageUsage,
+    tiling: rhi::ImageTiling,
+  ) -> rhi::FormatSupport {
+    let unsupported = rhi::FormatSupport {
+      supported: false,
+      max_sample_count: rhi::ImageSampleCount::E1,
+      max_mip_levels: 0,
+    };
+    let format_vk = translate_image_format(format);
+    let format_props = unsafe {
+      self.ash_instance.get_physical_device_format_properties(self.gpu, format_vk)
+    };
+    let tiling_features = match tiling {
+      rhi::ImageTiling::Optimal => format_props.optimal_tiling_features,
+      rhi::ImageTiling::Linear => format_props.linear_tiling_features,
+    };
+    if !tiling_features.contains(image_usage_format_features(usage, format)) {
+      return unsupported;
+    }
+    let image_format_props = unsafe {
+      self.ash_instance.get_physical_device_image_format_properties(
+        self.gpu,
+        format_vk,
+        vk::ImageType::TYPE_2D,
+        translate_image_tiling(tiling),
+        translate_image_usage(usage, format),
+        vk::ImageCreateFlags::empty()
+      )
+    };
+    // FORMAT_NOT_SUPPORTED is the expected failure, anything else is treated the same
+    let Ok(image_format_props) = image_format_props else {
+      return unsupported;
+    };
+    rhi::FormatSupport {
+      supported: true,
+      max_sample_count: max_sample_count(image_format_props.sample_counts),
+      max_mip_levels: image_format_props.max_mip_levels,
+    }
+  }
+
+  fn query_buffer_format_support(&self, format: rhi::ImageFormat) -> bool {
+    let format_props = unsafe {
+      self
+        .ash_instance
+        .get_physical_device_format_properties(self.gpu, translate_image_format(format))
+    };
+    format_props.buffer_features.contains(vk::FormatFeatureFlags::UNIFORM_TEXEL_BUFFER)
+  }
+
+  fn create_texture(&mut self, desc: rhi::TextureDesc) -> Result<rhi::ImageID, rhi::RhiError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("create_texture");
+    let rhi::TextureDesc {
+      ty,
+      res,
+      format,
+      usage,
+      memory_location,
+      sample_count,
+      mip_levels,
+      is_cube,
+    } = desc;
+    let (image_type, view_type, depth, layer_count) = match ty {
+      rhi::TextureType::Texture2D => {(vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D, 1, 1)}
+      rhi::TextureType::Texture3D { depth } => {
+        (vk::ImageType::TYPE_3D, vk::ImageViewType::TYPE_3D, depth, 1)
+      }
+      rhi::TextureType::Texture2DArray { layers } => {
+        (vk::ImageType::TYPE_2D, vk::ImageViewType::TYPE_2D_ARRAY, 1, layers)
+      }
+    };
+    if is_cube && !matches!(ty, rhi::TextureType::Texture2DArray { layers: 6 }) {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create texture: cube textures need to be a 2D array of 6 layers, got {ty:?}"
+      )));
+    }
+    let (create_flags, view_type) = if is_cube {
+      (vk::ImageCreateFlags::CUBE_COMPATIBLE, vk::ImageViewType::CUBE)
+    } else {
+      (vk::ImageCreateFlags::empty(), view_type)
+    };
+    unsafe {
+      let image_create_info = vk::ImageCreateInfo::default()
+        .flags(create_flags)
+        .image_type(image_type)
+        .format(translate_image_format(format))
+        .usage(translate_image_usage(usage, format))
+        .samples(translate_sample_count(sample_count))
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .mip_levels(mip_levels)
+        .array_layers(layer_count)
+        .extent(vk::Extent3D { width: res.width, height: res.height, depth, });
+      let image = self
+        .ash_device
+        .create_image(&image_create_info, None)
+        .map_err(|e| translate_vk_error("at vk image create", e))?;
+      let memory_requirements = self.ash_device.get_image_memory_requirements(image);
+      let allocation = self
+        .allocator
+        .allocate(
+          &AllocationCreateDesc{
+            name: "image",
+            requirements: memory_requirements,
+            location: translate_memory_location(memory_location),
+            linear: false,
+            allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+          })
+        .map_err(|e| self.allocation_error(memory_requirements.size, e))?;
+      // The view needs the memory bound first
+      self
+        .ash_device
+        .bind_image_memory(image, allocation.memory(), allocation.offset())
+        .map_err(|e| translate_vk_error("at bind image memory", e))?;
+      let view = self
+        .ash_device
+        .create_image_view(
+          &vk::ImageViewCreateInfo::default()
+            .image(image)
+            .format(translate_image_format(format))
+            .view_type(view_type)
+            .subresource_range(
+              vk::ImageSubresourceRange::default()
+                .aspect_mask(get_aspect_mask(format))
+                .base_array_layer(0)
+                .layer_count(layer_count)
+                .base_mip_level(0)
+                .level_count(mip_levels)
+            ),
+          None
+        )
+        .map_err(|e| translate_vk_error("at view creation", e))?;
+      let a_image = AllocatedTexture{
+        image,
+        view,
+        resolution: res,
+        format,
+        depth,
+        mip_levels,
+        layer_count,
+        is_cube,
+        allocation: Some(allocation),
+      };
+      let image_id_u32 = self
+        .images
+        .add_obj(a_image)
+        .map_err(|e| rhi::RhiError::AllocationFailed(format!("max image count reached: {e}")))?;
+      self.set_object_name(image, &format!("image_{image_id_u32}"))?;
+      Ok(rhi::ImageID(image_id_u32))
+    }
+  }
+
+  fn create_cube_face_view(
+    &mut self,
+    image: rhi::ImageID,
+    face: u32
+  ) -> Result<rhi::ImageViewID, rhi::RhiError> {
+    let image_vk = self.images.get_obj(image.0)?;
+    if !image_vk.is_cube || face >= 6 {
+      return Err(rhi::RhiError::InvalidHandle(format!(
+        "at create cube face view: face {face} of a {} texture",
+        if image_vk.is_cube { "cube" } else { "non cube" }
+      )));
+    }
+    let mip_levels = image_vk.mip_levels;
+    rhi::RenderBackend::create_image_view(self, image, 0, mip_levels, face, 1)
+  }
+
+  fn create_image_view(
+    &mut self,
+    image: rhi::ImageID,
+    base_mip: u32,
+    mip_count: u32,
+    base_layer: u32,
+    layer_count: u32,
+  ) -> Result<rhi::ImageViewID, rhi::RhiError> {
+    let image_vk = self.images.get_obj(image.0)?;
+    let mip_end = base_mip.checked_add(mip_count);
+    if mip_count == 0 || mip_end.is_none_or(|end| end > image_vk.mip_levels) {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create image view: {mip_count} mips from mip {base_mip} of an image with {} mips",
+        image_vk.mip_levels
+      )));
+    }
+    let layer_end = base_layer.checked_add(layer_count);
+    if layer_count == 0 || layer_end.is_none_or(|end| end > image_vk.layer_count) {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create image view: {layer_count} layers from layer {base_layer} of an image with {} \
+        layers",
+        image_vk.layer_count
+      )));
+    }
+    // 3D images always have a single layer
+    let view_type = if image_vk.depth > 1 {
+      vk::ImageViewType::TYPE_3D
+    } else if layer_count == 1 {
+      vk::ImageViewType::TYPE_2D
+    } else if image_vk.is_cube && layer_count == 6 {
+      vk::ImageViewType::CUBE
+    } else {
+      vk::ImageViewType::TYPE_2D_ARRAY
+    };
+    let view = unsafe {
+      self
+        .ash_device
+        .create_image_view(
+          &vk::ImageViewCreateInfo::default()
+            .image(image_vk.image)
+            .format(translate_image_format(image_vk.format))
+            .view_type(view_type)
+            .subresource_range(
+              vk::ImageSubresourceRange::default()
+                .aspect_mask(get_aspect_mask(image_vk.format))
+                .base_array_layer(base_layer)
+                .layer_count(layer_count)
+                .base_mip_level(base_mip)
+                .level_count(mip_count)
+            ),
+          None
+        )
+        .map_err(|e| translate_vk_error("at image view creation", e))?
+    };
+    let view_id_u32 = self.image_views.add_obj((view, image))?;
+    Ok(rhi::ImageViewID(view_id_u32))
+  }
+
+  fn destroy_image_view(&mut self, image_view_id: rhi::ImageViewID) -> Result<(), rhi::RhiError> {
+    let (view, _) = self.image_views.remove_obj(image_view_id.0)?;
     unsafe {
-      // Render pass
-      let render_pass = self.create_render_pass(
-        &color_attachment_formats,
-        depth_attachment_formats.as_ref()
-      )?;
+      self.ash_device.destroy_image_view(view, None);
+    }
+    Ok(())
+  }
+
+  fn upload_texture(
+    &mut self,
+    data: &[u8],
+    res: rhi::Resolution2D,
+    format: rhi::ImageFormat,
+  ) -> Result<rhi::ImageID, rhi::RhiError> {
+    let expected_size = res.width as u64 * res.height as u64 * get_texel_size(format);
+    if data.len() as u64 != expected_size {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at upload texture: expected {expected_size} bytes, got {}",
+        data.len()
+      )));
+    }
+    let image_id = self.create_texture(rhi::TextureDesc {
+      ty: rhi::TextureType::Texture2D,
+      res,
+      format,
+      usage: rhi::ImageUsage::SHADER_SAMPLED | rhi::ImageUsage::COPY_DST,
+      memory_location: rhi::MemoryLocation::GPU,
+      sample_count: rhi::ImageSampleCount::E1,
+      mip_levels: 1,
+      is_cube: false,
+    })?;
+    let image = self.images.get_obj(image_id.0)?.image;
+    let subresource_range = vk::ImageSubresourceRange::default()
+      .aspect_mask(get_aspect_mask(format))
+      .base_mip_level(0)
+      .level_count(1)
+      .base_array_layer(0)
+      .layer_count(1);
+    unsafe {
+      let (staging_handle, staging_buffer) = self.acquire_staging_buffer(data.len() as u64)?;
+      let staging_vk_buffer = staging_buffer.buffer;
+      let mapped_ptr = staging_buffer
+        .allocation
+        .as_ref()
+        .and_then(|a| a.mapped_ptr())
+        .map(|p| p.as_ptr() as *mut u8);
+      let result = match mapped_ptr {
+        None => Err(rhi::RhiError::BackendError(
+          "at upload texture: staging buffer not mapped".to_string()
+        )),
+        Some(ptr) => {
+          std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+          // The image is owned by the graphics queue family, so upload through that queue
+          self.run_one_shot_commands(self.command_pool, self.graphics_queue, |command_buffer| {
+            self.emit_barriers(
+              command_buffer,
+              vk::PipelineStageFlags::TOP_OF_PIPE,
+              vk::PipelineStageFlags::TRANSFER,
+              vk::DependencyFlags::empty(),
+              &[],
+              &[
+                vk::ImageMemoryBarrier2::default()
+                  .image(image)
+                  .old_layout(vk::ImageLayout::UNDEFINED)
+                  .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                  .src_access_mask(vk::AccessFlags2::NONE)
+                  .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                  .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                  .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                  .subresource_range(subresource_range)
+              ]
+            );
+            self.ash_device.cmd_copy_buffer_to_image(
+              command_buffer,
+              staging_vk_buffer,
+              image,
+              vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+              &[
+                vk::BufferImageCopy::default()
+                  .image_extent(vk::Extent3D { width: res.width, height: res.height, depth: 1 })
+                  .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(format))
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .mip_level(0)
+                  )
+              ]
+            );
+            self.emit_barriers(
+              command_buffer,
+              vk::PipelineStageFlags::TRANSFER,
+              vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+              vk::DependencyFlags::empty(),
+              &[],
+              &[
+                vk::ImageMemoryBarrier2::default()
+                  .image(image)
+                  .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                  .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                  .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                  .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                  .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                  .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                  .subresource_range(subresource_range)
+              ]
+            );
+          })
+        }
+      };
+      self.release_staging_buffer(staging_handle);
+      if result.is_err() {
+        self.destroy_image(image_id)?;
+      }
+      result.map(|_| image_id)
+    }
+  }
+
+  fn destroy_image(&mut self, image_id: rhi::ImageID) -> Result<(), rhi::RhiError> {
+    let rhi::ImageID(image_id) = image_id;
+    let swapchain_owned = self
+      .swapchain_images
+      .iter()
+      .chain(self.xr_swapchain_images().iter())
+      .any(|x| x.0 == image_id);
+    if swapchain_owned {
+      return Err(rhi::RhiError::InvalidHandle(format!(
+        "at destroy image: image {image_id} is owned by the swapchain"
+      )));
+    }
+    let a_image = self.images.remove_obj(image_id)?;
+    let view_ids = self
+      .image_views
+      .get_all()
+      .iter()
+      .filter(|(_, (_, image))| image.0 == image_id)
+      .map(|(view_id, _)| *view_id)
+      .collect::<Vec<_>>();
+    for view_id in view_ids {
+      rhi::RenderBackend::destroy_image_view(self, rhi::ImageViewID(view_id))?;
+    }
+    unsafe {
+      self.ash_device.destroy_image_view(a_image.view, None);
+      self.ash_device.destroy_image(a_image.image, None);
+      a_image.allocation.map(|a| self.allocator.free(a));
+    }
+    Ok(())
+  }
+
+  fn create_shader(&mut self, path: PathBuf) -> Result<rhi::ShaderID, rhi::RhiError> {
+    let code = std::fs::read(&path)
+      .map_err(|e| rhi::RhiError::BackendError(format!("at read shader file {path:?}: {e}")))?;
+    let shader_id = self.create_shader_module(&code)?;
+    #[cfg(feature = "hot-reload")]
+    self.shader_paths.insert(shader_id.0, path);
+    Ok(shader_id)
+  }
+
+  async fn load_shader(&mut self, path: PathBuf) -> Result<rhi::ShaderID, rhi::RhiError> {
+    let code = fs::read(&path)
+      .await
+      .map_err(|e| rhi::RhiError::BackendError(format!("at read shader file {path:?}: {e}")))?;
+    let shader_id = self.create_shader_module(&code)?;
+    #[cfg(feature = "hot-reload")]
+    self.shader_paths.insert(shader_id.0, path);
+    Ok(shader_id)
+  }
+
+  fn destroy_shader(&mut self, shader_id: rhi::ShaderID) -> Result<(), rhi::RhiError> {
+    let shader_vk = self.shaders.remove_obj(shader_id.0)?;
+    #[cfg(feature = "hot-reload")]
+    self.shader_paths.remove(&shader_id.0);
+    unsafe {
+      self.ash_device.destroy_shader_module(shader_vk, None);
+    }
+    Ok(())
+  }
+
+  async fn create_graphics_pipeline(
+    &mut self,
+    desc: rhi::GraphicsPipelineDesc
+  ) -> Result<rhi::PipelineID, rhi::RhiError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("create_graphics_pipeline");
+    let rhi::GraphicsPipelineDesc {
+      raster_config,
+      pass,
+      max_buffer_count,
+      uniform_buffer_count,
+      max_texture_count,
+      storage_image_count,
+      pipeline_type,
+      fragment_shader,
+      geometry_shader,
+      tessellation,
+      vertex_spec_constants,
+      fragment_spec_constants,
+      push_constant_size,
+      push_constant_stages,
+      multisample,
+      mut blend_states,
+      depth_state,
+      multiview_mask,
+      instance_input,
+    } = desc;
+    let rhi::RenderPassDesc {
+      color_attachments,
+      depth_attachment,
+      color_ops,
+      depth_ops,
+      dependencies,
+    } = &pass;
+    if blend_states.len() > color_attachments.len() {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create graphics pipeline: expected at most {} blend states, got {}",
+        color_attachments.len(),
+        blend_states.len()
+      )));
+    }
+    blend_states.resize(color_attachments.len(), rhi::BlendState::default());
+    if color_ops.len() != color_attachments.len() {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create graphics pipeline: expected {} color attachment ops, got {}",
+        color_attachments.len(),
+        color_ops.len()
+      )));
+    }
+    if depth_ops.is_some() != depth_attachment.is_some() {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: depth ops must be given exactly when there is a depth \
+        attachment".to_string()
+      ));
+    }
+    // The pass has a single subpass
+    let bad_subpass = dependencies
+      .iter()
+      .flat_map(|x| [x.src_subpass, x.dst_subpass])
+      .flatten()
+      .find(|x| *x != 0);
+    if let Some(subpass) = bad_subpass {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create graphics pipeline: dependency on subpass {subpass}, the pass only has subpass 0"
+      )));
+    }
+    // Command compilation moves attachments into the attachment layout before the pass
+    let attachment_layouts = color_attachments
+      .iter()
+      .map(|x| (x, rhi::ImageLayoutType::ColorAttachment))
+      .chain(depth_attachment.iter().map(|x| (x, rhi::ImageLayoutType::DepthAttachment)));
+    for (desc, attachment_layout) in attachment_layouts {
+      if !matches!(desc.initial_layout, rhi::ImageLayoutType::Undefined) &&
+        translate_image_layout(desc.initial_layout) != translate_image_layout(attachment_layout) {
+        return Err(rhi::RhiError::BackendError(format!(
+          "at create graphics pipeline: initial layout of a {:?} attachment must be Undefined or \
+          {attachment_layout:?}, got {:?}",
+          desc.format,
+          desc.initial_layout
+        )));
+      }
+      if matches!(desc.final_layout, rhi::ImageLayoutType::Undefined) {
+        return Err(rhi::RhiError::BackendError(format!(
+          "at create graphics pipeline: final layout of a {:?} attachment can't be Undefined",
+          desc.format
+        )));
+      }
+    }
+    let (needs_wide_lines, needs_large_points) = match raster_config.style {
+      rhi::RasterStyle::Fill | rhi::RasterStyle::FillConservative => {(false, false)}
+      rhi::RasterStyle::WireFrame { thickness } => {(thickness != 1.0, false)}
+      rhi::RasterStyle::Point { size } => {(false, size > 1.0)}
+    };
+    let conservative = matches!(raster_config.style, rhi::RasterStyle::FillConservative);
+    if conservative && !self.conservative_rasterization_supported {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: FillConservative needs VK_EXT_conservative_rasterization, \
+        which the GPU lacks".to_string()
+      ));
+    }
+    let filled =
+      matches!(raster_config.style, rhi::RasterStyle::Fill | rhi::RasterStyle::FillConservative);
+    if !filled && self.device_features.fill_mode_non_solid != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: wireframe and points need fillModeNonSolid, which the GPU \
+        lacks".to_string()
+      ));
+    }
+    if multiview_mask == Some(0) {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: multiview mask has no views".to_string()
+      ));
+    }
+    if multiview_mask.is_some() && !self.multiview_supported {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: multiview needs VK_KHR_multiview, which the GPU lacks"
+          .to_string()
+      ));
+    }
+    if let rhi::PipelineType::MeshShaderPipeline { task_shader, .. } = pipeline_type {
+      if self.mesh_shader_device.is_none() {
+        return Err(rhi::RhiError::BackendError(
+          "at create graphics pipeline: mesh shaders need VK_EXT_mesh_shader, which the GPU \
+          lacks".to_string()
+        ));
+      }
+      if task_shader.is_some() && !self.task_shader_supported {
+        return Err(rhi::RhiError::BackendError(
+          "at create graphics pipeline: the GPU has no task shaders".to_string()
+        ));
+      }
+      if geometry_shader.is_some() || tessellation.is_some() {
+        return Err(rhi::RhiError::BackendError(
+          "at create graphics pipeline: mesh shader pipelines can't have geometry or \
+          tessellation shaders".to_string()
+        ));
+      }
+      if instance_input.is_some() {
+        return Err(rhi::RhiError::BackendError(
+          "at create graphics pipeline: mesh shader pipelines have no instance input".to_string()
+        ));
+      }
+    }
+    if geometry_shader.is_some() && self.device_features.geometry_shader != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: geometry shaders need geometryShader, which the GPU lacks"
+          .to_string()
+      ));
+    }
+    if tessellation.is_some() && self.device_features.tessellation_shader != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: tessellation needs tessellationShader, which the GPU lacks"
+          .to_string()
+      ));
+    }
+    if tessellation.is_some_and(|x| x.patch_control_points == 0) {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: patches need at least one control point".to_string()
+      ));
+    }
+    if multisample.alpha_to_one && self.device_features.alpha_to_one != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: alpha to one needs alphaToOne, which the GPU lacks"
+          .to_string()
+      ));
+    }
+    if raster_config.depth_clamp && self.device_features.depth_clamp != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create graphics pipeline: depth clamp needs depthClamp, which the GPU lacks".to_string()
+      ));
+    }
+    if let Some(depth_bias) = raster_config.depth_bias {
+      self.check_depth_bias_clamp(depth_bias.clamp, "at create graphics pipeline")?;
+    }
+    if needs_wide_lines && self.device_features.wide_lines != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create graphics pipeline: {:?} needs wideLines, which the GPU lacks",
+        raster_config.style
+      )));
+    }
+    if needs_large_points && self.device_features.large_points != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create graphics pipeline: {:?} needs largePoints, which the GPU lacks",
+        raster_config.style
+      )));
+    }
+    unsafe {
+      // Shaders first, an unknown shader leaves nothing to clean up
+      // The vertex or mesh shader, and the task shader
+      let (first_stage, first_shader_vk, task_shader_vk) = match pipeline_type {
+        rhi::PipelineType::VertexPipeline { vertex_shader } => {
+          (vk::ShaderStageFlags::VERTEX, *self.shaders.get_obj(vertex_shader.0)?, None)
+        }
+        rhi::PipelineType::MeshShaderPipeline { task_shader, mesh_shader } => (
+          vk::ShaderStageFlags::MESH_EXT,
+          *self.shaders.get_obj(mesh_shader.0)?,
+          task_shader.map(|x| self.shaders.get_obj(x.0).cloned()).transpose()?,
+        ),
+      };
+      let frag_shader_vk = *self.shaders.get_obj(fragment_shader.0)?;
+      let geom_shader_vk = geometry_shader
+        .map(|x| self.shaders.get_obj(x.0).cloned())
+        .transpose()?;
+      let tess_shaders_vk = tessellation
+        .map(|x| Ok::<_, rhi::RhiError>((
+          *self.shaders.get_obj(x.control_shader.0)?,
+          *self.shaders.get_obj(x.evaluation_shader.0)?,
+        )))
+        .transpose()?;
+      let mut extra_stages = vec![];
+      if let Some(task_shader_vk) = task_shader_vk {
+        extra_stages.push((vk::ShaderStageFlags::TASK_EXT, task_shader_vk));
+      }
+      if let Some(geom_shader_vk) = geom_shader_vk {
+        extra_stages.push((vk::ShaderStageFlags::GEOMETRY, geom_shader_vk));
+      }
+      if let Some((control_shader_vk, evaluation_shader_vk)) = tess_shaders_vk {
+        extra_stages.push((vk::ShaderStageFlags::TESSELLATION_CONTROL, control_shader_vk));
+        extra_stages.push((vk::ShaderStageFlags::TESSELLATION_EVALUATION, evaluation_shader_vk));
+      }
+      // Render pass, not needed with dynamic rendering
+      let sample_count = translate_sample_count(multisample.sample_count);
+      let render_pass = if self.dynamic_rendering_device.is_some() {
+        vk::RenderPass::null()
+      } else {
+        self.create_render_pass(&pass, sample_count, multiview_mask)?
+      };
       // Pipeline layout
-      let buffer_dset_bindings = [
-        vk::DescriptorSetLayoutBinding::default()
-          .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-          .descriptor_count(max_buffer_count)
-          .stage_flags(vk::ShaderStageFlags::ALL)
-      ];
-      let buffer_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
-        .bindings(&buffer_dset_bindings);
-      let buffer_set_layout = self
-        .ash_device
-        .create_descriptor_set_layout(&buffer_set_layout_info, None)
-        .map_err(|e| format!("at buffer set layout creation: {e}"))?;
-      let texture_dset_bindings = [
-        vk::DescriptorSetLayoutBinding::default()
-          .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-          .descriptor_count(max_texture_count)
-          .stage_flags(vk::ShaderStageFlags::ALL)
-      ];
-      let texture_set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
-        .bindings(&texture_dset_bindings);
-      let texture_set_layout = self
-        .ash_device
-        .create_descriptor_set_layout(&texture_set_layout_info, None)
-        .map_err(|e| format!("at texture set layout creation: {e}"))?;
-      let pipeline_set_layouts = [buffer_set_layout, texture_set_layout];
-      let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
-        .set_layouts(&pipeline_set_layouts)
-        .push_constant_ranges(&[
+      let set_layouts = self.create_input_set_layouts(
+        max_buffer_count,
+        uniform_buffer_count,
+        max_texture_count,
+        storage_image_count,
+        translate_shader_stage_flags(rhi::ShaderStageFlags::ALL)
+      );
+      let (buffer_set_layout, texture_set_layout, storage_image_set_layout) = match set_layouts {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_render_pass(render_pass, None);
+          return Err(e);
+        }
+      };
+      let pipeline_set_layouts =
+        [buffer_set_layout, texture_set_layout, storage_image_set_layout];
+      let push_constant_stages = translate_shader_stage_flags(push_constant_stages);
+      let push_constant_ranges = if push_constant_size > 0 {
+        vec![
           vk::PushConstantRange::default()
             .offset(0)
-            .size(128)
-            .stage_flags(vk::ShaderStageFlags::ALL),
-        ]);
-      let pipeline_layout = self
-        .ash_device
-        .create_pipeline_layout(&pipeline_layout_create_info, None)
-        .map_err(|e| format!("at pipeline layout creation: {e}"))?;
+            .size(push_constant_size)
+            .stage_flags(push_constant_stages),
+        ]
+      } else {
+        vec![]
+      };
+      let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&pipeline_set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+      let pipeline_layout =
+        match self.ash_device.create_pipeline_layout(&pipeline_layout_create_info, None) {
+          Ok(x) => x,
+          Err(e) => {
+            self.destroy_pipeline_layouts(vk::PipelineLayout::null(), pipeline_set_layouts);
+            self.ash_device.destroy_render_pass(render_pass, None);
+            return Err(translate_vk_error("at pipeline layout creation", e));
+          }
+        };
       // Pipeline
-      let vert_fr = fs::read(&vertex_shader)
+      let has_instance_input = instance_input.is_some();
+      let state = GraphicsPipelineState {
+        raster_config,
+        multisample,
+        blend_states,
+        depth_state,
+        vertex_spec_constants,
+        fragment_spec_constants,
+        patch_control_points: tessellation.map(|x| x.patch_control_points),
+        color_formats: color_attachments.iter().map(|x| x.format).collect(),
+        depth_format: depth_attachment.map(|x| x.format),
+        multiview_mask,
+        instance_input,
+      };
+      let pipeline = match self.build_graphics_pipeline_vk(
+        &state,
+        pipeline_layout,
+        render_pass,
+        (first_stage, first_shader_vk),
+        frag_shader_vk,
+        &extra_stages
+      ) {
+        Ok(x) => x,
+        Err(e) => {
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          self.ash_device.destroy_render_pass(render_pass, None);
+          return Err(e);
+        }
+      };
+      #[cfg(feature = "hot-reload")]
+      let reload_source = self.pipeline_source(
+        pipeline_type,
+        fragment_shader,
+        !extra_stages.is_empty(),
+        state
+      );
+      let g_pipeline = GraphicsPipeline{
+        pipeline,
+        pipeline_layout,
+        render_pass,
+        sample_count,
+        color_ops: color_ops.clone(),
+        depth_ops: *depth_ops,
+        color_final_layouts: color_attachments
+          .iter()
+          .map(|x| translate_image_layout(x.final_layout))
+          .collect(),
+        depth_final_layout: depth_attachment.map(|x| translate_image_layout(x.final_layout)),
+        buffer_set_layout,
+        texture_set_layout,
+        storage_image_set_layout,
+        uniform_buffer_count,
+        max_texture_count,
+        push_constant_size,
+        push_constant_stages,
+        stencil_reference: depth_state
+          .map(|x| (x.front.reference, x.back.reference))
+          .unwrap_or((0, 0)),
+        depth_bias: raster_config
+          .depth_bias
+          .map(|x| (x.constant, x.clamp, x.slope))
+          .unwrap_or((0.0, 0.0, 0.0)),
+        multiview_mask,
+        mesh_shading: matches!(pipeline_type, rhi::PipelineType::MeshShaderPipeline { .. }),
+        instance_input: has_instance_input,
+        #[cfg(feature = "hot-reload")]
+        reload_source,
+      };
+      let g_pipeline_id = match self.pipelines.add_obj(g_pipeline) {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_pipeline(pipeline, None);
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          self.ash_device.destroy_render_pass(render_pass, None);
+          return Err(e.into());
+        }
+      };
+      Ok(rhi::PipelineID(g_pipeline_id))
+    }
+  }
+
+  fn destroy_pipeline(&mut self, pipeline_id: rhi::PipelineID) -> Result<(), rhi::RhiError> {
+    let g_pipeline = self.pipelines.remove_obj(pipeline_id.0)?;
+    unsafe {
+      self.ash_device.destroy_pipeline(g_pipeline.pipeline, None);
+      self.ash_device.destroy_pipeline_layout(g_pipeline.pipeline_layout, None);
+      // Null when dynamic rendering is used, which vulkan ignores
+      self.ash_device.destroy_render_pass(g_pipeline.render_pass, None);
+      self.ash_device.destroy_descriptor_set_layout(g_pipeline.buffer_set_layout, None);
+      self.ash_device.destroy_descriptor_set_layout(g_pipeline.texture_set_layout, None);
+      self.ash_device.destroy_descriptor_set_layout(g_pipeline.storage_image_set_layout, None);
+    }
+    Ok(())
+  }
+
+  async fn create_compute_pipeline(
+    &mut self,
+    shader: PathBuf,
+    max_buffer_count: u32,
+    max_texture_count: u32,
+    storage_image_count: u32,
+  ) -> Result<rhi::ComputePipelineID, rhi::RhiError> {
+    unsafe {
+      // Shader first, a missing or bad file leaves nothing to clean up
+      let shader_fr = fs::read(&shader)
         .await
-        .map_err(|e| format!("at read vertex shader file: {e}"))?;
-      let vert_data = ash::util::read_spv(&mut std::io::Cursor::new(&vert_fr))
-        .map_err(|e| format!("at read vertex shader: {e}"))?;
-      let vert_shader_vk = self.ash_device.create_shader_module(
-        &vk::ShaderModuleCreateInfo::default().code(&vert_data),
+        .map_err(|e| rhi::RhiError::BackendError(format!("at read compute shader file: {e}")))?;
+      let shader_data = ash::util::read_spv(&mut std::io::Cursor::new(&shader_fr))
+        .map_err(|e| rhi::RhiError::BackendError(format!("at read compute shader: {e}")))?;
+      let shader_vk = self.ash_device.create_shader_module(
+        &vk::ShaderModuleCreateInfo::default().code(&shader_data),
         None
       )
-        .map_err(|e| format!("at vert shader module creation: {e}"))?;
-      let frag_fr =
-        fs::read(&fragment_shader).await.map_err(|e| format!("at read fragment shader file: {e}"))?;
-      let frag_data = ash::util::read_spv(&mut std::io::Cursor::new(&frag_fr))
-        .map_err(|e| format!("at read fragment shader: {e}"))?;
-      let frag_shader_vk = self.ash_device.create_shader_module(
-        &vk::ShaderModuleCreateInfo::default().code(&frag_data),
-        None
-      )
-        .map_err(|e| format!("at frag shader module creation: {e}"))?;
-      let vert_input_info = vk::PipelineVertexInputStateCreateInfo::default();
-      let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::default()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-      let dynamic_states = vk::PipelineDynamicStateCreateInfo::default()
-        .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
-      let msaa_info = vk::PipelineMultisampleStateCreateInfo::default()
-        .sample_shading_enable(false);
-      let vp_state = vk::PipelineViewportStateCreateInfo::default()
-        .viewport_count(1)
-        .scissor_count(1);
-      let raster_style_vk = translate_raster_style(raster_style);
-      let shader_stages = [
-        vk::PipelineShaderStageCreateInfo::default()
-          .name(c"main")
-          .stage(vk::ShaderStageFlags::VERTEX)
-          .module(vert_shader_vk),
-        vk::PipelineShaderStageCreateInfo::default()
-          .name(c"main")
-          .stage(vk::ShaderStageFlags::FRAGMENT)
-          .module(frag_shader_vk),
-      ];
-      let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
-        .render_pass(render_pass)
-        .subpass(0)
-        .layout(pipeline_layout)
-        .vertex_input_state(&vert_input_info)
-        .input_assembly_state(&input_assembly_info)
-        .dynamic_state(&dynamic_states)
-        .multisample_state(&msaa_info)
-        .viewport_state(&vp_state)
-        .rasterization_state(&raster_style_vk)
-        .stages(&shader_stages);
-      let pipeline = self
+        .map_err(|e| translate_vk_error("at compute shader module creation", e))?;
+      // Pipeline layout
+      let set_layouts = self.create_input_set_layouts(
+        max_buffer_count,
+        0,
+        max_texture_count,
+        storage_image_count,
+        translate_shader_stage_flags(rhi::ShaderStageFlags::COMPUTE)
+      );
+      let (buffer_set_layout, texture_set_layout, storage_image_set_layout) = match set_layouts {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_shader_module(shader_vk, None);
+          return Err(e);
+        }
+      };
+      let pipeline_set_layouts =
+        [buffer_set_layout, texture_set_layout, storage_image_set_layout];
+      let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default()
+        .set_layouts(&pipeline_set_layouts);
+      let pipeline_layout = self
         .ash_device
-        .create_graphics_pipelines(
-          vk::PipelineCache::null(),
+        .create_pipeline_layout(&pipeline_layout_create_info, None)
+        .map_err(|e| translate_vk_error("at pipeline layout creation", e));
+      // Pipeline
+      let pipeline = pipeline_layout.and_then(|pipeline_layout| {
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::default()
+          .layout(pipeline_layout)
+          .stage(
+            vk::PipelineShaderStageCreateInfo::default()
+              .name(c"main")
+              .stage(vk::ShaderStageFlags::COMPUTE)
+              .module(shader_vk)
+          );
+        match self.ash_device.create_compute_pipelines(
+          self.pipeline_cache,
           &[pipeline_create_info],
           None
-        )
-        .map_err(|e| format!("at create pipeline: {}", e.1))?
-        .remove(0);
-      let g_pipeline = GraphicsPipeline{
+        ) {
+          Ok(mut x) => Ok((pipeline_layout, x.remove(0))),
+          Err((_, e)) => {
+            self.ash_device.destroy_pipeline_layout(pipeline_layout, None);
+            Err(translate_vk_error("at create compute pipeline", e))
+          }
+        }
+      });
+      // The pipeline doesn't need the module once it is created
+      self.ash_device.destroy_shader_module(shader_vk, None);
+      let (pipeline_layout, pipeline) = match pipeline {
+        Ok(x) => x,
+        Err(e) => {
+          self.destroy_pipeline_layouts(vk::PipelineLayout::null(), pipeline_set_layouts);
+          return Err(e);
+        }
+      };
+      let c_pipeline = ComputePipeline{
         pipeline,
         pipeline_layout,
-        render_pass,
         buffer_set_layout,
         texture_set_layout,
+        storage_image_set_layout,
+        max_texture_count,
       };
-      let g_pipeline_id = self.pipelines.add_obj(g_pipeline)?;
-      Ok(rhi::PipelineID(g_pipeline_id))
+      let c_pipeline_id = match self.compute_pipelines.add_obj(c_pipeline) {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_pipeline(pipeline, None);
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          return Err(e.into());
+        }
+      };
+      Ok(rhi::ComputePipelineID(c_pipeline_id))
+    }
+  }
+
+  fn create_ray_tracing_pipeline(
+    &mut self,
+    desc: rhi::RayTracingPipelineDesc
+  ) -> Result<rhi::RayTracingPipelineID, rhi::RhiError> {
+    let rhi::RayTracingPipelineDesc {
+      raygen,
+      miss,
+      hit_groups,
+      max_recursion,
+      max_buffer_count,
+      max_texture_count,
+      storage_image_count,
+    } = desc;
+    let Some(ray_tracing_device) = self.ray_tracing_device.as_ref() else {
+      return Err(rhi::RhiError::BackendError(
+        "at create ray tracing pipeline: needs VK_KHR_ray_tracing_pipeline, which the GPU lacks"
+          .to_string()
+      ));
+    };
+    let props = self.ray_tracing_properties;
+    if max_recursion > props.max_ray_recursion_depth {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at create ray tracing pipeline: recursion depth {max_recursion} exceeds the limit {}",
+        props.max_ray_recursion_depth
+      )));
+    }
+    let shader_stage = |shader: rhi::ShaderID, stage: vk::ShaderStageFlags| {
+      self.shaders.get_obj(shader.0).map(|x| vk::PipelineShaderStageCreateInfo::default()
+        .name(c"main")
+        .stage(stage)
+        .module(*x)
+      )
+    };
+    // Raygen and miss shaders are general groups, hit groups reference their stages by index
+    let mut stages = vec![shader_stage(raygen, vk::ShaderStageFlags::RAYGEN_KHR)?];
+    for shader in &miss {
+      stages.push(shader_stage(*shader, vk::ShaderStageFlags::MISS_KHR)?);
+    }
+    let general_group = |stage: usize| vk::RayTracingShaderGroupCreateInfoKHR::default()
+      .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+      .general_shader(stage as u32)
+      .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+      .any_hit_shader(vk::SHADER_UNUSED_KHR)
+      .intersection_shader(vk::SHADER_UNUSED_KHR);
+    let mut groups = (0..stages.len()).map(general_group).collect::<Vec<_>>();
+    for hit_group in &hit_groups {
+      let mut add_stage = |shader: Option<rhi::ShaderID>, stage| match shader {
+        Some(shader) => {
+          stages.push(shader_stage(shader, stage)?);
+          Ok::<_, rhi::RhiError>(stages.len() as u32 - 1)
+        }
+        None => Ok(vk::SHADER_UNUSED_KHR),
+      };
+      let closest_hit = add_stage(hit_group.closest_hit, vk::ShaderStageFlags::CLOSEST_HIT_KHR)?;
+      let any_hit = add_stage(hit_group.any_hit, vk::ShaderStageFlags::ANY_HIT_KHR)?;
+      let intersection =
+        add_stage(hit_group.intersection, vk::ShaderStageFlags::INTERSECTION_KHR)?;
+      let ty = if hit_group.intersection.is_some() {
+        vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP
+      } else {
+        vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP
+      };
+      groups.push(vk::RayTracingShaderGroupCreateInfoKHR::default()
+        .ty(ty)
+        .general_shader(vk::SHADER_UNUSED_KHR)
+        .closest_hit_shader(closest_hit)
+        .any_hit_shader(any_hit)
+        .intersection_shader(intersection)
+      );
+    }
+    unsafe {
+      // Pipeline layout
+      let stage_flags = vk::ShaderStageFlags::RAYGEN_KHR |
+        vk::ShaderStageFlags::MISS_KHR |
+        vk::ShaderStageFlags::CLOSEST_HIT_KHR |
+        vk::ShaderStageFlags::ANY_HIT_KHR |
+        vk::ShaderStageFlags::INTERSECTION_KHR;
+      let (buffer_set_layout, texture_set_layout, storage_image_set_layout) = self
+        .create_input_set_layouts(
+          max_buffer_count,
+          0,
+          max_texture_count,
+          storage_image_count,
+          stage_flags
+        )?;
+      let pipeline_set_layouts =
+        [buffer_set_layout, texture_set_layout, storage_image_set_layout];
+      let pipeline_layout = match self.ash_device.create_pipeline_layout(
+        &vk::PipelineLayoutCreateInfo::default().set_layouts(&pipeline_set_layouts),
+        None
+      ) {
+        Ok(x) => x,
+        Err(e) => {
+          self.destroy_pipeline_layouts(vk::PipelineLayout::null(), pipeline_set_layouts);
+          return Err(translate_vk_error("at pipeline layout creation", e));
+        }
+      };
+      // Pipeline
+      let pipeline_create_info = vk::RayTracingPipelineCreateInfoKHR::default()
+        .stages(&stages)
+        .groups(&groups)
+        .max_pipeline_ray_recursion_depth(max_recursion)
+        .layout(pipeline_layout);
+      let pipeline = match ray_tracing_device.create_ray_tracing_pipelines(
+        vk::DeferredOperationKHR::null(),
+        self.pipeline_cache,
+        &[pipeline_create_info],
+        None
+      ) {
+        Ok(mut x) => x.remove(0),
+        Err((_, e)) => {
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          return Err(translate_vk_error("at create ray tracing pipeline", e));
+        }
+      };
+      let sbt = self.create_shader_binding_table(pipeline, miss.len(), hit_groups.len());
+      let (sbt_buffer, [raygen_region, miss_region, hit_region]) = match sbt {
+        Ok(x) => x,
+        Err(e) => {
+          self.ash_device.destroy_pipeline(pipeline, None);
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          return Err(e);
+        }
+      };
+      let rt_pipeline = RayTracingPipeline {
+        pipeline,
+        pipeline_layout,
+        buffer_set_layout,
+        texture_set_layout,
+        storage_image_set_layout,
+        max_texture_count,
+        sbt_buffer,
+        raygen_region,
+        miss_region,
+        hit_region,
+      };
+      let rt_pipeline_id = match self.ray_tracing_pipelines.add_obj(rt_pipeline) {
+        Ok(x) => x,
+        Err(e) => {
+          rhi::RenderBackend::destroy_buffer(self, sbt_buffer)?;
+          self.ash_device.destroy_pipeline(pipeline, None);
+          self.destroy_pipeline_layouts(pipeline_layout, pipeline_set_layouts);
+          return Err(e.into());
+        }
+      };
+      Ok(rhi::RayTracingPipelineID(rt_pipeline_id))
     }
   }
 
+  fn destroy_ray_tracing_pipeline(
+    &mut self,
+    pipeline_id: rhi::RayTracingPipelineID
+  ) -> Result<(), rhi::RhiError> {
+    let rt_pipeline = self.ray_tracing_pipelines.remove_obj(pipeline_id.0)?;
+    unsafe {
+      self.ash_device.destroy_pipeline(rt_pipeline.pipeline, None);
+      self.ash_device.destroy_pipeline_layout(rt_pipeline.pipeline_layout, None);
+      self.ash_device.destroy_descriptor_set_layout(rt_pipeline.buffer_set_layout, None);
+      self.ash_device.destroy_descriptor_set_layout(rt_pipeline.texture_set_layout, None);
+      self.ash_device.destroy_descriptor_set_layout(rt_pipeline.storage_image_set_layout, None);
+    }
+    rhi::RenderBackend::destroy_buffer(self, rt_pipeline.sbt_buffer)
+  }
+
+  fn create_blas(&mut self, desc: rhi::BLASDesc) -> Result<rhi::AccelStructID, rhi::RhiError> {
+    unsafe {
+      let vertex_address = self.buffer_address(desc.vertex_buffer)?;
+      let index_address = desc.index_buffer.map(|x| self.buffer_address(x)).transpose()?;
+      let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+        .vertex_format(vk::Format::R32G32B32_SFLOAT)
+        .vertex_data(vk::DeviceOrHostAddressConstKHR { device_address: vertex_address })
+        .vertex_stride(desc.stride as u64)
+        .max_vertex(desc.vertex_count.saturating_sub(1))
+        .index_type(if index_address.is_some() { vk::IndexType::UINT32 } else {
+          vk::IndexType::NONE_KHR
+        })
+        .index_data(vk::DeviceOrHostAddressConstKHR {
+          device_address: index_address.unwrap_or(0)
+        });
+      let geometry = vk::AccelerationStructureGeometryKHR::default()
+        .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+        .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+        .flags(vk::GeometryFlagsKHR::OPAQUE);
+      let triangle_count = if desc.index_buffer.is_some() {
+        desc.index_count / 3
+      } else {
+        desc.vertex_count / 3
+      };
+      self.build_accel_struct(
+        vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+        geometry,
+        triangle_count,
+        "at create blas"
+      )
+    }
+  }
+
+  fn create_tlas(
+    &mut self,
+    instances: Vec<rhi::TLASInstance>
+  ) -> Result<rhi::AccelStructID, rhi::RhiError> {
+    let instances_vk = instances
+      .iter()
+      .map(|x| Ok(vk::AccelerationStructureInstanceKHR {
+        transform: vk::TransformMatrixKHR { matrix: x.transform },
+        instance_custom_index_and_mask: vk::Packed24_8::new(x.instance_id, x.mask),
+        instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+          x.hit_group_offset,
+          vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8
+        ),
+        acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+          device_handle: self.accel_structs.get_obj(x.blas.0)?.address
+        },
+      }))
+      .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+    unsafe {
+      // Host writes are visible to the build once it is submitted
+      let instances_size = size_of_val(instances_vk.as_slice());
+      let instance_buffer = self.create_buffer_vk(
+        instances_size.max(1) as u64,
+        vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR |
+          vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        rhi::MemoryLocation::CPUToGPU
+      )?;
+      std::ptr::copy_nonoverlapping(
+        instances_vk.as_ptr() as *const u8,
+        rhi::RenderBackend::map_buffer(self, instance_buffer)?,
+        instances_size
+      );
+      let instances_data = vk::AccelerationStructureGeometryInstancesDataKHR::default()
+        .data(vk::DeviceOrHostAddressConstKHR {
+          device_address: self.buffer_address(instance_buffer)?
+        });
+      let geometry = vk::AccelerationStructureGeometryKHR::default()
+        .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+        .geometry(vk::AccelerationStructureGeometryDataKHR { instances: instances_data });
+      let tlas = self.build_accel_struct(
+        vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+        geometry,
+        instances_vk.len() as u32,
+        "at create tlas"
+      );
+      rhi::RenderBackend::destroy_buffer(self, instance_buffer)?;
+      tlas
+    }
+  }
+
+  fn destroy_accel_struct(
+    &mut self,
+    accel_struct: rhi::AccelStructID
+  ) -> Result<(), rhi::RhiError> {
+    let a_accel_struct = self.accel_structs.remove_obj(accel_struct.0)?;
+    if let Some(accel_struct_device) = self.accel_struct_device.as_ref() {
+      unsafe {
+        accel_struct_device.destroy_acceleration_structure(a_accel_struct.accel_struct, None);
+      }
+    }
+    rhi::RenderBackend::destroy_buffer(self, a_accel_struct.buffer)
+  }
+
   fn create_frame_buffer(
     &mut self,
     pipeline_id: rhi::PipelineID,
     color_attachments: Vec<rhi::ImageID>,
-    depth_attachment: Option<rhi::ImageID>
-  ) -> Result<rhi::FramebufferID, String>{
+    depth_attachment: Option<rhi::ImageID>,
+    resolve_attachments: Vec<rhi::ImageID>,
+  ) -> Result<rhi::FramebufferID, rhi::RhiError>{
     unsafe {
       let g_pipeline = self.pipelines.get_obj(pipeline_id.0)?;
+      let expected_resolve_count = if g_pipeline.sample_count == vk::SampleCountFlags::TYPE_1 {
+        0
+      } else {
+        color_attachments.len()
+      };
+      if resolve_attachments.len() != expected_resolve_count {
+        return Err(rhi::RhiError::BackendError(format!(
+          "at create framebuffer: expected {expected_resolve_count} resolve attachments, got {}",
+          resolve_attachments.len()
+        )));
+      }
       let mut attachment_ids = color_attachments.clone();
       depth_attachment.map(|d| attachment_ids.push(d));
+      attachment_ids.extend(resolve_attachments.iter().cloned());
+      if let Some(mask) = g_pipeline.multiview_mask {
+        let view_count = u32::BITS - mask.leading_zeros();
+        for att_id in attachment_ids.iter() {
+          let layer_count = self.images.get_obj(att_id.0)?.layer_count;
+          if layer_count < view_count {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at create framebuffer: multiview mask {mask:#b} needs {view_count} layers, \
+              image {} has {layer_count}",
+              att_id.0
+            )));
+          }
+        }
+      }
       let attachments = attachment_ids
         .iter()
         .map(|x| self.images.get_obj(x.0).map(|img| img.view))
-        .collect::<Result<Vec<_>, &str>>()?;
+        .collect::<Result<Vec<_>, StoreError>>()?;
       let res = self.images.get_obj(color_attachments[0].0)?.resolution;
       let fb_create_info = vk::FramebufferCreateInfo::default()
         .render_pass(g_pipeline.render_pass)
@@ -713,42 +4362,95 @@ impl rhi::RenderBackend for VulkanBackend {
         .layers(1)
         .width(res.width)
         .height(res.height);
-      let frame_buffer = self
-        .ash_device
-        .create_framebuffer(&fb_create_info, None)
-        .map(|x| FramebufferVK{framebuffer: x, color_attachments, depth_attachment})
-        .map_err(|e| format!("at create framebuffer: {e}"))?;
+      // With dynamic rendering the framebuffer only tracks its attachments
+      let framebuffer_vk = if self.dynamic_rendering_device.is_some() {
+        Ok(vk::Framebuffer::null())
+      } else {
+        self.ash_device.create_framebuffer(&fb_create_info, None)
+      };
+      let frame_buffer = framebuffer_vk
+        .map(|x| FramebufferVK{
+          framebuffer: x,
+          color_attachments,
+          depth_attachment,
+          resolve_attachments
+        })
+        .map_err(|e| translate_vk_error("at create framebuffer", e))?;
       let fb_id_u32 = self.frame_buffers.add_obj(frame_buffer)?;
       Ok(rhi::FramebufferID(fb_id_u32))
     }
   }
 
+  fn destroy_framebuffer(
+    &mut self,
+    framebuffer_id: rhi::FramebufferID
+  ) -> Result<(), rhi::RhiError> {
+    let framebuffer_vk = self.frame_buffers.remove_obj(framebuffer_id.0)?;
+    unsafe {
+      // Null when dynamic rendering is used, which vulkan ignores
+      self.ash_device.destroy_framebuffer(framebuffer_vk.framebuffer, None);
+    }
+    Ok(())
+  }
+
   fn create_input_set(
     &mut self,
-    pipeline_id: rhi::PipelineID,
-  ) -> Result<rhi::InputSetID, String> {
+    pipeline_id: rhi::PipelineID,
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    unsafe {
+      let pipeline = self.pipelines.get_obj(pipeline_id.0)?;
+      let buffer_set_layout = pipeline.buffer_set_layout;
+      let texture_set_layout = pipeline.texture_set_layout;
+      let storage_image_set_layout = pipeline.storage_image_set_layout;
+      let uniform_buffer_count = pipeline.uniform_buffer_count;
+      let max_texture_count = pipeline.max_texture_count;
+      self.allocate_input_set(
+        buffer_set_layout,
+        texture_set_layout,
+        storage_image_set_layout,
+        uniform_buffer_count,
+        max_texture_count
+      )
+    }
+  }
+
+  fn create_compute_input_set(
+    &mut self,
+    pipeline_id: rhi::ComputePipelineID,
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
+    unsafe {
+      let pipeline = self.compute_pipelines.get_obj(pipeline_id.0)?;
+      let buffer_set_layout = pipeline.buffer_set_layout;
+      let texture_set_layout = pipeline.texture_set_layout;
+      let storage_image_set_layout = pipeline.storage_image_set_layout;
+      let max_texture_count = pipeline.max_texture_count;
+      self.allocate_input_set(
+        buffer_set_layout,
+        texture_set_layout,
+        storage_image_set_layout,
+        0,
+        max_texture_count
+      )
+    }
+  }
+
+  fn create_ray_tracing_input_set(
+    &mut self,
+    pipeline_id: rhi::RayTracingPipelineID,
+  ) -> Result<rhi::InputSetID, rhi::RhiError> {
     unsafe {
-      let pipeline = self.pipelines.get_obj(pipeline_id.0)?;
+      let pipeline = self.ray_tracing_pipelines.get_obj(pipeline_id.0)?;
       let buffer_set_layout = pipeline.buffer_set_layout;
       let texture_set_layout = pipeline.texture_set_layout;
-      let desc_sets = self
-        .ash_device
-        .allocate_descriptor_sets(
-          &vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(self.descriptor_pool)
-            .set_layouts(&[buffer_set_layout, texture_set_layout]),
-        )
-        .map_err(|e| format!("at allocate buffer descriptor set: {e}"))?;
-      let buffer_set = desc_sets[0];
-      let texture_set = desc_sets[1];
-      let b_descriptor_sets = InputSetVK {
-        buffer_set,
-        texture_set,
-        bound_buffers: vec![],
-        bound_textures: vec![]
-      };
-      let bds_id_u32 = self.descriptor_sets.add_obj(b_descriptor_sets)?;
-      Ok(rhi::InputSetID(bds_id_u32))
+      let storage_image_set_layout = pipeline.storage_image_set_layout;
+      let max_texture_count = pipeline.max_texture_count;
+      self.allocate_input_set(
+        buffer_set_layout,
+        texture_set_layout,
+        storage_image_set_layout,
+        0,
+        max_texture_count
+      )
     }
   }
 
@@ -756,12 +4458,50 @@ impl rhi::RenderBackend for VulkanBackend {
     &mut self,
     input_set: rhi::InputSetID,
     buffers: Vec<rhi::BufferID>,
-    textures: Vec<rhi::ImageID>
-  ) -> Result<(), String> {
+    uniform_buffers: Vec<rhi::BufferID>,
+    textures: Vec<(rhi::ImageViewID, rhi::SamplerID)>,
+    storage_images: Vec<rhi::ImageID>,
+  ) -> Result<(), rhi::RhiError> {
+    // Barriers are inferred per image, so the set tracks the images behind the views
+    let texture_images = textures
+      .iter()
+      .map(|(view_id, _)| self.image_views.get_obj(view_id.0).map(|x| x.1))
+      .collect::<Result<Vec<_>, _>>()?;
     unsafe {
       let b_desc_sets = self.descriptor_sets.get_obj(input_set.0)?;
-      let buffer_infos = buffers
+      if uniform_buffers.len() > b_desc_sets.uniform_buffer_count as usize {
+        return Err(rhi::RhiError::BackendError(format!(
+          "at update input set: {} uniform buffers given, the set holds {}",
+          uniform_buffers.len(),
+          b_desc_sets.uniform_buffer_count
+        )));
+      }
+      let uniform_buffer_infos = uniform_buffers
+        .iter()
+        .map(|x| self.buffers.get_obj(x.0))
+        .collect::<Result<Vec<_>, _>>()?
         .into_iter()
+        .map(|x| {
+          if x.size > self.max_uniform_buffer_range as u64 {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at update input set: uniform buffer of size {} exceeds maxUniformBufferRange {}",
+              x.size,
+              self.max_uniform_buffer_range
+            )));
+          }
+          Ok(vk::DescriptorBufferInfo::default()
+            .buffer(x.buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+      let uniform_buffer_write_info = vk::WriteDescriptorSet::default()
+        .dst_set(b_desc_sets.buffer_set)
+        .dst_binding(1)
+        .descriptor_type(translate_descriptor_type(rhi::DescriptorType::Uniform))
+        .buffer_info(&uniform_buffer_infos);
+      let buffer_infos = buffers
+        .iter()
         .map(|x| self.buffers.get_obj(x.0))
         .collect::<Result<Vec<_>, _>>()?
         .into_iter()
@@ -774,32 +4514,187 @@ impl rhi::RenderBackend for VulkanBackend {
         .dst_set(b_desc_sets.buffer_set)
         .dst_binding(0)
         .descriptor_type(translate_descriptor_type(rhi::DescriptorType::Storage))
-        .descriptor_count(1)
         .buffer_info(&buffer_infos);
       let image_infos = textures
-        .into_iter()
+        .iter()
+        .map(|(view_id, sampler_id)| {
+          let (view, _) = self.image_views.get_obj(view_id.0)?;
+          let sampler = self.samplers.get_obj(sampler_id.0)?;
+          Ok(vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(*view)
+            .sampler(*sampler))
+        })
+        .collect::<Result<Vec<_>, StoreError>>()?;
+      let texture_write_info = vk::WriteDescriptorSet::default()
+        .dst_set(b_desc_sets.texture_set)
+        .dst_binding(0)
+        .descriptor_type(translate_descriptor_type(rhi::DescriptorType::CombinedImageSampler))
+        .image_info(&image_infos);
+      let storage_image_infos = storage_images
+        .iter()
         .map(|x| self.images.get_obj(x.0))
         .collect::<Result<Vec<_>, _>>()?
         .into_iter()
         .map(|x| vk::DescriptorImageInfo::default()
-          .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+          .image_layout(vk::ImageLayout::GENERAL)
           .image_view(x.view))
         .collect::<Vec<_>>();
-      let texture_write_info = vk::WriteDescriptorSet::default()
-        .dst_set(b_desc_sets.texture_set)
+      let storage_image_write_info = vk::WriteDescriptorSet::default()
+        .dst_set(b_desc_sets.storage_image_set)
         .dst_binding(0)
-        .descriptor_type(translate_descriptor_type(rhi::DescriptorType::Sampler2D))
-        .descriptor_count(1)
-        .image_info(&image_infos);
+        .descriptor_type(translate_descriptor_type(rhi::DescriptorType::StorageImage))
+        .image_info(&storage_image_infos);
+      // The infos set descriptor_count, vulkan rejects writes of 0 descriptors
+      let writes = [
+        buffer_write_info,
+        uniform_buffer_write_info,
+        texture_write_info,
+        storage_image_write_info
+      ]
+        .into_iter()
+        .filter(|x| x.descriptor_count > 0)
+        .collect::<Vec<_>>();
+      self.ash_device.update_descriptor_sets(&writes, &[]);
+    }
+    let b_desc_sets = self.descriptor_sets.get_obj_mut(input_set.0)?;
+    b_desc_sets.bound_buffers =
+      buffers.into_iter().enumerate().map(|(i, x)| (i as u32, x)).collect();
+    b_desc_sets.bound_textures =
+      texture_images.into_iter().enumerate().map(|(i, x)| (i as u32, x)).collect();
+    b_desc_sets.bound_storage_images = storage_images;
+    Ok(())
+  }
+
+  fn update_input_set_buffer(
+    &mut self,
+    set: rhi::InputSetID,
+    binding: u32,
+    buffer: rhi::BufferID
+  ) -> Result<(), rhi::RhiError> {
+    let buffer_vk = self.buffers.get_obj(buffer.0)?.buffer;
+    let b_desc_sets = self.descriptor_sets.get_obj(set.0)?;
+    let buffer_infos = [
+      vk::DescriptorBufferInfo::default().buffer(buffer_vk).offset(0).range(vk::WHOLE_SIZE)
+    ];
+    unsafe {
+      self.ash_device.update_descriptor_sets(
+        &[
+          vk::WriteDescriptorSet::default()
+            .dst_set(b_desc_sets.buffer_set)
+            .dst_binding(0)
+            .dst_array_element(binding)
+            .descriptor_type(translate_descriptor_type(rhi::DescriptorType::Storage))
+            .buffer_info(&buffer_infos)
+        ],
+        &[]
+      );
+    }
+    self.descriptor_sets.get_obj_mut(set.0)?.bound_buffers.insert(binding, buffer);
+    Ok(())
+  }
+
+  fn update_input_set_texture(
+    &mut self,
+    set: rhi::InputSetID,
+    binding: u32,
+    image_view: rhi::ImageViewID,
+    sampler: rhi::SamplerID
+  ) -> Result<(), rhi::RhiError> {
+    let (view, image) = *self.image_views.get_obj(image_view.0)?;
+    let sampler_vk = *self.samplers.get_obj(sampler.0)?;
+    let b_desc_sets = self.descriptor_sets.get_obj(set.0)?;
+    let image_infos = [
+      vk::DescriptorImageInfo::default()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(view)
+        .sampler(sampler_vk)
+    ];
+    unsafe {
       self.ash_device.update_descriptor_sets(
-        &[buffer_write_info, texture_write_info],
+        &[
+          vk::WriteDescriptorSet::default()
+            .dst_set(b_desc_sets.texture_set)
+            .dst_binding(0)
+            .dst_array_element(binding)
+            .descriptor_type(translate_descriptor_type(rhi::DescriptorType::CombinedImageSampler))
+            .image_info(&image_infos)
+        ],
         &[]
       );
     }
+    self.descriptor_sets.get_obj_mut(set.0)?.bound_textures.insert(binding, image);
+    Ok(())
+  }
+
+  fn register_texture_bindless(
+    &mut self,
+    set: rhi::InputSetID,
+    slot: u32,
+    image_view: rhi::ImageViewID,
+    sampler: rhi::SamplerID
+  ) -> Result<(), rhi::RhiError> {
+    if self.bindless_texture_limit.is_none() {
+      return Err(rhi::RhiError::BackendError(
+        "at register texture bindless: bindless textures aren't enabled".to_string()
+      ));
+    }
+    let texture_count = self.descriptor_sets.get_obj(set.0)?.texture_count;
+    if slot >= texture_count {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at register texture bindless: slot {slot} is out of the set's {texture_count} textures"
+      )));
+    }
+    rhi::RenderBackend::update_input_set_texture(self, set, slot, image_view, sampler)
+  }
+
+  fn destroy_input_set(&mut self, input_set: rhi::InputSetID) -> Result<(), rhi::RhiError> {
+    let input_set_vk = self.descriptor_sets.remove_obj(input_set.0)?;
+    unsafe {
+      self
+        .ash_device
+        .free_descriptor_sets(
+          self.descriptor_pools[input_set_vk.pool_index as usize],
+          &[input_set_vk.buffer_set, input_set_vk.texture_set, input_set_vk.storage_image_set]
+        )
+        .map_err(|e| translate_vk_error("at free descriptor sets", e))
+    }
+  }
+
+  fn create_sampler(&mut self, desc: rhi::SamplerDesc) -> Result<rhi::SamplerID, rhi::RhiError> {
+    unsafe {
+      let anisotropy_enable =
+        desc.anisotropy_max > 1.0 && self.device_features.sampler_anisotropy == vk::TRUE;
+      let sampler_create_info = vk::SamplerCreateInfo::default()
+        .mag_filter(translate_sampler_filter(desc.mag_filter))
+        .min_filter(translate_sampler_filter(desc.min_filter))
+        .mipmap_mode(translate_sampler_mip_mode(desc.mip_mode))
+        .address_mode_u(translate_sampler_address_mode(desc.address_u))
+        .address_mode_v(translate_sampler_address_mode(desc.address_v))
+        .address_mode_w(translate_sampler_address_mode(desc.address_w))
+        .anisotropy_enable(anisotropy_enable)
+        .max_anisotropy(desc.anisotropy_max)
+        .mip_lod_bias(desc.lod_bias)
+        .min_lod(desc.min_lod)
+        .max_lod(desc.max_lod);
+      let sampler = self
+        .ash_device
+        .create_sampler(&sampler_create_info, None)
+        .map_err(|e| translate_vk_error("at create sampler", e))?;
+      let sampler_id_u32 = self.samplers.add_obj(sampler)?;
+      Ok(rhi::SamplerID(sampler_id_u32))
+    }
+  }
+
+  fn destroy_sampler(&mut self, sampler_id: rhi::SamplerID) -> Result<(), rhi::RhiError> {
+    let sampler = self.samplers.remove_obj(sampler_id.0)?;
+    unsafe {
+      self.ash_device.destroy_sampler(sampler, None);
+    }
     Ok(())
   }
 
-  fn create_fence(&mut self, signaled: bool) -> Result<rhi::FenceID, String> {
+  fn create_fence(&mut self, signaled: bool) -> Result<rhi::FenceID, rhi::RhiError> {
     unsafe {
       let fence_create_flags = if signaled {
         vk::FenceCreateFlags::SIGNALED
@@ -809,23 +4704,175 @@ impl rhi::RenderBackend for VulkanBackend {
       let fence_vk = self
         .ash_device
         .create_fence(&vk::FenceCreateInfo::default().flags(fence_create_flags), None)
-        .map_err(|e| format!("at create fence: {e}"))?;
+        .map_err(|e| translate_vk_error("at create fence", e))?;
       let fence_id_u32 = self.fences.add_obj(fence_vk)?;
       Ok(rhi::FenceID(fence_id_u32))
     }
   }
 
-  async fn wait_for_fence(&self, fence_id: rhi::FenceID) -> Result<(), String> {
+  async fn wait_for_fence(&self, fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
     unsafe {
       let fence = self.fences.get_obj(fence_id.0)?;
       self
         .ash_device
         .wait_for_fences(&[*fence], true, u64::MAX)
-        .map_err(|e| format!("at wait_for_fence: {e}"))
+        .map_err(|e| translate_vk_error("at wait_for_fence", e))
+    }
+  }
+
+  fn reset_fence(&self, fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
+    unsafe {
+      let fence = self.fences.get_obj(fence_id.0)?;
+      self
+        .ash_device
+        .reset_fences(&[*fence])
+        .map_err(|e| translate_vk_error("at reset_fence", e))
+    }
+  }
+
+  fn destroy_fence(&mut self, fence_id: rhi::FenceID) -> Result<(), rhi::RhiError> {
+    let fence = self.fences.remove_obj(fence_id.0)?;
+    unsafe {
+      self.ash_device.destroy_fence(fence, None);
+    }
+    Ok(())
+  }
+
+  fn create_semaphore(&mut self) -> Result<rhi::SemaphoreID, rhi::RhiError> {
+    unsafe {
+      let semaphore_vk = self
+        .ash_device
+        .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+        .map_err(|e| translate_vk_error("at create semaphore", e))?;
+      let semaphore_id_u32 = self.semaphores.add_obj(semaphore_vk)?;
+      Ok(rhi::SemaphoreID(semaphore_id_u32))
+    }
+  }
+
+  fn destroy_semaphore(&mut self, semaphore_id: rhi::SemaphoreID) -> Result<(), rhi::RhiError> {
+    let semaphore = self.semaphores.remove_obj(semaphore_id.0)?;
+    unsafe {
+      self.ash_device.destroy_semaphore(semaphore, None);
+    }
+    Ok(())
+  }
+
+  fn create_timestamp_query_pool(&mut self, count: u32) -> Result<rhi::QueryPoolID, rhi::RhiError> {
+    unsafe {
+      let pool_vk = self
+        .ash_device
+        .create_query_pool(
+          &vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(count),
+          None
+        )
+        .map_err(|e| translate_vk_error("at create query pool", e))?;
+      let pool_id_u32 = self.query_pools.add_obj((pool_vk, rhi::PipelineStatFlags::empty()))?;
+      Ok(rhi::QueryPoolID(pool_id_u32))
+    }
+  }
+
+  fn create_pipeline_stats_query_pool(
+    &mut self,
+    count: u32,
+    stats: rhi::PipelineStatFlags
+  ) -> Result<rhi::QueryPoolID, rhi::RhiError> {
+    if self.device_features.pipeline_statistics_query != vk::TRUE {
+      return Err(rhi::RhiError::BackendError(
+        "at create pipeline stats query pool: the GPU lacks pipelineStatisticsQuery".to_string()
+      ));
+    }
+    if stats.is_empty() {
+      return Err(rhi::RhiError::BackendError(
+        "at create pipeline stats query pool: no statistics requested".to_string()
+      ));
+    }
+    unsafe {
+      let pool_vk = self
+        .ash_device
+        .create_query_pool(
+          &vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::PIPELINE_STATISTICS)
+            .query_count(count)
+            .pipeline_statistics(translate_pipeline_stat_flags(stats)),
+          None
+        )
+        .map_err(|e| translate_vk_error("at create query pool", e))?;
+      let pool_id_u32 = self.query_pools.add_obj((pool_vk, stats))?;
+      Ok(rhi::QueryPoolID(pool_id_u32))
+    }
+  }
+
+  fn read_pipeline_stats(
+    &self,
+    pool: rhi::QueryPoolID,
+    index: u32
+  ) -> Result<rhi::PipelineStatsResult, rhi::RhiError> {
+    let (pool_vk, stats) = *self.query_pools.get_obj(pool.0)?;
+    if stats.is_empty() {
+      return Err(rhi::RhiError::BackendError(format!(
+        "at read pipeline stats: query pool {} is a timestamp pool",
+        pool.0
+      )));
+    }
+    // One value per counted statistic, in flag order
+    let mut values = vec![0u64; stats.bits().count_ones() as usize];
+    unsafe {
+      self
+        .ash_device
+        .get_query_pool_results(
+          pool_vk,
+          index,
+          &mut values,
+          vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT
+        )
+        .map_err(|e| translate_vk_error("at get query pool results", e))?;
+    }
+    let mut values = values.into_iter();
+    let mut next_if = |flag| if stats.contains(flag) { values.next() } else { None };
+    Ok(rhi::PipelineStatsResult {
+      input_assembly_vertices: next_if(rhi::PipelineStatFlags::INPUT_ASSEMBLY_VERTICES),
+      input_assembly_primitives: next_if(rhi::PipelineStatFlags::INPUT_ASSEMBLY_PRIMITIVES),
+      vertex_shader_invocations: next_if(rhi::PipelineStatFlags::VERTEX_SHADER_INVOCATIONS),
+      clipping_invocations: next_if(rhi::PipelineStatFlags::CLIPPING_INVOCATIONS),
+      clipping_primitives: next_if(rhi::PipelineStatFlags::CLIPPING_PRIMITIVES),
+      fragment_shader_invocations: next_if(rhi::PipelineStatFlags::FRAGMENT_SHADER_INVOCATIONS),
+      compute_shader_invocations: next_if(rhi::PipelineStatFlags::COMPUTE_SHADER_INVOCATIONS),
+    })
+  }
+
+  fn destroy_query_pool(&mut self, pool: rhi::QueryPoolID) -> Result<(), rhi::RhiError> {
+    let (pool_vk, _) = self.query_pools.remove_obj(pool.0)?;
+    unsafe {
+      self.ash_device.destroy_query_pool(pool_vk, None);
+    }
+    Ok(())
+  }
+
+  fn read_timestamps(
+    &self,
+    pool: rhi::QueryPoolID,
+    first: u32,
+    count: u32
+  ) -> Result<Vec<u64>, rhi::RhiError> {
+    let (pool_vk, _) = self.query_pools.get_obj(pool.0)?;
+    let mut timestamps = vec![0u64; count as usize];
+    unsafe {
+      self
+        .ash_device
+        .get_query_pool_results(
+          *pool_vk,
+          first,
+          &mut timestamps,
+          vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT
+        )
+        .map_err(|e| translate_vk_error("at get query pool results", e))?;
     }
+    Ok(timestamps)
   }
 
-  fn create_command_buffer(&mut self) -> Result<rhi::CommandBufferID, String> {
+  fn create_command_buffer(&mut self) -> Result<rhi::CommandBufferID, rhi::RhiError> {
     unsafe {
       let command_buffer = self
         .ash_device
@@ -835,26 +4882,156 @@ impl rhi::RenderBackend for VulkanBackend {
             .level(vk::CommandBufferLevel::PRIMARY)
             .command_buffer_count(1)
         )
-        .map_err(|e| format!("at allocate command buffers: {e}"))?
+        .map_err(|e| translate_vk_error("at allocate command buffers", e))?
+        .remove(0);
+      let cmd_buffer_id_u32 = self
+        .command_buffers
+        .add_obj(CommandBufferVK { command_buffer, inheritance: None, last_fence: None })?;
+      Ok(rhi::CommandBufferID(cmd_buffer_id_u32))
+    }
+  }
+
+  fn create_secondary_command_buffer(
+    &mut self,
+    inheritance: rhi::CommandBufferInheritance
+  ) -> Result<rhi::CommandBufferID, rhi::RhiError> {
+    // Fail early on bad handles instead of at compile time
+    self.pipelines.get_obj(inheritance.pipeline.0)?;
+    self.frame_buffers.get_obj(inheritance.framebuffer.0)?;
+    unsafe {
+      let command_buffer = self
+        .ash_device
+        .allocate_command_buffers(
+          &vk::CommandBufferAllocateInfo::default()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::SECONDARY)
+            .command_buffer_count(1)
+        )
+        .map_err(|e| translate_vk_error("at allocate secondary command buffers", e))?
         .remove(0);
-      let cmd_buffer_id_u32 = self.command_buffers.add_obj(command_buffer)?;
+      let cmd_buffer_id_u32 = self
+        .command_buffers
+        .add_obj(CommandBufferVK {
+          command_buffer,
+          inheritance: Some(inheritance),
+          last_fence: None,
+        })?;
       Ok(rhi::CommandBufferID(cmd_buffer_id_u32))
     }
   }
 
-  fn compile_commands(&self, command_buffer: rhi::CommandBufferID, commands: Vec<rhi::GPUCommands>) -> Result<(), String> {
+  fn reset_command_buffer(
+    &mut self,
+    command_buffer: rhi::CommandBufferID,
+    wait_before_reset: bool
+  ) -> Result<(), rhi::RhiError> {
+    let command_buffer_obj = self.command_buffers.get_obj_mut(command_buffer.0)?;
+    let last_fence = command_buffer_obj.last_fence.take();
+    let command_buffer_vk = command_buffer_obj.command_buffer;
+    unsafe {
+      if let (true, Some(fence)) = (wait_before_reset, last_fence) {
+        self
+          .ash_device
+          .wait_for_fences(&[fence], true, u64::MAX)
+          .map_err(|e| translate_vk_error("at reset command buffer wait", e))?;
+      }
+      self
+        .ash_device
+        .reset_command_buffer(command_buffer_vk, vk::CommandBufferResetFlags::empty())
+        .map_err(|e| translate_vk_error("at reset command buffer", e))
+    }
+  }
+
+  fn compile_commands(&self, command_buffer: rhi::CommandBufferID, commands: Vec<rhi::GPUCommands>) -> Result<(), rhi::RhiError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("compile_commands");
+    #[cfg(feature = "renderdoc")]
+    self.set_capture_marks(command_buffer.0, &commands);
     // Figure out image layout transitions
     let mut image_needed_state = HashMap::new();
     for (i, command) in commands.iter().enumerate() {
       match command {
-        rhi::GPUCommands::CopyBufferToBuffer { .. } => {}
-        rhi::GPUCommands::CopyBufferToImage { src, dst } => {
+        rhi::GPUCommands::CopyBufferRegion { src, src_offset, dst, dst_offset, size } => {
+          let src_size = self.buffers.get_obj(src.0)?.size;
+          let dst_size = self.buffers.get_obj(dst.0)?.size;
+          if src_offset.checked_add(*size).is_none_or(|end| end > src_size) ||
+            dst_offset.checked_add(*size).is_none_or(|end| end > dst_size) {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} copies {size} bytes from offset {src_offset} of a \
+              {src_size} byte buffer to offset {dst_offset} of a {dst_size} byte buffer"
+            )));
+          }
+        }
+        rhi::GPUCommands::FillBuffer { buffer, offset, size, .. } => {
+          let buffer_size = self.buffers.get_obj(buffer.0)?.size;
+          let fill_size = size.unwrap_or(buffer_size.saturating_sub(*offset));
+          if offset % 4 != 0 || size.is_some_and(|x| x % 4 != 0) {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} fills {size:?} bytes from offset {offset}, both \
+              must be multiples of 4"
+            )));
+          }
+          let fill_end = offset.checked_add(fill_size);
+          if *offset >= buffer_size || fill_end.is_none_or(|end| end > buffer_size) {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} fills {fill_size} bytes from offset {offset} of a \
+              {buffer_size} byte buffer"
+            )));
+          }
+        }
+        rhi::GPUCommands::UpdateBuffer { buffer, offset, data } => {
+          let buffer_size = self.buffers.get_obj(buffer.0)?.size;
+          helpers::check_update_buffer_range(i, *offset, data.len() as u64, buffer_size)?;
+        }
+        rhi::GPUCommands::CopyBufferToImage { dst, .. } => {
+          image_needed_state
+            .entry(*dst)
+            .or_insert(HashMap::new())
+            .insert(i, (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
+        }
+        rhi::GPUCommands::CopyImageToBuffer { src, .. } => {
+          image_needed_state
+            .entry(*src)
+            .or_insert(HashMap::new())
+            .insert(i, (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
+        }
+        rhi::GPUCommands::CopyImageRegion {
+          src,
+          dst,
+          src_mip,
+          dst_mip,
+          src_layer,
+          dst_layer,
+          ..
+        } => {
+          if src.0 == dst.0 {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} copies a region of image {} into itself",
+              src.0
+            )));
+          }
+          for (image, mip, layer) in [(src, src_mip, src_layer), (dst, dst_mip, dst_layer)] {
+            let image_vk = self.images.get_obj(image.0)?;
+            if *mip >= image_vk.mip_levels || *layer >= image_vk.layer_count {
+              return Err(rhi::RhiError::BackendError(format!(
+                "at compile commands: command {i} copies mip {mip} layer {layer} of image {}, \
+                which has {} mips and {} layers",
+                image.0,
+                image_vk.mip_levels,
+                image_vk.layer_count
+              )));
+            }
+          }
+          image_needed_state
+            .entry(*src)
+            .or_insert(HashMap::new())
+            .insert(i, (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
           image_needed_state
             .entry(*dst)
             .or_insert(HashMap::new())
             .insert(i, (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
         }
-        rhi::GPUCommands::BlitImage { src, dst } => {
+        rhi::GPUCommands::BlitImage { src, dst, .. } => {
           image_needed_state
             .entry(*src)
             .or_insert(HashMap::new())
@@ -864,9 +5041,58 @@ impl rhi::RenderBackend for VulkanBackend {
             .or_insert(HashMap::new())
             .insert(i, (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
         }
-        rhi::GPUCommands::RunGraphicsPipeline { pipeline, framebuffer, input_set, draw_infos } => {
+        rhi::GPUCommands::ClearColorImage { image, .. } |
+        rhi::GPUCommands::ClearDepthStencilImage { image, .. } => {
+          let is_depth = get_aspect_mask(self.images.get_obj(image.0)?.format)
+            .contains(vk::ImageAspectFlags::DEPTH);
+          if is_depth != matches!(command, rhi::GPUCommands::ClearDepthStencilImage { .. }) {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} clears image {} with the wrong clear kind",
+              image.0
+            )));
+          }
+          image_needed_state
+            .entry(*image)
+            .or_insert(HashMap::new())
+            .insert(i, (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
+        }
+        rhi::GPUCommands::PushConstants { pipeline, data, offset } => {
+          let pipeline_vk = self.pipelines.get_obj(pipeline.0)?;
+          if *offset as usize + data.len() > pipeline_vk.push_constant_size as usize {
+            return Err(rhi::RhiError::BackendError(format!(
+              "push constant range {offset}..{} exceeds pipeline push constant size {}",
+              *offset as usize + data.len(),
+              pipeline_vk.push_constant_size
+            )));
+          }
+        }
+        rhi::GPUCommands::BindVertexBuffer { .. } => {}
+        rhi::GPUCommands::BindIndexBuffer { .. } => {}
+        rhi::GPUCommands::RunGraphicsPipeline { framebuffer, input_set, .. } |
+        rhi::GPUCommands::DrawIndirect { framebuffer, input_set, .. } |
+        rhi::GPUCommands::DrawIndexedIndirect { framebuffer, input_set, .. } |
+        rhi::GPUCommands::DrawMeshTasks { framebuffer, input_set, .. } => {
+          if let rhi::GPUCommands::DrawMeshTasks { pipeline, .. } = command {
+            if !self.pipelines.get_obj(pipeline.0)?.mesh_shading {
+              return Err(rhi::RhiError::BackendError(format!(
+                "at compile commands: command {i} draws mesh tasks with a vertex pipeline"
+              )));
+            }
+          }
+          if let rhi::GPUCommands::RunGraphicsPipeline { pipeline, draw_infos, .. } = command {
+            let instance_input = self.pipelines.get_obj(pipeline.0)?.instance_input;
+            if !instance_input && draw_infos.iter().any(|x| x.instance_buffer.is_some()) {
+              return Err(rhi::RhiError::BackendError(format!(
+                "at compile commands: command {i} gives an instance buffer to a pipeline without \
+                instance input"
+              )));
+            }
+          }
           let frame_buffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
-          for att_id in frame_buffer_vk.color_attachments.iter() {
+          for att_id in frame_buffer_vk
+            .color_attachments
+            .iter()
+            .chain(frame_buffer_vk.resolve_attachments.iter()) {
             image_needed_state
               .entry(*att_id)
               .or_insert(HashMap::new())
@@ -885,7 +5111,7 @@ impl rhi::RenderBackend for VulkanBackend {
               ));
           }
           let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
-          for tex_id in input_set_vk.bound_textures.iter() {
+          for tex_id in input_set_vk.bound_textures.values() {
             image_needed_state
               .entry(*tex_id)
               .or_insert(HashMap::new())
@@ -894,34 +5120,260 @@ impl rhi::RenderBackend for VulkanBackend {
                 vk::PipelineStageFlags::FRAGMENT_SHADER
               ));
           }
+          for img_id in input_set_vk.bound_storage_images.iter() {
+            image_needed_state
+              .entry(*img_id)
+              .or_insert(HashMap::new())
+              .insert(i, (vk::ImageLayout::GENERAL, vk::PipelineStageFlags::FRAGMENT_SHADER));
+          }
+        }
+        rhi::GPUCommands::WriteTimestamp { .. } => {}
+        rhi::GPUCommands::ResetQueryPool { .. } => {}
+        rhi::GPUCommands::BeginPipelineStatsQuery { pool, .. } => {
+          if self.query_pools.get_obj(pool.0)?.1.is_empty() {
+            return Err(rhi::RhiError::BackendError(format!(
+              "at compile commands: command {i} begins a pipeline stats query on timestamp pool {}",
+              pool.0
+            )));
+          }
+        }
+        rhi::GPUCommands::EndPipelineStatsQuery { .. } => {}
+        rhi::GPUCommands::SetViewport { .. } => {}
+        rhi::GPUCommands::SetScissor { .. } => {}
+        rhi::GPUCommands::SetStencilReference { .. } => {}
+        rhi::GPUCommands::BeginDebugCapture => {}
+        rhi::GPUCommands::EndDebugCapture => {}
+        rhi::GPUCommands::BeginDebugLabel { .. } => {}
+        rhi::GPUCommands::EndDebugLabel => {}
+        rhi::GPUCommands::InsertDebugLabel { .. } => {}
+        rhi::GPUCommands::SetDepthBias { clamp, .. } => {
+          self.check_depth_bias_clamp(*clamp, &format!("at compile commands: command {i}"))?;
+        }
+        rhi::GPUCommands::GenerateMipmaps { image } => {
+          image_needed_state
+            .entry(*image)
+            .or_insert(HashMap::new())
+            .insert(i, (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::PipelineStageFlags::TRANSFER));
+        }
+        rhi::GPUCommands::ExecuteSecondary { secondaries } => {
+          let inheritance = self.secondary_inheritance(secondaries)?;
+          let frame_buffer_vk = self.frame_buffers.get_obj(inheritance.framebuffer.0)?;
+          for att_id in frame_buffer_vk
+            .color_attachments
+            .iter()
+            .chain(frame_buffer_vk.resolve_attachments.iter()) {
+            image_needed_state
+              .entry(*att_id)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+              ));
+          }
+          if let Some(att_id) = frame_buffer_vk.depth_attachment.as_ref() {
+            image_needed_state
+              .entry(*att_id)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+              ));
+          }
+        }
+        rhi::GPUCommands::PipelineBarrier { src_stage, image_barriers, .. } => {
+          // Bring the image into `old_layout` implicitly, the barrier itself does the rest
+          for barrier in image_barriers.iter() {
+            if matches!(barrier.old_layout, rhi::ImageLayoutType::Undefined) {
+              continue;
+            }
+            image_needed_state
+              .entry(barrier.image)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                translate_image_layout(barrier.old_layout),
+                translate_pipeline_stage(*src_stage)
+              ));
+          }
+        }
+        rhi::GPUCommands::DispatchCompute { input_set, .. } |
+        rhi::GPUCommands::DispatchComputeIndirect { input_set, .. } => {
+          if let rhi::GPUCommands::DispatchComputeIndirect { args_buffer, args_offset, .. } =
+            command {
+            let args_size = self.buffers.get_obj(args_buffer.0)?.size;
+            helpers::check_dispatch_args_range(i, *args_offset, args_size)?;
+          }
+          let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
+          for tex_id in input_set_vk.bound_textures.values() {
+            image_needed_state
+              .entry(*tex_id)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::COMPUTE_SHADER
+              ));
+          }
+          for img_id in input_set_vk.bound_storage_images.iter() {
+            image_needed_state
+              .entry(*img_id)
+              .or_insert(HashMap::new())
+              .insert(i, (vk::ImageLayout::GENERAL, vk::PipelineStageFlags::COMPUTE_SHADER));
+          }
+        }
+        rhi::GPUCommands::TraceRays { input_set, .. } => {
+          let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
+          for tex_id in input_set_vk.bound_textures.values() {
+            image_needed_state
+              .entry(*tex_id)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR
+              ));
+          }
+          for img_id in input_set_vk.bound_storage_images.iter() {
+            image_needed_state
+              .entry(*img_id)
+              .or_insert(HashMap::new())
+              .insert(i, (
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR
+              ));
+          }
+        }
+      }
+    };
+    // Fill command buffer
+    let command_buffer_obj = self.command_buffers.get_obj(command_buffer.0)?;
+    let command_buffer_vk = command_buffer_obj.command_buffer;
+    let mut image_curr_state = HashMap::new();
+    let mut overrides = DynamicStateOverrides::default();
+    unsafe {
+      match command_buffer_obj.inheritance {
+        None => {
+          self
+            .ash_device
+            .begin_command_buffer(command_buffer_vk, &vk::CommandBufferBeginInfo::default())
+            .map_err(|e| translate_vk_error("at begin_command_buffer", e))?;
+        }
+        Some(inheritance) => {
+          let pipeline_vk = self.pipelines.get_obj(inheritance.pipeline.0)?;
+          let framebuffer_vk = self.frame_buffers.get_obj(inheritance.framebuffer.0)?;
+          let color_formats_vk = framebuffer_vk
+            .color_attachments
+            .iter()
+            .map(|x| Ok(translate_image_format(self.images.get_obj(x.0)?.format)))
+            .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+          let depth_format = framebuffer_vk
+            .depth_attachment
+            .map(|x| self.images.get_obj(x.0).map(|image| image.format))
+            .transpose()?;
+          let stencil_format_vk = depth_format
+            .filter(|x| get_aspect_mask(*x).contains(vk::ImageAspectFlags::STENCIL))
+            .map(translate_image_format)
+            .unwrap_or(vk::Format::UNDEFINED);
+          let mut rendering_inheritance_info =
+            vk::CommandBufferInheritanceRenderingInfoKHR::default()
+              .color_attachment_formats(&color_formats_vk)
+              .depth_attachment_format(
+                depth_format.map(translate_image_format).unwrap_or(vk::Format::UNDEFINED)
+              )
+              .stencil_attachment_format(stencil_format_vk)
+              .rasterization_samples(pipeline_vk.sample_count)
+              .view_mask(pipeline_vk.multiview_mask.unwrap_or(0));
+          let inheritance_info = vk::CommandBufferInheritanceInfo::default()
+            .render_pass(pipeline_vk.render_pass)
+            .subpass(0)
+            .framebuffer(framebuffer_vk.framebuffer);
+          let inheritance_info = if self.dynamic_rendering_device.is_some() {
+            inheritance_info.push_next(&mut rendering_inheritance_info)
+          } else {
+            inheritance_info
+          };
+          self
+            .ash_device
+            .begin_command_buffer(
+              command_buffer_vk,
+              &vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+                .inheritance_info(&inheritance_info)
+            )
+            .map_err(|e| translate_vk_error("at begin_command_buffer", e))?;
         }
       }
-    };
-    // Fill command buffer
-    let command_buffer_vk = self.command_buffers.get_obj(command_buffer.0)?.clone();
-    unsafe {
-      self
-        .ash_device
-        .begin_command_buffer(command_buffer_vk, &vk::CommandBufferBeginInfo::default())
-        .map_err(|e| format!("at begin_command_buffer: {e}"))?;
       for (i, command) in commands.iter().enumerate() {
+        // Move every image used by this command out of its last tracked state,
+        // secondaries are already inside a render pass and leave this to the primary
+        for (img, states) in image_needed_state.iter() {
+          if command_buffer_obj.inheritance.is_some() {
+            break;
+          }
+          let Some(curr_state) = states.get(&i).cloned() else { continue };
+          let img_vk = self.images.get_obj(img.0)?;
+          let prev_state = image_curr_state
+            .insert(*img, curr_state)
+            .unwrap_or((curr_state.0, vk::PipelineStageFlags::BOTTOM_OF_PIPE));
+          self.emit_barriers(
+            command_buffer_vk,
+            prev_state.1,
+            curr_state.1,
+            vk::DependencyFlags::BY_REGION,
+            &[],
+            &[
+              vk::ImageMemoryBarrier2::default()
+                .image(img_vk.image)
+                .old_layout(prev_state.0)
+                .new_layout(curr_state.0)
+                .src_access_mask(infer_access_from_layout(prev_state.0))
+                .dst_access_mask(infer_access_from_layout(curr_state.0))
+                .src_queue_family_index(self.graphics_queue_family_id)
+                .dst_queue_family_index(self.graphics_queue_family_id)
+                .subresource_range(
+                  vk::ImageSubresourceRange::default()
+                    .aspect_mask(get_aspect_mask(img_vk.format))
+                    .base_mip_level(0)
+                    .level_count(img_vk.mip_levels)
+                    .base_array_layer(0)
+                    .layer_count(img_vk.layer_count)
+                )
+            ]
+          );
+        }
         match command {
-          rhi::GPUCommands::CopyBufferToBuffer { src, dst } => {
+          rhi::GPUCommands::CopyBufferRegion { src, src_offset, dst, dst_offset, size } => {
             let src_buffer_vk = self.buffers.get_obj(src.0)?;
             let dst_buffer_vk = self.buffers.get_obj(dst.0)?;
             self.ash_device.cmd_copy_buffer(
               command_buffer_vk,
               src_buffer_vk.buffer,
               dst_buffer_vk.buffer,
-              &[vk::BufferCopy::default().src_offset(0).dst_offset(0).size(src_buffer_vk.size)]
+              &[vk::BufferCopy::default()
+                .src_offset(*src_offset)
+                .dst_offset(*dst_offset)
+                .size(*size)]
+            );
+          }
+          rhi::GPUCommands::FillBuffer { buffer, offset, size, value } => {
+            let buffer_vk = self.buffers.get_obj(buffer.0)?.buffer;
+            let size = size.unwrap_or(vk::WHOLE_SIZE);
+            self.ash_device.cmd_fill_buffer(command_buffer_vk, buffer_vk, *offset, size, *value);
+            self.emit_buffer_write_barrier(command_buffer_vk, buffer_vk, *offset, size);
+          }
+          rhi::GPUCommands::UpdateBuffer { buffer, offset, data } => {
+            let buffer_vk = self.buffers.get_obj(buffer.0)?.buffer;
+            self.ash_device.cmd_update_buffer(command_buffer_vk, buffer_vk, *offset, data);
+            self.emit_buffer_write_barrier(
+              command_buffer_vk,
+              buffer_vk,
+              *offset,
+              data.len() as u64
             );
           }
-          rhi::GPUCommands::CopyBufferToImage { src, dst } => {
+          rhi::GPUCommands::CopyBufferToImage { src, dst, layer, depth_slice, mip } => {
             let src_buffer_vk = self.buffers.get_obj(src.0)?;
             let dst_image_vk = self.images.get_obj(dst.0)?;
             let dst_img_extent = vk::Extent3D{
-              width: dst_image_vk.resolution.width,
-              height: dst_image_vk.resolution.height,
+              width: (dst_image_vk.resolution.width >> mip).max(1),
+              height: (dst_image_vk.resolution.height >> mip).max(1),
               depth: 1
             };
             self.ash_device.cmd_copy_buffer_to_image(
@@ -931,10 +5383,42 @@ impl rhi::RenderBackend for VulkanBackend {
               vk::ImageLayout::TRANSFER_DST_OPTIMAL,
               &[
                 vk::BufferImageCopy::default()
-                  .image_offset(vk::Offset3D::default())
+                  .buffer_offset(0)
+                  .buffer_row_length(0)
+                  .buffer_image_height(0)
+                  .image_offset(vk::Offset3D { x: 0, y: 0, z: *depth_slice as _ })
                   .image_extent(dst_img_extent)
                   .image_subresource(vk::ImageSubresourceLayers::default()
                     .aspect_mask(get_aspect_mask(dst_image_vk.format))
+                    .base_array_layer(*layer)
+                    .layer_count(1)
+                    .mip_level(*mip)
+                  )
+              ]
+            );
+          }
+          rhi::GPUCommands::CopyImageToBuffer { src, dst } => {
+            let src_image_vk = self.images.get_obj(src.0)?;
+            let dst_buffer_vk = self.buffers.get_obj(dst.0)?;
+            let src_img_extent = vk::Extent3D{
+              width: src_image_vk.resolution.width,
+              height: src_image_vk.resolution.height,
+              depth: 1
+            };
+            self.ash_device.cmd_copy_image_to_buffer(
+              command_buffer_vk,
+              src_image_vk.image,
+              vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+              dst_buffer_vk.buffer,
+              &[
+                vk::BufferImageCopy::default()
+                  .buffer_offset(0)
+                  .buffer_row_length(0)
+                  .buffer_image_height(0)
+                  .image_offset(vk::Offset3D::default())
+                  .image_extent(src_img_extent)
+                  .image_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(src_image_vk.format))
                     .base_array_layer(0)
                     .layer_count(1)
                     .mip_level(0)
@@ -942,7 +5426,46 @@ impl rhi::RenderBackend for VulkanBackend {
               ]
             );
           }
-          rhi::GPUCommands::BlitImage { src, dst } => {
+          rhi::GPUCommands::CopyImageRegion {
+            src,
+            src_offset,
+            dst,
+            dst_offset,
+            extent,
+            src_mip,
+            dst_mip,
+            src_layer,
+            dst_layer
+          } => {
+            let src_image = self.images.get_obj(src.0)?;
+            let dst_image = self.images.get_obj(dst.0)?;
+            self.ash_device.cmd_copy_image(
+              command_buffer_vk,
+              src_image.image,
+              vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+              dst_image.image,
+              vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+              &[
+                vk::ImageCopy::default()
+                  .src_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(src_image.format))
+                    .base_array_layer(*src_layer)
+                    .layer_count(1)
+                    .mip_level(*src_mip)
+                  )
+                  .src_offset(vk::Offset3D{ x: src_offset[0], y: src_offset[1], z: src_offset[2] })
+                  .dst_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(dst_image.format))
+                    .base_array_layer(*dst_layer)
+                    .layer_count(1)
+                    .mip_level(*dst_mip)
+                  )
+                  .dst_offset(vk::Offset3D{ x: dst_offset[0], y: dst_offset[1], z: dst_offset[2] })
+                  .extent(vk::Extent3D{ width: extent[0], height: extent[1], depth: extent[2] })
+              ]
+            );
+          }
+          rhi::GPUCommands::BlitImage { src, dst, filter } => {
             let src_image = self.images.get_obj(src.0)?;
             let dst_image = self.images.get_obj(dst.0)?;
             self.ash_device.cmd_blit_image(
@@ -953,169 +5476,805 @@ impl rhi::RenderBackend for VulkanBackend {
               vk::ImageLayout::TRANSFER_DST_OPTIMAL,
               &[
                 vk::ImageBlit::default()
+                  .src_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(src_image.format))
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .mip_level(0)
+                  )
                   .src_offsets([
                     vk::Offset3D::default(),
                     vk::Offset3D{
                       x: src_image.resolution.width as _,
                       y: src_image.resolution.height as _,
-                      z: 0
+                      z: 1
                     },
                   ])
+                  .dst_subresource(vk::ImageSubresourceLayers::default()
+                    .aspect_mask(get_aspect_mask(dst_image.format))
+                    .base_array_layer(0)
+                    .layer_count(1)
+                    .mip_level(0)
+                  )
                   .dst_offsets([
                     vk::Offset3D::default(),
                     vk::Offset3D{
                       x: dst_image.resolution.width as _,
                       y: dst_image.resolution.height as _,
-                      z: 0
+                      z: 1
                     }
                   ])
               ],
-              vk::Filter::CUBIC_IMG
+              translate_blit_filter(*filter)
+            );
+          }
+          rhi::GPUCommands::ClearColorImage { image, color } => {
+            let img_vk = self.images.get_obj(image.0)?;
+            self.ash_device.cmd_clear_color_image(
+              command_buffer_vk,
+              img_vk.image,
+              vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+              &vk::ClearColorValue { float32: *color },
+              &[
+                vk::ImageSubresourceRange::default()
+                  .aspect_mask(vk::ImageAspectFlags::COLOR)
+                  .base_mip_level(0)
+                  .level_count(img_vk.mip_levels)
+                  .base_array_layer(0)
+                  .layer_count(img_vk.layer_count)
+              ]
+            );
+          }
+          rhi::GPUCommands::ClearDepthStencilImage { image, depth, stencil } => {
+            let img_vk = self.images.get_obj(image.0)?;
+            self.ash_device.cmd_clear_depth_stencil_image(
+              command_buffer_vk,
+              img_vk.image,
+              vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+              &vk::ClearDepthStencilValue { depth: *depth, stencil: *stencil },
+              &[
+                vk::ImageSubresourceRange::default()
+                  .aspect_mask(get_aspect_mask(img_vk.format))
+                  .base_mip_level(0)
+                  .level_count(img_vk.mip_levels)
+                  .base_array_layer(0)
+                  .layer_count(img_vk.layer_count)
+              ]
             );
           }
-          rhi::GPUCommands::RunGraphicsPipeline { pipeline, framebuffer, input_set, draw_infos } => {
-            let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
-            let framebuffer_res =
-              self.images.get_obj(framebuffer_vk.color_attachments[0].0)?.resolution;
+          rhi::GPUCommands::PushConstants { pipeline, data, offset } => {
             let pipeline_vk = self.pipelines.get_obj(pipeline.0)?;
-            let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
-            self.ash_device.cmd_begin_render_pass(
+            self.ash_device.cmd_push_constants(
               command_buffer_vk,
-              &vk::RenderPassBeginInfo::default()
-                .framebuffer(framebuffer_vk.framebuffer)
-                .render_pass(pipeline_vk.render_pass)
-                .render_area(
-                  vk::Rect2D::default()
-                    .offset(vk::Offset2D::default())
-                    .extent(
-                      vk::Extent2D::default()
-                        .width(framebuffer_res.width as _)
-                        .height(framebuffer_res.height as _)
-                        .width(1)
-                    )
-                ),
-              vk::SubpassContents::INLINE,
+              pipeline_vk.pipeline_layout,
+              pipeline_vk.push_constant_stages,
+              *offset,
+              data
+            );
+          }
+          rhi::GPUCommands::BindVertexBuffer { buffer, binding, offset } => {
+            let buffer_vk = self.buffers.get_obj(buffer.0)?;
+            self.ash_device.cmd_bind_vertex_buffers(
+              command_buffer_vk,
+              *binding,
+              &[buffer_vk.buffer],
+              &[*offset]
+            );
+          }
+          rhi::GPUCommands::BindIndexBuffer { buffer, offset, index_type } => {
+            let buffer_vk = self.buffers.get_obj(buffer.0)?;
+            self.ash_device.cmd_bind_index_buffer(
+              command_buffer_vk,
+              buffer_vk.buffer,
+              *offset,
+              translate_index_type(*index_type)
+            );
+          }
+          rhi::GPUCommands::RunGraphicsPipeline {
+            pipeline,
+            framebuffer,
+            input_set,
+            index_buffer,
+            draw_infos
+          } => {
+            let pipeline_vk = self.begin_graphics_run(
+              command_buffer_obj,
+              *pipeline,
+              *framebuffer,
+              *input_set,
+              &overrides
+            )?;
+            let mut bound_index_buffer = index_buffer
+              .map(|(buffer, index_type, offset)| {
+                self.buffers.get_obj(buffer.0).map(|x| (x.buffer, index_type, offset))
+              })
+              .transpose()?;
+            if let Some((buffer, index_type, offset)) = bound_index_buffer {
+              self.ash_device.cmd_bind_index_buffer(
+                command_buffer_vk,
+                buffer,
+                offset,
+                translate_index_type(index_type)
+              );
+            }
+            let mut bound_instance_buffer = None;
+            for draw_info in draw_infos.iter() {
+              if let Some(instance_buffer) = draw_info.instance_buffer {
+                let buffer = self.buffers.get_obj(instance_buffer.0)?.buffer;
+                if bound_instance_buffer != Some(buffer) {
+                  self.ash_device.cmd_bind_vertex_buffers(command_buffer_vk, 1, &[buffer], &[0]);
+                  bound_instance_buffer = Some(buffer);
+                }
+              }
+              if let Some(push_const_data) = draw_info.push_const_data.as_ref() {
+                let push_const_size =
+                  std::cmp::min(pipeline_vk.push_constant_size as usize, push_const_data.len());
+                if push_const_size > 0 {
+                  self.ash_device.cmd_push_constants(
+                    command_buffer_vk,
+                    pipeline_vk.pipeline_layout,
+                    pipeline_vk.push_constant_stages,
+                    0,
+                    &push_const_data[..push_const_size]
+                  );
+                }
+              }
+              match draw_info.mode {
+                rhi::DrawMode::Vertices => {
+                  self.ash_device.cmd_draw(
+                    command_buffer_vk,
+                    draw_info.count,
+                    draw_info.instance_count,
+                    draw_info.offset,
+                    draw_info.first_instance
+                  );
+                }
+                rhi::DrawMode::Indexed { index_type } => {
+                  if let Some(index_buffer) = draw_info.index_buffer {
+                    let buffer = self.buffers.get_obj(index_buffer.0)?.buffer;
+                    let already_bound = bound_index_buffer.is_some_and(|x| {
+                      x.0 == buffer && x.1 == index_type && x.2 == 0
+                    });
+                    if !already_bound {
+                      self.ash_device.cmd_bind_index_buffer(
+                        command_buffer_vk,
+                        buffer,
+                        0,
+                        translate_index_type(index_type)
+                      );
+                      bound_index_buffer = Some((buffer, index_type, 0));
+                    }
+                  }
+                  // Rebind the shared index buffer if this draw reads it as another index type
+                  if let Some((buffer, bound_index_type, offset)) = bound_index_buffer.as_mut() {
+                    if *bound_index_type != index_type {
+                      self.ash_device.cmd_bind_index_buffer(
+                        command_buffer_vk,
+                        *buffer,
+                        *offset,
+                        translate_index_type(index_type)
+                      );
+                      *bound_index_type = index_type;
+                    }
+                  }
+                  self.ash_device.cmd_draw_indexed(
+                    command_buffer_vk,
+                    draw_info.index_count,
+                    draw_info.instance_count,
+                    draw_info.index_offset,
+                    draw_info.vertex_offset,
+                    draw_info.first_instance
+                  );
+                }
+              }
+            }
+            if command_buffer_obj.inheritance.is_none() {
+              let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
+              self.end_framebuffer_pass(
+                command_buffer_vk,
+                pipeline_vk,
+                framebuffer_vk,
+                &mut image_curr_state
+              )?;
+            }
+          }
+          rhi::GPUCommands::DrawIndirect {
+            pipeline,
+            framebuffer,
+            input_set,
+            args_buffer,
+            args_offset,
+            draw_count,
+            stride
+          } => {
+            self.check_indirect_draw_count(*draw_count)?;
+            let args_buffer_vk = self.buffers.get_obj(args_buffer.0)?.buffer;
+            let pipeline_vk = self.begin_graphics_run(
+              command_buffer_obj,
+              *pipeline,
+              *framebuffer,
+              *input_set,
+              &overrides
+            )?;
+            self.ash_device.cmd_draw_indirect(
+              command_buffer_vk,
+              args_buffer_vk,
+              *args_offset,
+              *draw_count,
+              *stride
+            );
+            if command_buffer_obj.inheritance.is_none() {
+              let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
+              self.end_framebuffer_pass(
+                command_buffer_vk,
+                pipeline_vk,
+                framebuffer_vk,
+                &mut image_curr_state
+              )?;
+            }
+          }
+          rhi::GPUCommands::DrawMeshTasks {
+            pipeline,
+            framebuffer,
+            input_set,
+            groups_x,
+            groups_y,
+            groups_z
+          } => {
+            // Mesh pipelines can't be created without the extension
+            let mesh_shader_device = self.mesh_shader_device.as_ref().ok_or(
+              rhi::RhiError::BackendError("at draw mesh tasks: no VK_EXT_mesh_shader".to_string())
+            )?;
+            let pipeline_vk = self.begin_graphics_run(
+              command_buffer_obj,
+              *pipeline,
+              *framebuffer,
+              *input_set,
+              &overrides
+            )?;
+            mesh_shader_device.cmd_draw_mesh_tasks(
+              command_buffer_vk,
+              *groups_x,
+              *groups_y,
+              *groups_z
+            );
+            if command_buffer_obj.inheritance.is_none() {
+              let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
+              self.end_framebuffer_pass(
+                command_buffer_vk,
+                pipeline_vk,
+                framebuffer_vk,
+                &mut image_curr_state
+              )?;
+            }
+          }
+          rhi::GPUCommands::DrawIndexedIndirect {
+            pipeline,
+            framebuffer,
+            input_set,
+            args_buffer,
+            args_offset,
+            draw_count,
+            stride,
+            index_buffer,
+            index_type
+          } => {
+            self.check_indirect_draw_count(*draw_count)?;
+            let args_buffer_vk = self.buffers.get_obj(args_buffer.0)?.buffer;
+            let index_buffer_vk = self.buffers.get_obj(index_buffer.0)?.buffer;
+            let pipeline_vk = self.begin_graphics_run(
+              command_buffer_obj,
+              *pipeline,
+              *framebuffer,
+              *input_set,
+              &overrides
+            )?;
+            self.ash_device.cmd_bind_index_buffer(
+              command_buffer_vk,
+              index_buffer_vk,
+              0,
+              translate_index_type(*index_type)
+            );
+            self.ash_device.cmd_draw_indexed_indirect(
+              command_buffer_vk,
+              args_buffer_vk,
+              *args_offset,
+              *draw_count,
+              *stride
             );
+            if command_buffer_obj.inheritance.is_none() {
+              let framebuffer_vk = self.frame_buffers.get_obj(framebuffer.0)?;
+              self.end_framebuffer_pass(
+                command_buffer_vk,
+                pipeline_vk,
+                framebuffer_vk,
+                &mut image_curr_state
+              )?;
+            }
+          }
+          rhi::GPUCommands::DispatchCompute { pipeline, input_set, groups_x, groups_y, groups_z } => {
+            self.bind_compute_run(command_buffer_vk, *pipeline, *input_set)?;
+            self.ash_device.cmd_dispatch(command_buffer_vk, *groups_x, *groups_y, *groups_z);
+          }
+          rhi::GPUCommands::TraceRays { pipeline, input_set, width, height, depth } => {
+            // Ray tracing pipelines can't be created without the extension
+            let ray_tracing_device = self.ray_tracing_device.as_ref().ok_or(
+              rhi::RhiError::BackendError(
+                "at trace rays: no VK_KHR_ray_tracing_pipeline".to_string()
+              )
+            )?;
+            let pipeline_vk = self.ray_tracing_pipelines.get_obj(pipeline.0)?;
+            let input_set_vk = self.descriptor_sets.get_obj(input_set.0)?;
             self.ash_device.cmd_bind_pipeline(
               command_buffer_vk,
-              vk::PipelineBindPoint::GRAPHICS,
+              vk::PipelineBindPoint::RAY_TRACING_KHR,
               pipeline_vk.pipeline,
             );
             self.ash_device.cmd_bind_descriptor_sets(
               command_buffer_vk,
-              vk::PipelineBindPoint::GRAPHICS,
+              vk::PipelineBindPoint::RAY_TRACING_KHR,
               pipeline_vk.pipeline_layout,
               0,
-              &[input_set_vk.buffer_set, input_set_vk.texture_set],
-              &[0, 0]
+              &[input_set_vk.buffer_set, input_set_vk.texture_set, input_set_vk.storage_image_set],
+              &[]
+            );
+            ray_tracing_device.cmd_trace_rays(
+              command_buffer_vk,
+              &pipeline_vk.raygen_region,
+              &pipeline_vk.miss_region,
+              &pipeline_vk.hit_region,
+              &vk::StridedDeviceAddressRegionKHR::default(),
+              *width,
+              *height,
+              *depth
             );
-            for draw_info in draw_infos.iter() {
-              self.ash_device.cmd_push_constants(
-                command_buffer_vk,
-                pipeline_vk.pipeline_layout,
-                vk::ShaderStageFlags::ALL,
-                0,
-                &draw_info.push_const_data
-              );
-              self.ash_device.cmd_draw(command_buffer_vk, draw_info.count, 1, 0, 0);
-            }
-            self.ash_device.cmd_end_render_pass(command_buffer_vk);
           }
-        }
-        for (img, states) in image_needed_state.iter() {
-          let img_vk = self.images.get_obj(img.0)?;
-          let Some(curr_state) = states.get(&i).cloned() else { continue };
-          let prev_state = if i > 0{
-             states
-              .get(&(i - 1))
-              .cloned()
-              .unwrap_or((curr_state.0, vk::PipelineStageFlags::BOTTOM_OF_PIPE))
-          } else {
-            (curr_state.0, vk::PipelineStageFlags::BOTTOM_OF_PIPE)
-          };
-          self.ash_device.cmd_pipeline_barrier(
-            command_buffer_vk,
-            prev_state.1,
-            curr_state.1,
-            vk::DependencyFlags::BY_REGION,
-            &[],
-            &[],
-            &[
-              vk::ImageMemoryBarrier::default()
+          rhi::GPUCommands::DispatchComputeIndirect {
+            pipeline,
+            input_set,
+            args_buffer,
+            args_offset
+          } => {
+            let args_buffer_vk = self.buffers.get_obj(args_buffer.0)?.buffer;
+            self.bind_compute_run(command_buffer_vk, *pipeline, *input_set)?;
+            self.ash_device.cmd_dispatch_indirect(command_buffer_vk, args_buffer_vk, *args_offset);
+          }
+          rhi::GPUCommands::WriteTimestamp { pool, index } => {
+            let (pool_vk, _) = *self.query_pools.get_obj(pool.0)?;
+            self.ash_device.cmd_write_timestamp(
+              command_buffer_vk,
+              vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+              pool_vk,
+              *index
+            );
+          }
+          rhi::GPUCommands::ResetQueryPool { pool, first, count } => {
+            let (pool_vk, _) = *self.query_pools.get_obj(pool.0)?;
+            self.ash_device.cmd_reset_query_pool(command_buffer_vk, pool_vk, *first, *count);
+          }
+          rhi::GPUCommands::BeginPipelineStatsQuery { pool, index } => {
+            let (pool_vk, _) = *self.query_pools.get_obj(pool.0)?;
+            self.ash_device.cmd_begin_query(
+              command_buffer_vk,
+              pool_vk,
+              *index,
+              vk::QueryControlFlags::empty()
+            );
+          }
+          rhi::GPUCommands::EndPipelineStatsQuery { pool, index } => {
+            let (pool_vk, _) = *self.query_pools.get_obj(pool.0)?;
+            self.ash_device.cmd_end_query(command_buffer_vk, pool_vk, *index);
+          }
+          rhi::GPUCommands::SetViewport { x, y, width, height, min_depth, max_depth } => {
+            let viewport = vk::Viewport::default()
+              .x(*x)
+              .y(*y)
+              .width(*width)
+              .height(*height)
+              .min_depth(*min_depth)
+              .max_depth(*max_depth);
+            self.ash_device.cmd_set_viewport(command_buffer_vk, 0, &[viewport]);
+            overrides.viewport = Some(viewport);
+          }
+          rhi::GPUCommands::SetScissor { x, y, width, height } => {
+            let scissor = vk::Rect2D::default()
+              .offset(vk::Offset2D { x: *x, y: *y })
+              .extent(vk::Extent2D { width: *width, height: *height });
+            self.ash_device.cmd_set_scissor(command_buffer_vk, 0, &[scissor]);
+            overrides.scissor = Some(scissor);
+          }
+          rhi::GPUCommands::GenerateMipmaps { image } => {
+            let img_vk = self.images.get_obj(image.0)?;
+            let mip_levels = img_vk.resolution.mip_count().min(img_vk.mip_levels);
+            let aspect_mask = get_aspect_mask(img_vk.format);
+            let level_barrier = |level: u32, old_layout, new_layout| {
+              vk::ImageMemoryBarrier2::default()
                 .image(img_vk.image)
-                .old_layout(prev_state.0)
-                .new_layout(curr_state.0)
-                .src_access_mask(infer_access_from_layout(prev_state.0))
-                .dst_access_mask(infer_access_from_layout(curr_state.0))
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(infer_access_from_layout(old_layout))
+                .dst_access_mask(infer_access_from_layout(new_layout))
                 .src_queue_family_index(self.graphics_queue_family_id)
                 .dst_queue_family_index(self.graphics_queue_family_id)
                 .subresource_range(
                   vk::ImageSubresourceRange::default()
-                    .aspect_mask(get_aspect_mask(img_vk.format))
-                    .base_mip_level(0)
+                    .aspect_mask(aspect_mask)
+                    .base_mip_level(level)
                     .level_count(1)
                     .base_array_layer(0)
-                    .layer_count(1)
+                    .layer_count(img_vk.layer_count)
                 )
-            ]
-          );
+            };
+            let level_extent = |level: u32| vk::Offset3D {
+              x: (img_vk.resolution.width >> level).max(1) as _,
+              y: (img_vk.resolution.height >> level).max(1) as _,
+              z: (img_vk.depth >> level).max(1) as _,
+            };
+            for level in 0..mip_levels.saturating_sub(1) {
+              self.emit_barriers(
+                command_buffer_vk,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[level_barrier(
+                  level,
+                  vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                  vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                )]
+              );
+              self.ash_device.cmd_blit_image(
+                command_buffer_vk,
+                img_vk.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                img_vk.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[
+                  vk::ImageBlit::default()
+                    .src_subresource(vk::ImageSubresourceLayers::default()
+                      .aspect_mask(aspect_mask)
+                      .base_array_layer(0)
+                      .layer_count(img_vk.layer_count)
+                      .mip_level(level)
+                    )
+                    .src_offsets([vk::Offset3D::default(), level_extent(level)])
+                    .dst_subresource(vk::ImageSubresourceLayers::default()
+                      .aspect_mask(aspect_mask)
+                      .base_array_layer(0)
+                      .layer_count(img_vk.layer_count)
+                      .mip_level(level + 1)
+                    )
+                    .dst_offsets([vk::Offset3D::default(), level_extent(level + 1)])
+                ],
+                vk::Filter::LINEAR
+              );
+            }
+            // All levels but the last one were blit sources
+            let final_barriers = (0..mip_levels)
+              .map(|level| {
+                let old_layout = if level + 1 == mip_levels {
+                  vk::ImageLayout::TRANSFER_DST_OPTIMAL
+                } else {
+                  vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                };
+                level_barrier(level, old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+              })
+              .collect::<Vec<_>>();
+            self.emit_barriers(
+              command_buffer_vk,
+              vk::PipelineStageFlags::TRANSFER,
+              vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+              vk::DependencyFlags::empty(),
+              &[],
+              &final_barriers
+            );
+            image_curr_state.insert(*image, (
+              vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+              vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER
+            ));
+          }
+          rhi::GPUCommands::SetStencilReference { front, back } => {
+            overrides.stencil_reference = Some((*front, *back));
+          }
+          rhi::GPUCommands::SetDepthBias { constant, clamp, slope } => {
+            overrides.depth_bias = Some((*constant, *clamp, *slope));
+          }
+          // Acted on when the command buffer is submitted
+          rhi::GPUCommands::BeginDebugCapture => {}
+          rhi::GPUCommands::EndDebugCapture => {}
+          rhi::GPUCommands::BeginDebugLabel { label, color } => {
+            let at = format!("at compile commands: command {i}");
+            if let Some((debug_utils_device, label)) = self.debug_label(label, &at)? {
+              debug_utils_device.cmd_begin_debug_utils_label(
+                command_buffer_vk,
+                &vk::DebugUtilsLabelEXT::default().label_name(&label).color(*color)
+              );
+            }
+          }
+          rhi::GPUCommands::EndDebugLabel => {
+            if let Some(debug_utils_device) = self.debug_utils_device.as_ref() {
+              debug_utils_device.cmd_end_debug_utils_label(command_buffer_vk);
+            }
+          }
+          rhi::GPUCommands::InsertDebugLabel { label, color } => {
+            let at = format!("at compile commands: command {i}");
+            if let Some((debug_utils_device, label)) = self.debug_label(label, &at)? {
+              debug_utils_device.cmd_insert_debug_utils_label(
+                command_buffer_vk,
+                &vk::DebugUtilsLabelEXT::default().label_name(&label).color(*color)
+              );
+            }
+          }
+          rhi::GPUCommands::ExecuteSecondary { secondaries } => {
+            let inheritance = self.secondary_inheritance(secondaries)?;
+            let pipeline_vk = self.pipelines.get_obj(inheritance.pipeline.0)?;
+            let framebuffer_vk = self.frame_buffers.get_obj(inheritance.framebuffer.0)?;
+            let secondaries_vk = secondaries
+              .iter()
+              .map(|x| self.command_buffers.get_obj(x.0).map(|x| x.command_buffer))
+              .collect::<Result<Vec<_>, _>>()?;
+            self.begin_framebuffer_pass(
+              command_buffer_vk,
+              pipeline_vk,
+              framebuffer_vk,
+              self.framebuffer_render_area(framebuffer_vk)?,
+              vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+            )?;
+            self.ash_device.cmd_execute_commands(command_buffer_vk, &secondaries_vk);
+            self.end_framebuffer_pass(
+              command_buffer_vk,
+              pipeline_vk,
+              framebuffer_vk,
+              &mut image_curr_state
+            )?;
+          }
+          rhi::GPUCommands::PipelineBarrier {
+            src_stage,
+            dst_stage,
+            buffer_barriers,
+            image_barriers
+          } => {
+            let buffer_barriers_vk = buffer_barriers
+              .iter()
+              .map(|barrier| {
+                let buffer_vk = self.buffers.get_obj(barrier.buffer.0)?;
+                Ok(vk::BufferMemoryBarrier2::default()
+                  .buffer(buffer_vk.buffer)
+                  .offset(barrier.offset)
+                  .size(if barrier.size == u64::MAX { vk::WHOLE_SIZE } else { barrier.size })
+                  .src_access_mask(translate_mem_access(barrier.src_access))
+                  .dst_access_mask(translate_mem_access(barrier.dst_access))
+                  .src_queue_family_index(self.graphics_queue_family_id)
+                  .dst_queue_family_index(self.graphics_queue_family_id))
+              })
+              .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+            let image_barriers_vk = image_barriers
+              .iter()
+              .map(|barrier| {
+                let img_vk = self.images.get_obj(barrier.image.0)?;
+                Ok(vk::ImageMemoryBarrier2::default()
+                  .image(img_vk.image)
+                  .old_layout(translate_image_layout(barrier.old_layout))
+                  .new_layout(translate_image_layout(barrier.new_layout))
+                  .src_access_mask(translate_mem_access(barrier.src_access))
+                  .dst_access_mask(translate_mem_access(barrier.dst_access))
+                  .src_queue_family_index(self.graphics_queue_family_id)
+                  .dst_queue_family_index(self.graphics_queue_family_id)
+                  .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                      .aspect_mask(get_aspect_mask(img_vk.format))
+                      .base_mip_level(0)
+                      .level_count(img_vk.mip_levels)
+                      .base_array_layer(0)
+                      .layer_count(img_vk.layer_count)
+                  ))
+              })
+              .collect::<Result<Vec<_>, rhi::RhiError>>()?;
+            self.emit_barriers(
+              command_buffer_vk,
+              translate_pipeline_stage(*src_stage),
+              translate_pipeline_stage(*dst_stage),
+              vk::DependencyFlags::empty(),
+              &buffer_barriers_vk,
+              &image_barriers_vk
+            );
+            // Later implicit transitions start from where the explicit barrier left the image
+            for barrier in image_barriers.iter() {
+              image_curr_state.insert(barrier.image, (
+                translate_image_layout(barrier.new_layout),
+                translate_pipeline_stage(*dst_stage)
+              ));
+            }
+          }
         }
       };
       self
         .ash_device
         .end_command_buffer(command_buffer_vk)
-        .map_err(|e| format!("at end_command_buffer: {e}"))?;
+        .map_err(|e| translate_vk_error("at end_command_buffer", e))?;
     }
     Ok(())
   }
 
   fn run_commands(
-    &self,
+    &mut self,
     command_buffer: rhi::CommandBufferID,
-    fence_id: rhi::FenceID
-  ) -> Result<(), String> {
-    let command_buffer_vk = self.command_buffers.get_obj(command_buffer.0)?.clone();
-    let fence_vk = self.fences.get_obj(fence_id.0)?;
+    fence_id: rhi::FenceID,
+    wait_semaphores: Vec<rhi::SemaphoreID>,
+    signal_semaphores: Vec<rhi::SemaphoreID>,
+  ) -> Result<(), rhi::RhiError> {
+    #[cfg(feature = "tracy")]
+    let _span = tracy_client::span!("run_commands");
+    let command_buffer_obj = self.command_buffers.get_obj(command_buffer.0)?;
+    if command_buffer_obj.inheritance.is_some() {
+      return Err(rhi::RhiError::BackendError(
+        "at run commands: secondary command buffers run through ExecuteSecondary".to_string()
+      ));
+    }
+    let command_buffer_vk = command_buffer_obj.command_buffer;
+    let fence_vk = *self.fences.get_obj(fence_id.0)?;
+    let wait_semaphores_vk = wait_semaphores
+      .iter()
+      .map(|x| self.semaphores.get_obj(x.0).cloned())
+      .collect::<Result<Vec<_>, _>>()?;
+    let wait_stages = wait_semaphores_vk
+      .iter()
+      .map(|_| vk::PipelineStageFlags::ALL_COMMANDS)
+      .collect::<Vec<_>>();
+    let signal_semaphores_vk = signal_semaphores
+      .iter()
+      .map(|x| self.semaphores.get_obj(x.0).cloned())
+      .collect::<Result<Vec<_>, _>>()?;
+    #[cfg(feature = "renderdoc")]
+    let (begins_capture, ends_capture) = self.capture_marks(command_buffer.0);
+    #[cfg(feature = "renderdoc")]
+    if begins_capture {
+      self.start_renderdoc_capture();
+    }
     unsafe {
       self
         .ash_device
         .queue_submit(
           self.graphics_queue,
-          &[vk::SubmitInfo::default().command_buffers(&[command_buffer_vk])],
-          *fence_vk
+          &[
+            vk::SubmitInfo::default()
+              .command_buffers(&[command_buffer_vk])
+              .wait_semaphores(&wait_semaphores_vk)
+              .wait_dst_stage_mask(&wait_stages)
+              .signal_semaphores(&signal_semaphores_vk)
+          ],
+          fence_vk
         )
-        .map_err(|e| format!("at submit queue submit: {e}"))
+        .map_err(|e| translate_vk_error("at submit queue submit", e))?;
+    }
+    #[cfg(feature = "renderdoc")]
+    if ends_capture {
+      self.end_renderdoc_capture();
+    }
+    self.command_buffers.get_obj_mut(command_buffer.0)?.last_fence = Some(fence_vk);
+    Ok(())
+  }
+
+  fn device_wait_idle(&self) -> Result<(), rhi::RhiError> {
+    unsafe {
+      self
+        .ash_device
+        .device_wait_idle()
+        .map_err(|e| translate_vk_error("at device wait idle", e))
+    }
+  }
+
+  fn queue_wait_idle(&self) -> Result<(), rhi::RhiError> {
+    unsafe {
+      self
+        .ash_device
+        .queue_wait_idle(self.graphics_queue)
+        .map_err(|e| translate_vk_error("at queue wait idle", e))
     }
   }
   
   fn get_swapchain_images(&self) -> Vec<rhi::ImageID>{
     self.swapchain_images.clone()
   }
-  
-  fn present_swapchain_image(&self, id: u32) -> Result<bool,String> {
+
+  fn rebuild_swapchain(&mut self, width: u32, height: u32) -> Result<(), rhi::RhiError> {
     unsafe {
+      self
+        .ash_device
+        .device_wait_idle()
+        .map_err(|e| translate_vk_error("at device wait idle", e))?;
+      self.release_swapchain_images()?;
+      let (swapchain_res, surface_format, _, present_mode, swapchain) = helpers::make_swapchain(
+        self.gpu,
+        &self.surface_instance,
+        self.surface,
+        &self.swapchain_device,
+        vk::Extent2D { width, height },
+        translate_present_mode(self.preferred_present_mode),
+        self.swapchain
+      )?;
+      self.swapchain_device.destroy_swapchain(self.swapchain, None);
+      self.swapchain = swapchain;
+      self.swapchain_res = swapchain_res;
+      self.surface_format = surface_format;
+      self.present_mode = present_mode;
+      self.register_swapchain_images()
+    }
+  }
+  
+  fn present_swapchain_image(
+    &self,
+    image_index: u32,
+    wait_semaphores: &[rhi::SemaphoreID]
+  ) -> Result<bool, rhi::RhiError> {
+    let wait_semaphores_vk = wait_semaphores
+      .iter()
+      .map(|x| self.semaphores.get_obj(x.0).cloned())
+      .collect::<Result<Vec<_>, _>>()?;
+    let presented = unsafe {
       self
         .swapchain_device
         .queue_present(
           self.graphics_queue,
-          &vk::PresentInfoKHR::default().image_indices(&[id]).swapchains(&[self.swapchain])
+          &vk::PresentInfoKHR::default()
+            .image_indices(&[image_index])
+            .swapchains(&[self.swapchain])
+            .wait_semaphores(&wait_semaphores_vk)
         )
-        .map_err(|e| format!("at presenting: {e}"))
-    }
+        .map_err(|e| translate_vk_error("at presenting", e))
+    };
+    #[cfg(feature = "tracy")]
+    self.frame_mark();
+    presented
   }
   
-  fn acquire_present_image(&self, fence_id: rhi::FenceID) -> Result<u32,String>{
+  fn acquire_present_image(
+    &self,
+    fence_id: rhi::FenceID,
+    signal_semaphore: Option<rhi::SemaphoreID>
+  ) -> Result<u32, rhi::RhiError>{
+    let signal_semaphore_vk = signal_semaphore
+      .map(|x| self.semaphores.get_obj(x.0).cloned())
+      .transpose()?
+      .unwrap_or(vk::Semaphore::null());
     unsafe {
       self
         .swapchain_device
         .acquire_next_image(
           self.swapchain,
           999999,
-          vk::Semaphore::null(),
+          signal_semaphore_vk,
           self.fences.get_obj(fence_id.0)?.clone()
         )
+        // A suboptimal image is still acquired and has to be presented, which reports it
         .map(|x| x.0)
-        .map_err(|e| format!("at acquiring present image: {e}"))
+        .map_err(|e| translate_vk_error("at acquiring present image", e))
+    }
+  }
+
+  fn set_debug_name(
+    &self,
+    resource: impl Into<rhi::DebugResource>,
+    name: &str
+  ) -> Result<(), rhi::RhiError> {
+    unsafe {
+      match resource.into() {
+        rhi::DebugResource::Buffer(id) => {
+          self.set_object_name(self.buffers.get_obj(id.0)?.buffer, name)
+        }
+        rhi::DebugResource::Image(id) => {
+          self.set_object_name(self.images.get_obj(id.0)?.image, name)
+        }
+        rhi::DebugResource::Pipeline(id) => {
+          self.set_object_name(self.pipelines.get_obj(id.0)?.pipeline, name)
+        }
+        rhi::DebugResource::Fence(id) => {
+          self.set_object_name(*self.fences.get_obj(id.0)?, name)
+        }
+        rhi::DebugResource::CommandBuffer(id) => {
+          self.set_object_name(self.command_buffers.get_obj(id.0)?.command_buffer, name)
+        }
+      }
     }
   }
 }
@@ -1123,16 +6282,93 @@ impl rhi::RenderBackend for VulkanBackend {
 impl Drop for VulkanBackend{
   fn drop(&mut self){
     unsafe {
+      // Nothing can be destroyed while the GPU might still be using it
+      let _ = self.ash_device.device_wait_idle();
+      let _ = self.release_swapchain_images();
+      // The session has to go before the device it renders with
+      #[cfg(feature = "openxr-backend")]
+      let _ = self.release_openxr_state();
+      // Framebuffers go before the image views they use
+      let framebuffer_ids = self.frame_buffers.get_all().keys().cloned().collect::<Vec<_>>();
+      for framebuffer_id in framebuffer_ids {
+        let _ = rhi::RenderBackend::destroy_framebuffer(self, rhi::FramebufferID(framebuffer_id));
+      }
+      // Graphics pipelines take their render pass and layouts along
+      let pipeline_ids = self.pipelines.get_all().keys().cloned().collect::<Vec<_>>();
+      for pipeline_id in pipeline_ids {
+        let _ = rhi::RenderBackend::destroy_pipeline(self, rhi::PipelineID(pipeline_id));
+      }
+      for c_pipeline in self.compute_pipelines.clear() {
+        self.ash_device.destroy_pipeline(c_pipeline.pipeline, None);
+        self.ash_device.destroy_pipeline_layout(c_pipeline.pipeline_layout, None);
+        self.ash_device.destroy_descriptor_set_layout(c_pipeline.buffer_set_layout, None);
+        self.ash_device.destroy_descriptor_set_layout(c_pipeline.texture_set_layout, None);
+        self.ash_device.destroy_descriptor_set_layout(c_pipeline.storage_image_set_layout, None);
+      }
+      // Before the buffers, the shader binding tables are among them
+      let rt_pipeline_ids =
+        self.ray_tracing_pipelines.get_all().keys().cloned().collect::<Vec<_>>();
+      for rt_pipeline_id in rt_pipeline_ids {
+        let _ = rhi::RenderBackend::destroy_ray_tracing_pipeline(
+          self,
+          rhi::RayTracingPipelineID(rt_pipeline_id)
+        );
+      }
+      let accel_struct_ids = self.accel_structs.get_all().keys().cloned().collect::<Vec<_>>();
+      for accel_struct_id in accel_struct_ids {
+        let _ = rhi::RenderBackend::destroy_accel_struct(self, rhi::AccelStructID(accel_struct_id));
+      }
       let image_ids = self.images.get_all().keys().cloned().collect::<Vec<_>>();
       for image_id in image_ids {
-        self.destroy_image(rhi::ImageID(image_id));
+        let _ = rhi::RenderBackend::destroy_image(self, rhi::ImageID(image_id));
       }
       let buffer_ids = self.buffers.get_all().keys().cloned().collect::<Vec<_>>();
       for buffer_id in buffer_ids {
-        self.destroy_buffer(rhi::BufferID(buffer_id));
+        let _ = rhi::RenderBackend::destroy_buffer(self, rhi::BufferID(buffer_id));
+      }
+      for shader in self.shaders.get_all().values() {
+        self.ash_device.destroy_shader_module(*shader, None);
+      }
+      for (view, _) in self.image_views.get_all().values() {
+        self.ash_device.destroy_image_view(*view, None);
       }
+      for sampler in self.samplers.get_all().values() {
+        self.ash_device.destroy_sampler(*sampler, None);
+      }
+      for (query_pool, _) in self.query_pools.get_all().values() {
+        self.ash_device.destroy_query_pool(*query_pool, None);
+      }
+      for fence in self.fences.clear() {
+        self.ash_device.destroy_fence(fence, None);
+      }
+      for semaphore in self.semaphores.clear() {
+        self.ash_device.destroy_semaphore(semaphore, None);
+      }
+      self.flush_staging_pool();
+      // Destroying the pools frees the input sets allocated from them
+      for descriptor_pool in self.descriptor_pools.drain(..) {
+        self.ash_device.destroy_descriptor_pool(descriptor_pool, None);
+      }
+      // Destroying the pools frees their command buffers
+      self.ash_device.destroy_command_pool(self.command_pool, None);
+      self.ash_device.destroy_command_pool(self.transfer_command_pool, None);
+      if let Some(path) = self.pipeline_cache_path.as_ref() {
+        let _ = self
+          .ash_device
+          .get_pipeline_cache_data(self.pipeline_cache)
+          .map_err(|e| format!("at get pipeline cache data: {e}"))
+          .and_then(|data| {
+            std::fs::write(path, data).map_err(|e| format!("at write pipeline cache: {e}"))
+          })
+          .inspect_err(|e| log::error!("error saving pipeline cache: {e}"));
+      }
+      self.ash_device.destroy_pipeline_cache(self.pipeline_cache, None);
+      self.swapchain_device.destroy_swapchain(self.swapchain, None);
       self.surface_instance.destroy_surface(self.surface, None);
       self.ash_device.destroy_device(None);
+      if let Some((debug_utils_instance, debug_messenger)) = self.debug_utils.as_ref() {
+        debug_utils_instance.destroy_debug_utils_messenger(*debug_messenger, None);
+      }
       self.ash_instance.destroy_instance(None);
     }
   }
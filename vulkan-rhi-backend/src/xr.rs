@@ -0,0 +1,225 @@
+use ash::vk;
+use ash::vk::Handle;
+use rhi::{HasDisplayHandle, HasWindowHandle};
+
+use crate::{
+  get_aspect_mask,
+  helpers,
+  translate_image_format,
+  translate_vk_error,
+  AllocatedTexture,
+  DeviceSetup,
+  VulkanBackend,
+  VulkanBackendConfig,
+};
+
+/// Vulkan objects the OpenXR runtime created through `xrCreateVulkanInstanceKHR` and
+/// `xrCreateVulkanDeviceKHR`, and the session was created with
+pub struct OpenXrDevice {
+  pub instance: vk::Instance,
+  pub gpu: vk::PhysicalDevice,
+  pub device: vk::Device,
+  /// Queue 0 of this family is used for graphics and transfers
+  pub queue_family_index: u32,
+  /// `VK_KHR_multiview` was enabled on the device
+  pub multiview: bool,
+}
+
+pub struct OpenXrSwapchainDesc {
+  pub format: rhi::ImageFormat,
+  /// Per eye resolution, usually the recommended size of the system's views
+  pub resolution: rhi::Resolution2D,
+  /// 2 for stereo multiview rendering, one layer per eye
+  pub layer_count: u32,
+}
+
+pub(crate) struct OpenXrState {
+  session: openxr::Session<openxr::Vulkan>,
+  swapchain: openxr::Swapchain<openxr::Vulkan>,
+  pub(crate) images: Vec<rhi::ImageID>,
+}
+
+fn translate_xr_error(context: &str, e: openxr::sys::Result) -> rhi::RhiError {
+  rhi::RhiError::BackendError(format!("{context}: {e}"))
+}
+
+impl VulkanBackend {
+  /// Renders with the device of an OpenXR session instead of creating one. `window` still gets
+  /// the regular swapchain, e.g. to mirror the headset view on the desktop. The instance needs
+  /// the surface extensions `new` enables and the device `VK_KHR_swapchain`. The backend can't
+  /// tell which other extensions and features OpenXR enabled, so the optional ones aren't used.
+  ///
+  /// # Safety
+  /// `device` must hold the live handles `session` was created with. The backend takes
+  /// ownership of the device and instance and destroys them on drop, after the session
+  pub unsafe fn new_from_openxr_session(
+    window: &(impl HasWindowHandle + HasDisplayHandle),
+    config: VulkanBackendConfig,
+    session: openxr::Session<openxr::Vulkan>,
+    device: OpenXrDevice,
+    swapchain_desc: OpenXrSwapchainDesc,
+  ) -> Result<Self, rhi::RhiError> {
+    let ash_entry = ash::Entry::load()
+      .map_err(|e| rhi::RhiError::BackendError(format!("at VK load: {e}")))?;
+    let ash_instance = ash::Instance::load(ash_entry.static_fn(), device.instance);
+    let ash_device = ash::Device::load(ash_instance.fp_v1_0(), device.device);
+    let gpu_index = ash_instance
+      .enumerate_physical_devices()
+      .map_err(|e| translate_vk_error("at getting GPU list", e))?
+      .iter()
+      .position(|x| *x == device.gpu)
+      .ok_or(rhi::RhiError::BackendError(
+        "at new from openxr session: GPU isn't part of the instance".to_string()
+      ))?;
+    let gpu_info = helpers::list_gpus(&ash_instance)?.swap_remove(gpu_index);
+    let mut backend = Self::from_device(window, config, DeviceSetup {
+      ash_entry,
+      ash_instance,
+      debug_utils: None,
+      gpu: device.gpu,
+      gpu_info,
+      ash_device,
+      graphics_queue_family_id: device.queue_family_index,
+      transfer_queue_family_id: device.queue_family_index,
+      use_dynamic_rendering: false,
+      use_synchronization2: false,
+      bindless_texture_limit: None,
+      memory_budget_supported: false,
+      conservative_rasterization_supported: false,
+      multiview_supported: device.multiview,
+      mesh_shader_supported: false,
+      task_shader_supported: false,
+      ray_tracing_supported: false,
+      buffer_device_address_supported: false,
+      device_features: vk::PhysicalDeviceFeatures::default(),
+    })?;
+    let format_vk = translate_image_format(swapchain_desc.format);
+    let swapchain = session
+      .create_swapchain(&openxr::SwapchainCreateInfo {
+        create_flags: openxr::SwapchainCreateFlags::EMPTY,
+        usage_flags: openxr::SwapchainUsageFlags::COLOR_ATTACHMENT |
+          openxr::SwapchainUsageFlags::SAMPLED,
+        format: format_vk.as_raw() as u32,
+        sample_count: 1,
+        width: swapchain_desc.resolution.width,
+        height: swapchain_desc.resolution.height,
+        face_count: 1,
+        array_size: swapchain_desc.layer_count,
+        mip_count: 1,
+      })
+      .map_err(|e| translate_xr_error("at create openxr swapchain", e))?;
+    let images_vk = swapchain
+      .enumerate_images()
+      .map_err(|e| translate_xr_error("at getting openxr swapchain images", e))?;
+    let view_type = if swapchain_desc.layer_count == 1 {
+      vk::ImageViewType::TYPE_2D
+    } else {
+      vk::ImageViewType::TYPE_2D_ARRAY
+    };
+    // The runtime owns these images, so nothing is registered until every view exists and a
+    // failure only has views to destroy
+    let mut textures: Vec<AllocatedTexture> = Vec::with_capacity(images_vk.len());
+    for raw_image in images_vk {
+      let image = vk::Image::from_raw(raw_image);
+      let view = backend.ash_device.create_image_view(
+        &vk::ImageViewCreateInfo::default()
+          .image(image)
+          .format(format_vk)
+          .view_type(view_type)
+          .subresource_range(
+            vk::ImageSubresourceRange::default()
+              .aspect_mask(get_aspect_mask(swapchain_desc.format))
+              .base_array_layer(0)
+              .layer_count(swapchain_desc.layer_count)
+              .base_mip_level(0)
+              .level_count(1)
+          ),
+        None
+      );
+      let view = match view {
+        Ok(view) => view,
+        Err(e) => {
+          for texture in &textures {
+            backend.ash_device.destroy_image_view(texture.view, None);
+          }
+          return Err(translate_vk_error("at openxr swapchain image view", e));
+        }
+      };
+      textures.push(AllocatedTexture {
+        image,
+        view,
+        resolution: swapchain_desc.resolution,
+        format: swapchain_desc.format,
+        depth: 1,
+        mip_levels: 1,
+        layer_count: swapchain_desc.layer_count,
+        is_cube: false,
+        allocation: None
+      });
+    }
+    let views = textures.iter().map(|texture| texture.view).collect::<Vec<_>>();
+    let mut images = Vec::with_capacity(textures.len());
+    for texture in textures {
+      match backend.images.add_obj(texture) {
+        Ok(id) => images.push(rhi::ImageID(id)),
+        Err(e) => {
+          for id in &images {
+            let _ = backend.images.remove_obj(id.0);
+          }
+          for view in views {
+            backend.ash_device.destroy_image_view(view, None);
+          }
+          return Err(e.into());
+        }
+      }
+    }
+    backend.xr = Some(OpenXrState { session, swapchain, images });
+    Ok(backend)
+  }
+
+  pub fn openxr_session(&self) -> Option<&openxr::Session<openxr::Vulkan>> {
+    self.xr.as_ref().map(|xr| &xr.session)
+  }
+
+  /// Index in `xr_swapchain_images` of the image to render next. Waits until the runtime is done
+  /// reading it, the image has to be released before the next acquire
+  pub fn acquire_openxr_swapchain_image(&mut self) -> Result<u32, rhi::RhiError> {
+    let xr = self.openxr_state_mut("at acquire openxr swapchain image")?;
+    let index = xr
+      .swapchain
+      .acquire_image()
+      .map_err(|e| translate_xr_error("at acquire openxr swapchain image", e))?;
+    xr
+      .swapchain
+      .wait_image(openxr::Duration::INFINITE)
+      .map_err(|e| translate_xr_error("at wait openxr swapchain image", e))?;
+    Ok(index)
+  }
+
+  /// Hands the acquired image back to the runtime, after the commands rendering it were submitted
+  pub fn release_openxr_swapchain_image(&mut self) -> Result<(), rhi::RhiError> {
+    self
+      .openxr_state_mut("at release openxr swapchain image")?
+      .swapchain
+      .release_image()
+      .map_err(|e| translate_xr_error("at release openxr swapchain image", e))
+  }
+
+  fn openxr_state_mut(&mut self, context: &str) -> Result<&mut OpenXrState, rhi::RhiError> {
+    self
+      .xr
+      .as_mut()
+      .ok_or(rhi::RhiError::BackendError(format!("{context}: backend has no openxr session")))
+  }
+
+  /// Drops the swapchain and session, the images belong to the runtime so only the views go
+  pub(crate) unsafe fn release_openxr_state(&mut self) -> Result<(), rhi::RhiError> {
+    if let Some(xr) = self.xr.take() {
+      for image_id in xr.images {
+        let a_image = self.images.remove_obj(image_id.0)?;
+        self.ash_device.destroy_image_view(a_image.view, None);
+      }
+    }
+    Ok(())
+  }
+}